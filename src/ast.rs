@@ -1,9 +1,11 @@
 use crate::idx::*;
-use serde::{Serialize, Deserialize};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Copy, Clone, PartialEq, Hash, Serialize, Deserialize)]
 pub enum ExprNode {
-    Number(i32),
+    Number(NumberId),
+    Rational(RationalId),
+    Float(FloatId),
     Variable(NameId),
     Call {
         fun: FunctionId,
@@ -17,6 +19,41 @@ pub enum ExprNode {
     },
 }
 
+/// An exact numeric literal. Rationals with a denominator of one are always
+/// stored as integers, so each value has exactly one representation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ExactNumber {
+    Integer(NumberId),
+    Rational(RationalId),
+}
+
+impl From<ExactNumber> for ExprNode {
+    fn from(number: ExactNumber) -> Self {
+        match number {
+            ExactNumber::Integer(id) => ExprNode::Number(id),
+            ExactNumber::Rational(id) => ExprNode::Rational(id),
+        }
+    }
+}
+
+impl From<ExactNumber> for Pattern {
+    fn from(number: ExactNumber) -> Self {
+        match number {
+            ExactNumber::Integer(id) => Pattern::Number(id),
+            ExactNumber::Rational(id) => Pattern::Rational(id),
+        }
+    }
+}
+
+impl From<ExactNumber> for Action {
+    fn from(number: ExactNumber) -> Self {
+        match number {
+            ExactNumber::Integer(id) => Action::Number(id),
+            ExactNumber::Rational(id) => Action::Rational(id),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Function {
     Add,
@@ -29,12 +66,36 @@ pub enum Function {
     Custom(NameId),
 }
 
+/// The nodes a typed capture `?x:kind` accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CaptureKind {
+    /// `?x:var`, a variable symbol.
+    Variable,
+    /// `?x:call`, any function call, including operators.
+    Call,
+    /// `?x:struct`
+    Struct,
+    /// `?x:ground`, a term without variables.
+    Ground,
+    /// `?x:nonnum`, anything but a number.
+    NonNumber,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Pattern {
-    Number(i32),
+    Number(NumberId),
+    Rational(RationalId),
+    Float(FloatId),
     Variable(NameId),
     AnyNumber(NameId),
     Wildcard(NameId),
+    /// `?x:kind`: a capture that only matches nodes of that kind.
+    Typed {
+        name: NameId,
+        kind: CaptureKind,
+    },
+    /// `?xs...`: zero or more consecutive arguments of a call or struct.
+    Sequence(NameId),
     Call {
         fun: FunctionId,
         last: usize,
@@ -55,12 +116,42 @@ pub enum Pattern {
         last: usize,
         arity: usize,
     },
+    /// `not(p)`: matches any term that `p` does not match on its own. The
+    /// captures inside it are local to it.
+    Not {
+        last: usize,
+    },
+    /// `p where ?x != q, ..`: the first child is `p`, the others are the
+    /// `Distinct` constraints its matches must satisfy.
+    Where {
+        last: usize,
+        arity: usize,
+    },
+    /// `?x != q`, which holds if the capture `x` does not match `q`.
+    Distinct {
+        var: NameId,
+        last: usize,
+    },
+    /// `C[p]`: matches a term with a subterm at any depth, the term itself
+    /// included, that matches `p`. `C` captures the term around it.
+    Context {
+        var: NameId,
+        last: usize,
+    },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Action {
-    Number(i32),
+    Number(NumberId),
+    Rational(RationalId),
+    Float(FloatId),
     Variable(NameId),
+    /// `fresh(n)`: a variable named after `n` that is new in every
+    /// application of the rule.
+    Fresh(NameId),
+    /// `xs...`: the arguments captured by `?xs...`, spliced into the
+    /// enclosing call or struct, whose arity counts it as one.
+    Splice(NameId),
     Call {
         fun: FunctionId,
         last: usize,
@@ -81,6 +172,12 @@ pub enum Action {
         last: usize,
         arity: usize,
     },
+    /// `C[a]`: the term captured by `C[p]` with `a` in place of the subterm
+    /// `p` matched.
+    Plug {
+        var: NameId,
+        last: usize,
+    },
 
     Compute {
         op: ComputeOp,
@@ -97,6 +194,86 @@ pub enum ComputeOp {
     Divide,
     Power,
     Negate,
+    /// `a % b` and `mod(a, b)`: the remainder with the sign of `b`.
+    Modulo,
+    /// `div(a, b)`: `a / b` rounded down.
+    IntegerDivide,
+    Abs,
+    Min,
+    Max,
+    Gcd,
+    Lcm,
+    /// `a < b` and friends, which compute to 1 or 0.
+    Compare(CompareOp),
+    /// `if(c, a, b)`: `a` if `c` is not zero, else `b`. Only the chosen
+    /// branch is evaluated.
+    If,
+}
+
+impl ComputeOp {
+    /// The compute functions that are written as calls, e.g. `gcd(a, b)`.
+    pub const FUNCTIONS: [(&'static str, ComputeOp); 8] = [
+        ("abs", ComputeOp::Abs),
+        ("min", ComputeOp::Min),
+        ("max", ComputeOp::Max),
+        ("gcd", ComputeOp::Gcd),
+        ("lcm", ComputeOp::Lcm),
+        ("div", ComputeOp::IntegerDivide),
+        ("mod", ComputeOp::Modulo),
+        ("if", ComputeOp::If),
+    ];
+
+    pub fn from_function_name(name: &str) -> Option<ComputeOp> {
+        ComputeOp::FUNCTIONS
+            .iter()
+            .find(|(function, _)| *function == name)
+            .map(|&(_, op)| op)
+    }
+}
+
+/// A condition on the captures of a match, stored in postfix order like
+/// patterns and actions. Operands are compute expressions over the captures.
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Guard {
+    Operand(ActionId),
+    Compare {
+        op: CompareOp,
+        last: usize,
+        arity: usize,
+    },
+    Predicate {
+        predicate: GuardPredicate,
+        last: usize,
+        arity: usize,
+    },
+    Logic {
+        op: LogicOp,
+        last: usize,
+        arity: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GuardPredicate {
+    IsNumber,
+    IsVariable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LogicOp {
+    And,
+    Or,
+    Not,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Serialize, Deserialize)]
@@ -104,26 +281,108 @@ pub struct Rule {
     pub name: NameId,
     pub pattern: PatternId,
     pub action: ActionId,
+    /// Matches are only reported when this `when` clause holds.
+    #[serde(default)]
+    pub guard: Option<GuardId>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Hash, Serialize, Deserialize)]
+/// How a declared operator is written and how it groups with itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Fixity {
+    /// `infixl`: `a op b op c` is `(a op b) op c`.
+    InfixLeft,
+    /// `infixr`: `a op b op c` is `a op (b op c)`.
+    InfixRight,
+    /// `infix`: `a op b op c` is an error.
+    Infix,
+    /// `prefix`: `op a`.
+    Prefix,
+}
+
+/// An operator declared with e.g. `infixl 6 <+>`. Applications are stored as
+/// calls of `function`, which is `Function::Custom` of the symbol.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OperatorDecl {
+    pub symbol: String,
+    pub fixity: Fixity,
+    pub precedence: u8,
+    pub function: FunctionId,
+}
+
+/// Equational properties declared with e.g. `ac + identity 0`. Patterns
+/// over `function` match modulo these properties, see theories.rs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TheoryDecl {
+    pub symbol: String,
+    pub function: FunctionId,
+    pub associative: bool,
+    pub commutative: bool,
+    pub identity: Option<ExactNumber>,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Ruleset {
     pub name: NameId,
     pub rules_start: usize,
     pub rules_end: usize,
+    /// Rulesets brought in with `use name;` or `extends name`.
+    #[serde(default)]
+    pub imports: Vec<RulesetId>,
 }
 
-impl std::fmt::Display for Function {
+impl std::fmt::Display for CompareOp {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Function::Add => write!(f, "+"),
-            Function::Subtract => write!(f, "-"),
-            Function::Multiply => write!(f, "*"),
-            Function::Divide => write!(f, "/"),
-            Function::Power => write!(f, "^"),
-            Function::Negate => write!(f, "neg"),
-            Function::Plus => write!(f, "+"),
-            Function::Custom(name_id) => write!(f, "custom_{}", name_id.0),
+            CompareOp::Equal => write!(f, "=="),
+            CompareOp::NotEqual => write!(f, "!="),
+            CompareOp::Less => write!(f, "<"),
+            CompareOp::LessEqual => write!(f, "<="),
+            CompareOp::Greater => write!(f, ">"),
+            CompareOp::GreaterEqual => write!(f, ">="),
         }
     }
 }
+
+impl std::fmt::Display for ComputeOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ComputeOp::Add => write!(f, "+"),
+            ComputeOp::Subtract => write!(f, "-"),
+            ComputeOp::Multiply => write!(f, "*"),
+            ComputeOp::Divide => write!(f, "/"),
+            ComputeOp::Power => write!(f, "^"),
+            ComputeOp::Negate => write!(f, "neg"),
+            ComputeOp::Modulo => write!(f, "%"),
+            ComputeOp::IntegerDivide => write!(f, "div"),
+            ComputeOp::Abs => write!(f, "abs"),
+            ComputeOp::Min => write!(f, "min"),
+            ComputeOp::Max => write!(f, "max"),
+            ComputeOp::Gcd => write!(f, "gcd"),
+            ComputeOp::Lcm => write!(f, "lcm"),
+            ComputeOp::Compare(op) => write!(f, "{}", op),
+            ComputeOp::If => write!(f, "if"),
+        }
+    }
+}
+
+impl std::fmt::Display for CaptureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CaptureKind::Variable => write!(f, "var"),
+            CaptureKind::Call => write!(f, "call"),
+            CaptureKind::Struct => write!(f, "struct"),
+            CaptureKind::Ground => write!(f, "ground"),
+            CaptureKind::NonNumber => write!(f, "nonnum"),
+        }
+    }
+}
+
+impl std::fmt::Display for GuardPredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GuardPredicate::IsNumber => write!(f, "is_number"),
+            GuardPredicate::IsVariable => write!(f, "is_variable"),
+        }
+    }
+}
+
@@ -73,14 +73,20 @@ pub trait NodeInfo {
 impl NodeInfo for ExprNode {
     fn arity(&self) -> usize {
         match self {
-            ExprNode::Number(_) | ExprNode::Variable(_) => 0,
+            ExprNode::Number(_)
+            | ExprNode::Rational(_)
+            | ExprNode::Float(_)
+            | ExprNode::Variable(_) => 0,
             ExprNode::Call { arity, .. } | ExprNode::Struct { arity, .. } => *arity,
         }
     }
 
     fn last(&self) -> Option<usize> {
         match self {
-            ExprNode::Number(_) | ExprNode::Variable(_) => None,
+            ExprNode::Number(_)
+            | ExprNode::Rational(_)
+            | ExprNode::Float(_)
+            | ExprNode::Variable(_) => None,
             ExprNode::Call { last, .. } | ExprNode::Struct { last, .. } => Some(*last),
         }
     }
@@ -89,7 +95,13 @@ impl NodeInfo for ExprNode {
 impl NodeInfo for Action {
     fn arity(&self) -> usize {
         match self {
-            Action::Number(_) | Action::Variable(_) => 0,
+            Action::Number(_)
+            | Action::Rational(_)
+            | Action::Float(_)
+            | Action::Variable(_)
+            | Action::Fresh(_)
+            | Action::Splice(_) => 0,
+            Action::Plug { .. } => 1,
             Action::Call { arity, .. }
             | Action::Struct { arity, .. }
             | Action::VarCallName { arity, .. }
@@ -100,11 +112,17 @@ impl NodeInfo for Action {
 
     fn last(&self) -> Option<usize> {
         match self {
-            Action::Number(_) | Action::Variable(_) => None,
+            Action::Number(_)
+            | Action::Rational(_)
+            | Action::Float(_)
+            | Action::Variable(_)
+            | Action::Fresh(_)
+            | Action::Splice(_) => None,
             Action::Call { last, .. }
             | Action::Struct { last, .. }
             | Action::VarCallName { last, .. }
             | Action::VarStructName { last, .. }
+            | Action::Plug { last, .. }
             | Action::Compute { last, .. } => Some(*last),
         }
     }
@@ -114,26 +132,60 @@ impl NodeInfo for Pattern {
     fn arity(&self) -> usize {
         match self {
             Pattern::Number(_)
+            | Pattern::Rational(_)
+            | Pattern::Float(_)
             | Pattern::Variable(_)
             | Pattern::AnyNumber(_)
-            | Pattern::Wildcard(_) => 0,
+            | Pattern::Wildcard(_)
+            | Pattern::Typed { .. }
+            | Pattern::Sequence(_) => 0,
+            Pattern::Not { .. } | Pattern::Distinct { .. } | Pattern::Context { .. } => 1,
             Pattern::Call { arity, .. }
             | Pattern::Struct { arity, .. }
             | Pattern::VarCallName { arity, .. }
-            | Pattern::VarStructName { arity, .. } => *arity,
+            | Pattern::VarStructName { arity, .. }
+            | Pattern::Where { arity, .. } => *arity,
         }
     }
 
     fn last(&self) -> Option<usize> {
         match self {
             Pattern::Number(_)
+            | Pattern::Rational(_)
+            | Pattern::Float(_)
             | Pattern::Variable(_)
             | Pattern::AnyNumber(_)
-            | Pattern::Wildcard(_) => None,
+            | Pattern::Wildcard(_)
+            | Pattern::Typed { .. }
+            | Pattern::Sequence(_) => None,
             Pattern::Call { last, .. }
             | Pattern::Struct { last, .. }
             | Pattern::VarCallName { last, .. }
-            | Pattern::VarStructName { last, .. } => Some(*last),
+            | Pattern::VarStructName { last, .. }
+            | Pattern::Not { last }
+            | Pattern::Where { last, .. }
+            | Pattern::Distinct { last, .. }
+            | Pattern::Context { last, .. } => Some(*last),
+        }
+    }
+}
+
+impl NodeInfo for Guard {
+    fn arity(&self) -> usize {
+        match self {
+            Guard::Operand(_) => 0,
+            Guard::Compare { arity, .. }
+            | Guard::Predicate { arity, .. }
+            | Guard::Logic { arity, .. } => *arity,
+        }
+    }
+
+    fn last(&self) -> Option<usize> {
+        match self {
+            Guard::Operand(_) => None,
+            Guard::Compare { last, .. }
+            | Guard::Predicate { last, .. }
+            | Guard::Logic { last, .. } => Some(*last),
         }
     }
 }
@@ -211,7 +263,7 @@ where
         let end = Id::from(node_idx + 1);
         &self[start..end]
     }
-    
+
     fn calculate_tree_size(&self, node_id: Id) -> usize {
         let node = self.get_node(node_id);
         match node.last() {
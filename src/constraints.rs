@@ -0,0 +1,80 @@
+use crate::ast::*;
+use crate::children::Children;
+use crate::idx::*;
+use crate::pool::Pool;
+use crate::rules::CapturedValue;
+use std::collections::HashMap;
+
+impl Pool {
+    /// Matches `not(p)`, which keeps `captures` as they are if `p` does not
+    /// match `node_id`. `p` is matched without the outer captures, so
+    /// `not(?a * ?a)` rejects every square whatever `a` is bound to.
+    pub(crate) fn match_not(
+        &self,
+        pattern_id: PatternId,
+        node_id: ExprId,
+        captures: HashMap<NameId, CapturedValue>,
+    ) -> Vec<HashMap<NameId, CapturedValue>> {
+        let negated = self.children(pattern_id).all(|inner| {
+            self.match_pattern(inner, node_id, HashMap::new())
+                .is_empty()
+        });
+        if negated { vec![captures] } else { Vec::new() }
+    }
+
+    /// Matches `p where ?x != q, ..`: every match of `p` that satisfies all
+    /// the constraints, which are checked once `p` bound its captures.
+    pub(crate) fn match_where(
+        &self,
+        pattern_id: PatternId,
+        node_id: ExprId,
+        captures: HashMap<NameId, CapturedValue>,
+    ) -> Vec<HashMap<NameId, CapturedValue>> {
+        // Children come right to left, so the constrained pattern is last.
+        let mut constraints: Vec<_> = self.children(pattern_id).collect();
+        let Some(pattern) = constraints.pop() else {
+            return Vec::new();
+        };
+        self.match_pattern(pattern, node_id, captures)
+            .into_iter()
+            .filter(|found| {
+                constraints
+                    .iter()
+                    .all(|&constraint| self.constraint_holds(constraint, found))
+            })
+            .collect()
+    }
+
+    /// The pattern a `where` clause constrains, or the pattern itself.
+    pub(crate) fn constrained_pattern(&self, pattern_id: PatternId) -> PatternId {
+        match self[pattern_id] {
+            Pattern::Where { .. } => self.children(pattern_id).last().unwrap_or(pattern_id),
+            _ => pattern_id,
+        }
+    }
+
+    /// Whether `?x != q` holds, that is the capture `x` does not match `q`.
+    /// Operand lists and sequences are only compared with a capture `?y`.
+    fn constraint_holds(
+        &self,
+        constraint: PatternId,
+        captures: &HashMap<NameId, CapturedValue>,
+    ) -> bool {
+        let Pattern::Distinct { var, .. } = self[constraint] else {
+            return true;
+        };
+        let (Some(value), Some(other)) = (captures.get(&var), self.children(constraint).next())
+        else {
+            return true;
+        };
+        let matched = match value {
+            CapturedValue::Expression(node) => !self
+                .match_pattern(other, *node, captures.clone())
+                .is_empty(),
+            value => self
+                .bound_capture(other, captures)
+                .is_some_and(|bound| self.captured_eq(value, bound)),
+        };
+        !matched
+    }
+}
@@ -0,0 +1,158 @@
+use crate::ast::*;
+use crate::children::Children;
+use crate::idx::*;
+use crate::pool::{Location, Pool, Provenance};
+use crate::rules::{CapturedValue, RewriteError};
+use std::collections::HashMap;
+
+impl Pool {
+    /// Matches `C[p]` by trying `p` on `node_id` and on every subterm below
+    /// it, nearest to the root first. Each match binds `C` to the path from
+    /// `node_id` down to the subterm, so a rule like `C[?x - ?x] => C[0]`
+    /// rewrites one occurrence anywhere in the term.
+    pub(crate) fn match_context(
+        &self,
+        pattern_id: PatternId,
+        node_id: ExprId,
+        captures: HashMap<NameId, CapturedValue>,
+    ) -> Vec<HashMap<NameId, CapturedValue>> {
+        let (Pattern::Context { var, .. }, Some(hole)) =
+            (self[pattern_id], self.children(pattern_id).next())
+        else {
+            return Vec::new();
+        };
+
+        let mut found = Vec::new();
+        let mut stack = vec![(node_id, Vec::new())];
+        while let Some((current, path)) = stack.pop() {
+            for mut solution in self.match_pattern(hole, current, captures.clone()) {
+                let context = CapturedValue::Context {
+                    root: node_id,
+                    path: path.clone(),
+                };
+                match solution.get(&var) {
+                    Some(bound) if !self.captured_eq(bound, &context) => continue,
+                    Some(_) => {}
+                    None => {
+                        solution.insert(var, context);
+                    }
+                }
+                found.push(solution);
+            }
+
+            // Children come right to left, so the leftmost is visited first.
+            let children: Vec<_> = self.children(current).collect();
+            let arity = children.len();
+            for (offset, child) in children.into_iter().enumerate() {
+                let mut child_path = path.clone();
+                child_path.push(arity - 1 - offset);
+                stack.push((child, child_path));
+            }
+        }
+        found
+    }
+
+    /// Whether a rule's pattern is a `C[p]`, which only needs to be tried
+    /// at the root of a term.
+    pub(crate) fn is_context_pattern(&self, pattern_id: PatternId) -> bool {
+        matches!(
+            self[self.constrained_pattern(pattern_id)],
+            Pattern::Context { .. }
+        )
+    }
+
+    /// The subterm at the hole of a captured context.
+    pub fn context_hole(&self, root: ExprId, path: &[usize]) -> ExprId {
+        path.iter().fold(root, |node, &index| {
+            self.argument(node, index).unwrap_or(node)
+        })
+    }
+
+    /// Whether two contexts with the same hole position agree everywhere
+    /// but in their holes.
+    pub(crate) fn context_eq(&self, a: ExprId, b: ExprId, path: &[usize]) -> bool {
+        let (mut a, mut b) = (a, b);
+        for &index in path {
+            let same_head = match (self[a], self[b]) {
+                (
+                    ExprNode::Call { fun, arity, .. },
+                    ExprNode::Call {
+                        fun: other_fun,
+                        arity: other_arity,
+                        ..
+                    },
+                ) => fun == other_fun && arity == other_arity,
+                (
+                    ExprNode::Struct { name, arity, .. },
+                    ExprNode::Struct {
+                        name: other_name,
+                        arity: other_arity,
+                        ..
+                    },
+                ) => name == other_name && arity == other_arity,
+                _ => false,
+            };
+            if !same_head {
+                return false;
+            }
+            let mut args: Vec<_> = self.children(a).zip(self.children(b)).collect();
+            args.reverse();
+            let Some(&(hole_a, hole_b)) = args.get(index) else {
+                return false;
+            };
+            if args
+                .iter()
+                .enumerate()
+                .any(|(position, &(x, y))| position != index && !self.expr_eq(x, y))
+            {
+                return false;
+            }
+            (a, b) = (hole_a, hole_b);
+        }
+        true
+    }
+
+    /// Builds the term `root` with the subterm at `path` replaced by the
+    /// action `hole`, for `C[a]`.
+    pub(crate) fn build_plugged(
+        &mut self,
+        root: ExprId,
+        path: &[usize],
+        hole: ActionId,
+        captures: &HashMap<NameId, CapturedValue>,
+        output: &mut Vec<(ExprNode, Provenance)>,
+    ) -> Result<(), RewriteError> {
+        let Some((&index, rest)) = path.split_first() else {
+            return self.build_action_simple(hole, captures, output);
+        };
+
+        let mut args: Vec<_> = self.children(root).collect();
+        args.reverse();
+        let start = output.len();
+        for (position, &arg) in args.iter().enumerate() {
+            if position == index {
+                self.build_plugged(arg, rest, hole, captures, output)?;
+            } else {
+                self.copy_expression_to_vec(arg, output);
+            }
+        }
+        let last = output.len() - start;
+        let node = match self[root] {
+            ExprNode::Call { fun, arity, .. } => ExprNode::Call { fun, last, arity },
+            ExprNode::Struct { name, arity, .. } => ExprNode::Struct { name, last, arity },
+            leaf => leaf,
+        };
+        let provenance = self
+            .get_provenance(root)
+            .cloned()
+            .unwrap_or_else(|| Provenance::Parsed(Location::new(0, 0)));
+        output.push((node, provenance));
+        Ok(())
+    }
+
+    /// The argument at `index` of a call or struct, counted from the left.
+    fn argument(&self, node_id: ExprId, index: usize) -> Option<ExprId> {
+        let arity = self.children(node_id).count();
+        self.children(node_id).nth(arity.checked_sub(index + 1)?)
+    }
+}
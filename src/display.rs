@@ -39,7 +39,13 @@ impl DisplayNode<ExprId> for crate::Pool {
                     let node = self[id];
                     match node {
                         ExprNode::Number(n) => {
-                            results.push(n.to_string());
+                            results.push(self.display_number(n));
+                        }
+                        ExprNode::Rational(r) => {
+                            results.push(self.display_rational(r));
+                        }
+                        ExprNode::Float(f) => {
+                            results.push(self.display_float(f));
                         }
                         ExprNode::Variable(name_id) => {
                             results.push(self.display_name(name_id));
@@ -102,13 +108,7 @@ impl DisplayNode<ExprId> for crate::Pool {
                                 (Some(Function::Plus), 1, 1) => {
                                     format!("(+{})", child_results[0])
                                 }
-                                _ => {
-                                    format!(
-                                        "{}({})",
-                                        self.display_function(fun),
-                                        child_results.join(", ")
-                                    )
-                                }
+                                _ => self.display_call(fun, &child_results),
                             }
                         }
                         ExprNode::Struct { name, .. } => {
@@ -146,7 +146,13 @@ impl DisplayNode<PatternId> for crate::Pool {
                     let node = self[id];
                     match node {
                         Pattern::Number(n) => {
-                            results.push(n.to_string());
+                            results.push(self.display_number(n));
+                        }
+                        Pattern::Rational(r) => {
+                            results.push(self.display_rational(r));
+                        }
+                        Pattern::Float(f) => {
+                            results.push(self.display_float(f));
                         }
                         Pattern::Variable(name_id) => {
                             results.push(self.display_name(name_id));
@@ -157,10 +163,20 @@ impl DisplayNode<PatternId> for crate::Pool {
                         Pattern::Wildcard(name_id) => {
                             results.push(format!("?{}", self.display_name(name_id)));
                         }
+                        Pattern::Typed { name, kind } => {
+                            results.push(format!("?{}:{}", self.display_name(name), kind));
+                        }
+                        Pattern::Sequence(name_id) => {
+                            results.push(format!("?{}...", self.display_name(name_id)));
+                        }
                         Pattern::Call { .. }
                         | Pattern::Struct { .. }
                         | Pattern::VarCallName { .. }
-                        | Pattern::VarStructName { .. } => {
+                        | Pattern::VarStructName { .. }
+                        | Pattern::Not { .. }
+                        | Pattern::Where { .. }
+                        | Pattern::Distinct { .. }
+                        | Pattern::Context { .. } => {
                             let children: Vec<_> = self.children(id).collect();
                             if children.is_empty() {
                                 match node {
@@ -193,7 +209,11 @@ impl DisplayNode<PatternId> for crate::Pool {
                         Pattern::Call { arity, .. }
                         | Pattern::Struct { arity, .. }
                         | Pattern::VarCallName { arity, .. }
-                        | Pattern::VarStructName { arity, .. } => arity,
+                        | Pattern::VarStructName { arity, .. }
+                        | Pattern::Where { arity, .. } => arity,
+                        Pattern::Not { .. }
+                        | Pattern::Distinct { .. }
+                        | Pattern::Context { .. } => 1,
                         _ => 0,
                     };
 
@@ -226,13 +246,7 @@ impl DisplayNode<PatternId> for crate::Pool {
                             (Some(Function::Plus), 1) => {
                                 format!("(+{})", child_results[0])
                             }
-                            _ => {
-                                format!(
-                                    "{}({})",
-                                    self.display_function(fun),
-                                    child_results.join(", ")
-                                )
-                            }
+                            _ => self.display_call(fun, &child_results),
                         },
                         Pattern::Struct { name, .. } => {
                             format!(
@@ -251,6 +265,20 @@ impl DisplayNode<PatternId> for crate::Pool {
                                 child_results.join(", ")
                             )
                         }
+                        Pattern::Not { .. } => format!("not({})", child_results[0]),
+                        Pattern::Where { .. } => {
+                            format!(
+                                "{} where {}",
+                                child_results[0],
+                                child_results[1..].join(", ")
+                            )
+                        }
+                        Pattern::Distinct { var, .. } => {
+                            format!("?{} != {}", self.display_name(var), child_results[0])
+                        }
+                        Pattern::Context { var, .. } => {
+                            format!("{}[{}]", self.display_name(var), child_results[0])
+                        }
                         _ => unreachable!(),
                     };
 
@@ -279,15 +307,28 @@ impl DisplayNode<ActionId> for crate::Pool {
                     let node = self[id];
                     match node {
                         Action::Number(n) => {
-                            results.push(n.to_string());
+                            results.push(self.display_number(n));
+                        }
+                        Action::Rational(r) => {
+                            results.push(self.display_rational(r));
+                        }
+                        Action::Float(f) => {
+                            results.push(self.display_float(f));
                         }
                         Action::Variable(name_id) => {
                             results.push(self.display_name(name_id));
                         }
+                        Action::Fresh(name_id) => {
+                            results.push(format!("fresh({})", self.display_name(name_id)));
+                        }
+                        Action::Splice(name_id) => {
+                            results.push(format!("{}...", self.display_name(name_id)));
+                        }
                         Action::Call { .. }
                         | Action::Struct { .. }
                         | Action::VarCallName { .. }
                         | Action::VarStructName { .. }
+                        | Action::Plug { .. }
                         | Action::Compute { .. } => {
                             let children: Vec<_> = self.children(id).collect();
                             if children.is_empty() {
@@ -305,15 +346,7 @@ impl DisplayNode<ActionId> for crate::Pool {
                                         results.push(format!("?{}{{ }}", self.display_name(var)));
                                     }
                                     Action::Compute { op, .. } => {
-                                        let op_str = match op {
-                                            ComputeOp::Add => "+",
-                                            ComputeOp::Subtract => "-",
-                                            ComputeOp::Multiply => "*",
-                                            ComputeOp::Divide => "/",
-                                            ComputeOp::Power => "^",
-                                            ComputeOp::Negate => "neg",
-                                        };
-                                        results.push(format!("[{}()]", op_str));
+                                        results.push(format!("[{}()]", op));
                                     }
                                     _ => unreachable!(),
                                 }
@@ -334,6 +367,7 @@ impl DisplayNode<ActionId> for crate::Pool {
                         | Action::VarCallName { arity, .. }
                         | Action::VarStructName { arity, .. }
                         | Action::Compute { arity, .. } => arity,
+                        Action::Plug { .. } => 1,
                         _ => 0,
                     };
 
@@ -366,13 +400,7 @@ impl DisplayNode<ActionId> for crate::Pool {
                             (Some(Function::Plus), 1) => {
                                 format!("(+{})", child_results[0])
                             }
-                            _ => {
-                                format!(
-                                    "{}({})",
-                                    self.display_function(fun),
-                                    child_results.join(", ")
-                                )
-                            }
+                            _ => self.display_call(fun, &child_results),
                         },
                         Action::Struct { name, .. } => {
                             format!(
@@ -410,18 +438,14 @@ impl DisplayNode<ActionId> for crate::Pool {
                             (ComputeOp::Negate, 1) => {
                                 format!("[-{}]", child_results[0])
                             }
-                            _ => {
-                                let op_str = match op {
-                                    ComputeOp::Add => "+",
-                                    ComputeOp::Subtract => "-",
-                                    ComputeOp::Multiply => "*",
-                                    ComputeOp::Divide => "/",
-                                    ComputeOp::Power => "^",
-                                    ComputeOp::Negate => "neg",
-                                };
-                                format!("[{}({})]", op_str, child_results.join(", "))
+                            (ComputeOp::Modulo | ComputeOp::Compare(_), 2) => {
+                                format!("[{} {} {}]", child_results[0], op, child_results[1])
                             }
+                            _ => format!("[{}({})]", op, child_results.join(", ")),
                         },
+                        Action::Plug { var, .. } => {
+                            format!("{}[{}]", self.display_name(var), child_results[0])
+                        }
                         _ => unreachable!(),
                     };
 
@@ -434,19 +458,66 @@ impl DisplayNode<ActionId> for crate::Pool {
     }
 }
 
+impl DisplayNode<GuardId> for crate::Pool {
+    fn display_with_children(&self, guard_id: GuardId) -> String {
+        // Children come right to left.
+        let mut children: Vec<_> = self
+            .children(guard_id)
+            .map(|child| self.display_with_children(child))
+            .collect();
+        children.reverse();
+
+        match self[guard_id] {
+            Guard::Operand(action) => self.display_with_children(action),
+            Guard::Compare { op, .. } => children.join(&format!(" {} ", op)),
+            Guard::Predicate { predicate, .. } => format!("{}({})", predicate, children.join(", ")),
+            Guard::Logic {
+                op: LogicOp::Not, ..
+            } => format!("!{}", children.join("")),
+            Guard::Logic {
+                op: LogicOp::And, ..
+            } => format!("({})", children.join(" && ")),
+            Guard::Logic {
+                op: LogicOp::Or, ..
+            } => format!("({})", children.join(" || ")),
+        }
+    }
+}
+
 impl DisplayNode<RuleId> for crate::Pool {
     fn display_with_children(&self, rule_id: RuleId) -> String {
         let rule = &self[rule_id];
+        let guard = rule
+            .guard
+            .map(|guard| format!(" when {}", self.display_with_children(guard)))
+            .unwrap_or_default();
         format!(
-            "{}: {} => {}",
+            "{}: {} => {}{}",
             self.display_name(rule.name),
             self.display_with_children(rule.pattern),
-            self.display_with_children(rule.action)
+            self.display_with_children(rule.action),
+            guard
         )
     }
 }
 
 impl crate::Pool {
+    /// Shows a rule that came from `lhs <=> rhs` as that equation.
+    pub fn display_equation(&self, rule_id: RuleId) -> Option<String> {
+        let (forward, reverse) = self.rule_equation(rule_id)?;
+        let guard = self[forward]
+            .guard
+            .map(|guard| format!(" when {}", self.display_with_children(guard)))
+            .unwrap_or_default();
+        Some(format!(
+            "{}: {} <=> {}{}",
+            self.display_name(self[forward].name),
+            self.display_with_children(self[forward].pattern),
+            self.display_with_children(self[reverse].pattern),
+            guard
+        ))
+    }
+
     pub fn display_name(&self, name_id: NameId) -> String {
         if let Some(name) = self.get(name_id) {
             name.clone()
@@ -455,6 +526,59 @@ impl crate::Pool {
         }
     }
 
+    pub fn display_number(&self, number_id: NumberId) -> String {
+        if let Some(value) = self.numbers.get(number_id.0) {
+            value.to_string()
+        } else {
+            format!("number_{}", number_id.0)
+        }
+    }
+
+    pub fn display_rational(&self, rational_id: RationalId) -> String {
+        if let Some(value) = self.rationals.get(rational_id.0) {
+            format!("{}//{}", value.numer(), value.denom())
+        } else {
+            format!("rational_{}", rational_id.0)
+        }
+    }
+
+    pub fn display_exact(&self, number: ExactNumber) -> String {
+        match number {
+            ExactNumber::Integer(number_id) => self.display_number(number_id),
+            ExactNumber::Rational(rational_id) => self.display_rational(rational_id),
+        }
+    }
+
+    pub fn display_float(&self, float_id: FloatId) -> String {
+        let Some(&bits) = self.floats.get(float_id.0) else {
+            return format!("float_{}", float_id.0);
+        };
+        let value = f64::from_bits(bits);
+        // `Debug` keeps a `.` or an exponent on finite floats, so they parse
+        // back as float literals. Infinity and NaN have no literal and would
+        // print as `inf` and `NaN`, which parse as variables, so they are
+        // printed as the divisions that compute them.
+        if value.is_nan() {
+            "(0.0 / 0.0)".to_string()
+        } else if value == f64::INFINITY {
+            "(1.0 / 0.0)".to_string()
+        } else if value == f64::NEG_INFINITY {
+            "(-(1.0 / 0.0))".to_string()
+        } else {
+            format!("{:?}", value)
+        }
+    }
+
+    /// Prints a call of a custom function, in operator form when the function
+    /// was declared as an operator of matching arity.
+    fn display_call(&self, fun_id: FunctionId, args: &[String]) -> String {
+        match (self.operator_for_call(fun_id, args.len()), args) {
+            (Some(op), [operand]) => format!("({}{})", op.symbol, operand),
+            (Some(op), [left, right]) => format!("({} {} {})", left, op.symbol, right),
+            _ => format!("{}({})", self.display_function(fun_id), args.join(", ")),
+        }
+    }
+
     pub fn display_function(&self, fun_id: FunctionId) -> String {
         if let Some(function) = self.get(fun_id) {
             match function {
@@ -0,0 +1,349 @@
+use crate::ast::Guard;
+use crate::children::Children;
+use crate::idx::*;
+use crate::pool::{Location, Pool, Provenance};
+use crate::rules::{CapturedValue, Match, MatchLimit};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Old-to-new id mapping produced by `Pool::collect_garbage`. Ids missing from
+/// a table were collected.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GcRemap {
+    pub exprs: HashMap<ExprId, ExprId>,
+    pub patterns: HashMap<PatternId, PatternId>,
+    pub actions: HashMap<ActionId, ActionId>,
+    pub groups: HashMap<EquivalenceGroupId, EquivalenceGroupId>,
+}
+
+impl GcRemap {
+    pub fn expr(&self, id: ExprId) -> Option<ExprId> {
+        self.exprs.get(&id).copied()
+    }
+
+    pub fn pattern(&self, id: PatternId) -> Option<PatternId> {
+        self.patterns.get(&id).copied()
+    }
+
+    pub fn action(&self, id: ActionId) -> Option<ActionId> {
+        self.actions.get(&id).copied()
+    }
+
+    pub fn group(&self, id: EquivalenceGroupId) -> Option<EquivalenceGroupId> {
+        self.groups.get(&id).copied()
+    }
+
+    pub fn remap_match(&self, match_: &Match) -> Option<Match> {
+        let mut captures = HashMap::new();
+        for (&name, value) in &match_.captures {
+            let value = match value {
+                CapturedValue::Expression(expr_id) => {
+                    CapturedValue::Expression(self.expr(*expr_id)?)
+                }
+                CapturedValue::Operands { fun, args } => CapturedValue::Operands {
+                    fun: *fun,
+                    args: args
+                        .iter()
+                        .map(|&arg| self.expr(arg))
+                        .collect::<Option<_>>()?,
+                },
+                CapturedValue::Sequence(items) => CapturedValue::Sequence(
+                    items
+                        .iter()
+                        .map(|&item| self.expr(item))
+                        .collect::<Option<_>>()?,
+                ),
+                CapturedValue::Context { root, path } => CapturedValue::Context {
+                    root: self.expr(*root)?,
+                    path: path.clone(),
+                },
+                other => other.clone(),
+            };
+            captures.insert(name, value);
+        }
+
+        Some(Match {
+            root: self.expr(match_.root)?,
+            offset: self.expr(match_.offset)?,
+            rule_id: match_.rule_id,
+            captures,
+        })
+    }
+
+    pub fn remap_match_limit(&self, limit: MatchLimit) -> Option<MatchLimit> {
+        Some(match limit {
+            MatchLimit::TooManyMatches { offset, rule_id } => MatchLimit::TooManyMatches {
+                offset: self.expr(offset)?,
+                rule_id,
+            },
+            MatchLimit::TooManyOperands { offset, rule_id } => MatchLimit::TooManyOperands {
+                offset: self.expr(offset)?,
+                rule_id,
+            },
+        })
+    }
+}
+
+/// Merges the `[start, end]` extents of live trees into disjoint, ascending
+/// segments. Nested or overlapping extents are copied as one block so the
+/// relative `last` offsets inside them stay valid.
+fn live_segments(mut extents: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    extents.sort_unstable();
+    let mut segments: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in extents {
+        match segments.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+            _ => segments.push((start, end)),
+        }
+    }
+    segments
+}
+
+fn compact<T: Clone>(items: &[T], segments: &[(usize, usize)]) -> (Vec<T>, HashMap<usize, usize>) {
+    let mut kept = Vec::new();
+    let mut mapping = HashMap::new();
+    for &(start, end) in segments {
+        for (old, item) in items.iter().enumerate().take(end + 1).skip(start) {
+            mapping.insert(old, kept.len());
+            kept.push(item.clone());
+        }
+    }
+    (kept, mapping)
+}
+
+impl Pool {
+    /// Drops every expression, pattern and action that is not reachable from
+    /// `roots`, the registered rules or the transformation graph, and compacts
+    /// the pool's flat vectors.
+    ///
+    /// An expression stays alive if it lies inside the tree of one of `roots`
+    /// or of an expression with recorded transformations, so the explored
+    /// graph survives. Rules, rulesets, names and functions are never
+    /// collected, so `RuleId`s stay stable.
+    pub fn collect_garbage(&mut self, roots: &[ExprId]) -> GcRemap {
+        let mut remap = GcRemap::default();
+
+        self.compact_exprs(roots, &mut remap);
+        self.compact_patterns(&mut remap);
+        self.compact_actions(&mut remap);
+        self.rebuild_hash_cons();
+        self.remap_transformation_graph(&mut remap);
+
+        remap
+    }
+
+    fn containing_root(&self, expr_id: ExprId) -> Option<ExprId> {
+        let root = self.find_root(expr_id)?;
+        let start = root.0 + 1 - self.calculate_tree_size(root);
+        (start <= expr_id.0).then_some(root)
+    }
+
+    fn live_expr_roots(&self, roots: &[ExprId]) -> HashSet<ExprId> {
+        roots
+            .iter()
+            .filter(|id| id.0 < self.exprs.len())
+            .chain(self.outgoing.keys())
+            .chain(self.incoming.keys())
+            .filter_map(|&id| self.containing_root(id))
+            .collect()
+    }
+
+    fn compact_exprs(&mut self, roots: &[ExprId], remap: &mut GcRemap) {
+        let live_roots = self.live_expr_roots(roots);
+        let extents = live_roots
+            .iter()
+            .map(|&root| (root.0 + 1 - self.calculate_tree_size(root), root.0))
+            .collect();
+        let segments = live_segments(extents);
+
+        let (exprs, mapping) = compact(&self.exprs, &segments);
+        let mut locations = Vec::with_capacity(exprs.len());
+        for &(start, end) in &segments {
+            for old in start..=end {
+                let provenance = match self.locations.get(old) {
+                    Some(Provenance::Rule {
+                        rule_id,
+                        source_node,
+                        source_location,
+                    }) => Provenance::Rule {
+                        rule_id: *rule_id,
+                        source_node: mapping
+                            .get(&source_node.0)
+                            .map(|&new| ExprId(new))
+                            .unwrap_or(*source_node),
+                        source_location: source_location.clone(),
+                    },
+                    Some(provenance) => provenance.clone(),
+                    None => Provenance::Parsed(Location::new(0, 0)),
+                };
+                locations.push(provenance);
+            }
+        }
+
+        self.expr_ends = self
+            .expr_ends
+            .iter()
+            .filter_map(|end| mapping.get(end).copied())
+            .collect();
+        self.exprs = exprs;
+        self.locations = locations;
+
+        remap.exprs = mapping
+            .into_iter()
+            .map(|(old, new)| (ExprId(old), ExprId(new)))
+            .collect();
+    }
+
+    fn compact_patterns(&mut self, remap: &mut GcRemap) {
+        let extents = self
+            .rules
+            .iter()
+            .filter(|rule| rule.pattern.0 < self.patterns.len())
+            .map(|rule| {
+                let root = rule.pattern;
+                (root.0 + 1 - self.calculate_tree_size(root), root.0)
+            })
+            .collect();
+        let segments = live_segments(extents);
+
+        let (patterns, mapping) = compact(&self.patterns, &segments);
+        self.patterns = patterns;
+        for rule in &mut self.rules {
+            if let Some(&new) = mapping.get(&rule.pattern.0) {
+                rule.pattern = PatternId(new);
+            }
+        }
+
+        remap.patterns = mapping
+            .into_iter()
+            .map(|(old, new)| (PatternId(old), PatternId(new)))
+            .collect();
+    }
+
+    fn compact_actions(&mut self, remap: &mut GcRemap) {
+        // Guards are never collected, so the operands they compute stay too.
+        let guard_operands = self.guards.iter().filter_map(|guard| match guard {
+            Guard::Operand(action) => Some(*action),
+            _ => None,
+        });
+        let extents = self
+            .rules
+            .iter()
+            .map(|rule| rule.action)
+            .chain(guard_operands)
+            .filter(|root| root.0 < self.actions.len())
+            .map(|root| (root.0 + 1 - self.calculate_tree_size(root), root.0))
+            .collect();
+        let segments = live_segments(extents);
+
+        if self.action_locations.len() == self.actions.len() {
+            self.action_locations = compact(&self.action_locations, &segments).0;
+        } else {
+            self.action_locations.clear();
+        }
+
+        let (actions, mapping) = compact(&self.actions, &segments);
+        self.actions = actions;
+        for rule in &mut self.rules {
+            if let Some(&new) = mapping.get(&rule.action.0) {
+                rule.action = ActionId(new);
+            }
+        }
+        for guard in &mut self.guards {
+            if let Guard::Operand(action) = guard
+                && let Some(&new) = mapping.get(&action.0)
+            {
+                *action = ActionId(new);
+            }
+        }
+
+        remap.actions = mapping
+            .into_iter()
+            .map(|(old, new)| (ActionId(old), ActionId(new)))
+            .collect();
+    }
+
+    fn remap_transformation_graph(&mut self, remap: &mut GcRemap) {
+        let remap_edges = |edges: &HashMap<ExprId, Vec<(ExprId, RuleId)>>| {
+            edges
+                .iter()
+                .filter_map(|(from, targets)| {
+                    let targets: Vec<_> = targets
+                        .iter()
+                        .filter_map(|&(to, rule)| Some((remap.expr(to)?, rule)))
+                        .collect();
+                    Some((remap.expr(*from)?, targets))
+                })
+                .collect::<HashMap<_, _>>()
+        };
+        self.outgoing = remap_edges(&self.outgoing);
+        self.incoming = remap_edges(&self.incoming);
+
+        self.by_rule = self
+            .by_rule
+            .iter()
+            .map(|(&rule, applications)| {
+                let applications = applications
+                    .iter()
+                    .filter_map(|&(from, to)| Some((remap.expr(from)?, remap.expr(to)?)))
+                    .collect();
+                (rule, applications)
+            })
+            .collect();
+
+        let old_groups = std::mem::take(&mut self.equivalence_groups);
+        self.expr_to_group.clear();
+        self.canonical_groups.clear();
+        for (old_id, members) in old_groups.into_iter().enumerate() {
+            let members: HashSet<_> = members
+                .into_iter()
+                .filter_map(|expr| remap.expr(expr))
+                .collect();
+            let Some(&representative) = members.iter().next() else {
+                continue;
+            };
+
+            let new_id = EquivalenceGroupId::new(self.equivalence_groups.len());
+            remap.groups.insert(EquivalenceGroupId::new(old_id), new_id);
+            for &member in &members {
+                self.expr_to_group.insert(member, new_id);
+            }
+            self.canonical_groups
+                .insert(self.canonical(representative), new_id);
+            self.equivalence_groups.push(members);
+        }
+
+        let remap_group_edges =
+            |edges: &HashMap<EquivalenceGroupId, Vec<(EquivalenceGroupId, RuleId)>>| {
+                edges
+                    .iter()
+                    .filter_map(|(from, targets)| {
+                        let targets: Vec<_> = targets
+                            .iter()
+                            .filter_map(|&(to, rule)| Some((remap.group(to)?, rule)))
+                            .collect();
+                        Some((remap.group(*from)?, targets))
+                    })
+                    .collect::<HashMap<_, _>>()
+            };
+        self.equivalence_outgoing = remap_group_edges(&self.equivalence_outgoing);
+        self.equivalence_incoming = remap_group_edges(&self.equivalence_incoming);
+
+        let remap_chain = |chain: &Vec<(EquivalenceGroupId, RuleId)>| {
+            chain
+                .iter()
+                .map(|&(group, rule)| Some((remap.group(group)?, rule)))
+                .collect::<Option<Vec<_>>>()
+        };
+        self.blacklisted_chains = self
+            .blacklisted_chains
+            .iter()
+            .filter_map(remap_chain)
+            .collect();
+        self.current_application_chains = self
+            .current_application_chains
+            .iter()
+            .filter_map(|(group, chain)| Some((remap.group(*group)?, remap_chain(chain)?)))
+            .collect();
+    }
+}
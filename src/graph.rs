@@ -207,11 +207,11 @@ impl Pool {
             for &(next_node, _rule) in outgoing {
                 if !visited.contains(&next_node) {
                     self.dfs_cycle_detection(next_node, visited, rec_stack, path, cycles);
-                } else if rec_stack.contains(&next_node) {
-                    if let Some(cycle_start) = path.iter().position(|&x| x == next_node) {
-                        let cycle = path[cycle_start..].to_vec();
-                        cycles.push(cycle);
-                    }
+                } else if rec_stack.contains(&next_node)
+                    && let Some(cycle_start) = path.iter().position(|&x| x == next_node)
+                {
+                    let cycle = path[cycle_start..].to_vec();
+                    cycles.push(cycle);
                 }
             }
         }
@@ -347,14 +347,17 @@ impl Pool {
     }
 
     pub fn update_equivalence_groups(&mut self, new_expr: ExprId) {
-        for (&existing_expr, &group_id) in &self.expr_to_group.clone() {
-            if self.expr_eq(new_expr, existing_expr) {
-                if let Some(group) = self.equivalence_groups.get_mut(group_id.0) {
-                    group.insert(new_expr);
-                    self.expr_to_group.insert(new_expr, group_id);
-                    return;
-                }
-            }
+        if self.expr_to_group.contains_key(&new_expr) {
+            return;
+        }
+
+        let canonical = self.canonical(new_expr);
+        if let Some(&group_id) = self.canonical_groups.get(&canonical)
+            && let Some(group) = self.equivalence_groups.get_mut(group_id.0)
+        {
+            group.insert(new_expr);
+            self.expr_to_group.insert(new_expr, group_id);
+            return;
         }
 
         let group_id = EquivalenceGroupId::new(self.equivalence_groups.len());
@@ -362,6 +365,7 @@ impl Pool {
         new_group.insert(new_expr);
         self.equivalence_groups.push(new_group);
         self.expr_to_group.insert(new_expr, group_id);
+        self.canonical_groups.insert(canonical, group_id);
     }
 
     pub fn should_apply_rule(
@@ -0,0 +1,243 @@
+use crate::ast::*;
+use crate::children::Children;
+use crate::idx::*;
+use crate::pool::{Pool, Provenance};
+use std::hash::{Hash, Hasher};
+
+/// FNV-1a hasher. Node hashes are stored in the pool and exported with it, so
+/// they must not depend on the per-process keys of `DefaultHasher`.
+struct MerkleHasher(u64);
+
+impl Default for MerkleHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for MerkleHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+impl Pool {
+    /// Registers a freshly pushed node in the hash-cons table. The node's
+    /// children must already be interned, which the postfix layout guarantees.
+    /// If the tables lag behind, e.g. on a deserialized pool, they are rebuilt
+    /// first.
+    pub(crate) fn intern_expr_node(&mut self, expr_id: ExprId) {
+        if self.expr_hashes.len() != expr_id.0 {
+            self.rebuild_hash_cons();
+            return;
+        }
+        let hash = self.compute_expr_hash(expr_id);
+        self.expr_hashes.push(hash);
+
+        let existing = self.hash_cons.get(&hash).and_then(|bucket| {
+            bucket
+                .iter()
+                .copied()
+                .find(|&candidate| self.same_expr_node(candidate, expr_id))
+        });
+
+        match existing {
+            Some(canonical) => self.canonical_exprs.push(canonical),
+            None => {
+                self.canonical_exprs.push(expr_id);
+                self.hash_cons.entry(hash).or_default().push(expr_id);
+            }
+        }
+    }
+
+    /// Returns the first expression in the pool that is structurally identical
+    /// to `expr_id`. Two expressions are equal iff their canonical ids are,
+    /// wherever in the pool each of them is stored.
+    pub fn canonical(&self, expr_id: ExprId) -> ExprId {
+        self.canonical_exprs
+            .get(expr_id.0)
+            .copied()
+            .unwrap_or(expr_id)
+    }
+
+    pub fn expr_hash(&self, expr_id: ExprId) -> Option<u64> {
+        self.expr_hashes.get(expr_id.0).copied()
+    }
+
+    pub fn is_interned(&self, expr_id: ExprId) -> bool {
+        expr_id.0 < self.canonical_exprs.len()
+    }
+
+    /// Looks up a root expression that is structurally identical to `expr_id`.
+    pub fn find_interned_root(&self, expr_id: ExprId) -> Option<ExprId> {
+        self.interned_roots.get(&self.canonical(expr_id)).copied()
+    }
+
+    /// Appends a complete postfix tree and marks it as a root. If the same
+    /// tree is already stored as a root, the appended nodes are dropped again
+    /// and the existing root is returned instead.
+    ///
+    /// Only ids are shared between roots, not storage: the postfix layout
+    /// needs a node's children right before it, so a new root stores its
+    /// whole tree, and subtrees it has in common with other roots are
+    /// recognised by their canonical id. A rewrite therefore adds at most the
+    /// size of its result, and nothing if the result is already a root.
+    pub(crate) fn add_expr_tree(&mut self, nodes: Vec<(ExprNode, Provenance)>) -> Option<ExprId> {
+        let exprs_start = self.exprs.len();
+        let locations_start = self.locations.len();
+
+        for (node, provenance) in nodes {
+            self.add_expr_with_provenance(node, provenance);
+        }
+
+        if self.exprs.len() == exprs_start {
+            return None;
+        }
+
+        let new_root = ExprId(self.exprs.len() - 1);
+        if let Some(existing_root) = self.find_interned_root(new_root) {
+            self.truncate_exprs(exprs_start, locations_start);
+            return Some(existing_root);
+        }
+
+        self.mark_expr_end(new_root);
+        Some(new_root)
+    }
+
+    fn truncate_exprs(&mut self, exprs_len: usize, locations_len: usize) {
+        for idx in (exprs_len..self.exprs.len()).rev() {
+            if self.canonical(ExprId(idx)) != ExprId(idx) {
+                continue;
+            }
+            let Some(hash) = self.expr_hash(ExprId(idx)) else {
+                continue;
+            };
+            if let Some(bucket) = self.hash_cons.get_mut(&hash) {
+                bucket.retain(|&id| id != ExprId(idx));
+                if bucket.is_empty() {
+                    self.hash_cons.remove(&hash);
+                }
+            }
+        }
+
+        self.exprs.truncate(exprs_len);
+        self.expr_hashes.truncate(exprs_len);
+        self.canonical_exprs.truncate(exprs_len);
+        self.locations.truncate(locations_len);
+    }
+
+    /// Rebuilds the hash-cons tables from `exprs`, e.g. after nodes were
+    /// written to the pool without going through `add_expr` or after the pool
+    /// was deserialized, since the tables are not exported. The groups of the
+    /// canonical ids are looked up again from `expr_to_group`, so pools
+    /// exported without them still find their equivalence groups.
+    pub fn rebuild_hash_cons(&mut self) {
+        self.expr_hashes.clear();
+        self.canonical_exprs.clear();
+        self.hash_cons.clear();
+        self.interned_roots.clear();
+
+        for idx in 0..self.exprs.len() {
+            self.intern_expr_node(ExprId(idx));
+        }
+        let roots: Vec<_> = self.get_all_roots().collect();
+        for root in roots {
+            let canonical = self.canonical(root);
+            self.interned_roots.entry(canonical).or_insert(root);
+        }
+
+        let mut members: Vec<_> = self
+            .expr_to_group
+            .iter()
+            .map(|(&expr_id, &group_id)| (expr_id, group_id))
+            .collect();
+        members.sort_by_key(|(expr_id, _)| expr_id.0);
+        self.canonical_groups.clear();
+        for (expr_id, group_id) in members {
+            let canonical = self.canonical(expr_id);
+            self.canonical_groups.entry(canonical).or_insert(group_id);
+        }
+    }
+
+    fn compute_expr_hash(&self, expr_id: ExprId) -> u64 {
+        let mut hasher = MerkleHasher::default();
+        match self[expr_id] {
+            ExprNode::Number(n) => {
+                0u8.hash(&mut hasher);
+                n.hash(&mut hasher);
+            }
+            ExprNode::Variable(name) => {
+                1u8.hash(&mut hasher);
+                name.hash(&mut hasher);
+            }
+            ExprNode::Rational(r) => {
+                4u8.hash(&mut hasher);
+                r.hash(&mut hasher);
+            }
+            ExprNode::Float(f) => {
+                5u8.hash(&mut hasher);
+                f.hash(&mut hasher);
+            }
+            ExprNode::Call { fun, arity, .. } => {
+                2u8.hash(&mut hasher);
+                fun.hash(&mut hasher);
+                arity.hash(&mut hasher);
+            }
+            ExprNode::Struct { name, arity, .. } => {
+                3u8.hash(&mut hasher);
+                name.hash(&mut hasher);
+                arity.hash(&mut hasher);
+            }
+        }
+        for child in self.children(expr_id) {
+            self.expr_hash(child).unwrap_or_default().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    fn same_expr_node(&self, left: ExprId, right: ExprId) -> bool {
+        let same_head = match (self[left], self[right]) {
+            (ExprNode::Number(a), ExprNode::Number(b)) => a == b,
+            (ExprNode::Rational(a), ExprNode::Rational(b)) => a == b,
+            (ExprNode::Float(a), ExprNode::Float(b)) => a == b,
+            (ExprNode::Variable(a), ExprNode::Variable(b)) => a == b,
+            (
+                ExprNode::Call {
+                    fun: fun1,
+                    arity: arity1,
+                    ..
+                },
+                ExprNode::Call {
+                    fun: fun2,
+                    arity: arity2,
+                    ..
+                },
+            ) => fun1 == fun2 && arity1 == arity2,
+            (
+                ExprNode::Struct {
+                    name: name1,
+                    arity: arity1,
+                    ..
+                },
+                ExprNode::Struct {
+                    name: name2,
+                    arity: arity2,
+                    ..
+                },
+            ) => name1 == name2 && arity1 == arity2,
+            _ => false,
+        };
+
+        same_head
+            && self
+                .children(left)
+                .zip(self.children(right))
+                .all(|(a, b)| self.canonical(a) == self.canonical(b))
+    }
+}
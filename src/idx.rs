@@ -25,6 +25,18 @@ pub struct RuleId(pub usize);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ActionId(pub usize);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GuardId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NumberId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RationalId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FloatId(pub usize);
+
 impl ExprId {
     pub fn new(id: usize) -> Self {
         Self(id)
@@ -97,6 +109,42 @@ impl ActionId {
     }
 }
 
+impl GuardId {
+    pub fn new(id: usize) -> Self {
+        Self(id)
+    }
+    pub fn get(&self) -> usize {
+        self.0
+    }
+}
+
+impl NumberId {
+    pub fn new(id: usize) -> Self {
+        Self(id)
+    }
+    pub fn get(&self) -> usize {
+        self.0
+    }
+}
+
+impl RationalId {
+    pub fn new(id: usize) -> Self {
+        Self(id)
+    }
+    pub fn get(&self) -> usize {
+        self.0
+    }
+}
+
+impl FloatId {
+    pub fn new(id: usize) -> Self {
+        Self(id)
+    }
+    pub fn get(&self) -> usize {
+        self.0
+    }
+}
+
 impl fmt::Display for NameId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -210,3 +258,51 @@ impl From<ActionId> for usize {
         id.0
     }
 }
+
+impl From<usize> for GuardId {
+    fn from(value: usize) -> Self {
+        Self(value)
+    }
+}
+
+impl From<GuardId> for usize {
+    fn from(id: GuardId) -> Self {
+        id.0
+    }
+}
+
+impl From<usize> for NumberId {
+    fn from(value: usize) -> Self {
+        Self(value)
+    }
+}
+
+impl From<NumberId> for usize {
+    fn from(id: NumberId) -> Self {
+        id.0
+    }
+}
+
+impl From<usize> for RationalId {
+    fn from(value: usize) -> Self {
+        Self(value)
+    }
+}
+
+impl From<RationalId> for usize {
+    fn from(id: RationalId) -> Self {
+        id.0
+    }
+}
+
+impl From<usize> for FloatId {
+    fn from(value: usize) -> Self {
+        Self(value)
+    }
+}
+
+impl From<FloatId> for usize {
+    fn from(id: FloatId) -> Self {
+        id.0
+    }
+}
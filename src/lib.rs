@@ -2,15 +2,25 @@ use crate::children::Children;
 
 pub mod ast;
 pub mod children;
+pub mod constraints;
+pub mod contexts;
 pub mod display;
+pub mod gc;
 pub mod graph;
+pub mod hashcons;
 pub mod idx;
+pub mod numeric;
+pub mod operators;
 pub mod pool;
+pub mod pretty;
+pub mod rule_index;
+pub mod theories;
 
 pub use ast::*;
 pub use display::*;
 pub use idx::*;
 pub use pool::*;
+pub use pretty::*;
 
 pub mod parser;
 pub mod rules;
@@ -21,11 +31,19 @@ pub mod ui;
 pub use parser::action_parser::ActionParser;
 pub use parser::compute_parser::ComputeParser;
 pub use parser::expr_parser::ExprParser;
+pub use parser::guard_parser::GuardParser;
 pub use parser::pattern_parser::PatternParser;
 pub use parser::ruleset_parser::RulesetParser;
 
 impl Pool {
     pub fn expr_eq(&self, node1_id: ExprId, node2_id: ExprId) -> bool {
+        if self.is_interned(node1_id) && self.is_interned(node2_id) {
+            return self.canonical(node1_id) == self.canonical(node2_id);
+        }
+        self.expr_eq_structural(node1_id, node2_id)
+    }
+
+    fn expr_eq_structural(&self, node1_id: ExprId, node2_id: ExprId) -> bool {
         let mut stack = vec![(node1_id, node2_id)];
 
         loop {
@@ -42,6 +60,8 @@ impl Pool {
 
             match (node1, node2) {
                 (ExprNode::Number(i), ExprNode::Number(j)) if i == j => continue,
+                (ExprNode::Rational(i), ExprNode::Rational(j)) if i == j => continue,
+                (ExprNode::Float(i), ExprNode::Float(j)) if i == j => continue,
                 (ExprNode::Variable(i), ExprNode::Variable(j)) if i == j => continue,
                 (
                     ExprNode::Call {
@@ -61,7 +81,7 @@ impl Pool {
                     let children1 = self.children(node1_id);
                     let children2 = self.children(node2_id);
 
-                    for (child1, child2) in children1.into_iter().zip(children2.into_iter()) {
+                    for (child1, child2) in children1.into_iter().zip(children2) {
                         stack.push((child1, child2));
                     }
                 }
@@ -0,0 +1,272 @@
+use crate::ast::*;
+use crate::idx::*;
+use crate::pool::Pool;
+use crate::rules::RewriteError;
+use num_bigint::{BigInt, Sign};
+use num_rational::BigRational;
+use num_traits::{One, Pow, Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+
+/// Exact powers whose result would need more bits than this are rejected
+/// with `RewriteError::Overflow`, since computing `3 ^ 2000000000` would take
+/// minutes and gigabytes. The size is estimated as `|exponent|` times the bits
+/// of the base's numerator or denominator; bases `0`, `1` and `-1` never grow
+/// and take any integer exponent.
+pub const MAX_POW_BITS: u64 = 1 << 20;
+
+/// A value produced by `[...]` compute blocks.
+///
+/// Exact values stay exact. As soon as one operand is a float the operation is
+/// carried out in `f64` with IEEE semantics, so `1 / 0.0` is `inf` and
+/// `0.0 / 0.0` is `NaN` while `1 / 0` has no result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Numeric {
+    Exact(BigRational),
+    Float(f64),
+}
+
+impl Numeric {
+    pub fn zero() -> Numeric {
+        Numeric::Exact(BigRational::zero())
+    }
+
+    pub fn one() -> Numeric {
+        Numeric::Exact(BigRational::one())
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Numeric::Exact(value) => value.to_f64().unwrap_or(f64::NAN),
+            Numeric::Float(value) => *value,
+        }
+    }
+
+    pub fn add(&self, other: &Numeric) -> Numeric {
+        match (self, other) {
+            (Numeric::Exact(a), Numeric::Exact(b)) => Numeric::Exact(a + b),
+            _ => Numeric::Float(self.to_f64() + other.to_f64()),
+        }
+    }
+
+    pub fn sub(&self, other: &Numeric) -> Numeric {
+        match (self, other) {
+            (Numeric::Exact(a), Numeric::Exact(b)) => Numeric::Exact(a - b),
+            _ => Numeric::Float(self.to_f64() - other.to_f64()),
+        }
+    }
+
+    pub fn mul(&self, other: &Numeric) -> Numeric {
+        match (self, other) {
+            (Numeric::Exact(a), Numeric::Exact(b)) => Numeric::Exact(a * b),
+            _ => Numeric::Float(self.to_f64() * other.to_f64()),
+        }
+    }
+
+    pub fn div(&self, other: &Numeric) -> Result<Numeric, RewriteError> {
+        match (self, other) {
+            (Numeric::Exact(_), Numeric::Exact(b)) if b.is_zero() => {
+                Err(RewriteError::DivisionByZero)
+            }
+            (Numeric::Exact(a), Numeric::Exact(b)) => Ok(Numeric::Exact(a / b)),
+            _ => Ok(Numeric::Float(self.to_f64() / other.to_f64())),
+        }
+    }
+
+    pub fn pow(&self, exponent: &Numeric) -> Result<Numeric, RewriteError> {
+        match (self, exponent) {
+            (Numeric::Exact(base), Numeric::Exact(exp)) if exp.is_integer() => {
+                let exp = exp.to_integer();
+                // Bases that never grow take any exponent, even one that does
+                // not fit an `i32`.
+                if base.is_zero() {
+                    return match exp.sign() {
+                        Sign::Minus => Err(RewriteError::NegativeExponent),
+                        Sign::NoSign => Ok(Numeric::one()),
+                        Sign::Plus => Ok(Numeric::zero()),
+                    };
+                }
+                if base.abs().is_one() {
+                    let odd = !(&exp % 2u32).is_zero();
+                    return Ok(if base.is_negative() && odd {
+                        Numeric::Exact(-BigRational::one())
+                    } else {
+                        Numeric::one()
+                    });
+                }
+                let exp = exp.to_i32().ok_or(RewriteError::Overflow)?;
+                let bits = base.numer().bits().max(base.denom().bits());
+                if u64::from(exp.unsigned_abs()) * bits > MAX_POW_BITS {
+                    return Err(RewriteError::Overflow);
+                }
+                Ok(Numeric::Exact(Pow::pow(base, exp)))
+            }
+            _ => Ok(Numeric::Float(self.to_f64().powf(exponent.to_f64()))),
+        }
+    }
+
+    /// `self / other` rounded down.
+    pub fn div_floor(&self, other: &Numeric) -> Result<Numeric, RewriteError> {
+        match self.div(other)? {
+            Numeric::Exact(quotient) => Ok(Numeric::Exact(quotient.floor())),
+            Numeric::Float(quotient) => Ok(Numeric::Float(quotient.floor())),
+        }
+    }
+
+    /// The remainder of `div_floor`, which has the sign of `other`.
+    pub fn modulo(&self, other: &Numeric) -> Result<Numeric, RewriteError> {
+        Ok(self.sub(&other.mul(&self.div_floor(other)?)))
+    }
+
+    pub fn abs(&self) -> Numeric {
+        match self {
+            Numeric::Exact(value) => Numeric::Exact(value.abs()),
+            Numeric::Float(value) => Numeric::Float(value.abs()),
+        }
+    }
+
+    /// The non-negative greatest common divisor of two integers.
+    pub fn gcd(&self, other: &Numeric) -> Result<Numeric, RewriteError> {
+        let (mut a, mut b) = (
+            self.to_integer("gcd")?.abs(),
+            other.to_integer("gcd")?.abs(),
+        );
+        while !b.is_zero() {
+            let remainder = &a % &b;
+            a = b;
+            b = remainder;
+        }
+        Ok(Numeric::Exact(BigRational::from_integer(a)))
+    }
+
+    /// The non-negative least common multiple of two integers, zero if
+    /// either is zero.
+    pub fn lcm(&self, other: &Numeric) -> Result<Numeric, RewriteError> {
+        let gcd = self.gcd(other)?;
+        if gcd == Numeric::zero() {
+            return Ok(gcd);
+        }
+        self.mul(other).abs().div(&gcd)
+    }
+
+    fn to_integer(&self, operation: &str) -> Result<BigInt, RewriteError> {
+        match self {
+            Numeric::Exact(value) if value.is_integer() => Ok(value.to_integer()),
+            _ => Err(RewriteError::TypeMismatch(format!(
+                "{} needs integer operands",
+                operation
+            ))),
+        }
+    }
+
+    /// Zero is false, everything else, including NaN, is true.
+    pub fn is_true(&self) -> bool {
+        match self {
+            Numeric::Exact(value) => !value.is_zero(),
+            Numeric::Float(value) => *value != 0.0,
+        }
+    }
+
+    pub fn from_bool(value: bool) -> Numeric {
+        if value {
+            Numeric::one()
+        } else {
+            Numeric::zero()
+        }
+    }
+
+    /// Orders exact values exactly and everything else as `f64`, so
+    /// comparisons involving NaN have no ordering.
+    pub fn partial_cmp(&self, other: &Numeric) -> Option<Ordering> {
+        match (self, other) {
+            (Numeric::Exact(a), Numeric::Exact(b)) => Some(a.cmp(b)),
+            _ => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+
+    /// Whether `self op other` holds. NaN is unordered, so only `!=` holds
+    /// for it.
+    pub fn compare(&self, op: CompareOp, other: &Numeric) -> bool {
+        match (op, self.partial_cmp(other)) {
+            (CompareOp::NotEqual, ordering) => ordering != Some(Ordering::Equal),
+            (_, None) => false,
+            (CompareOp::Equal, Some(ordering)) => ordering.is_eq(),
+            (CompareOp::Less, Some(ordering)) => ordering.is_lt(),
+            (CompareOp::LessEqual, Some(ordering)) => ordering.is_le(),
+            (CompareOp::Greater, Some(ordering)) => ordering.is_gt(),
+            (CompareOp::GreaterEqual, Some(ordering)) => ordering.is_ge(),
+        }
+    }
+
+    pub fn neg(&self) -> Numeric {
+        match self {
+            Numeric::Exact(value) => Numeric::Exact(-value),
+            Numeric::Float(value) => Numeric::Float(-value),
+        }
+    }
+}
+
+impl Pool {
+    /// Interns a float by its bit pattern. All NaNs share one id so that
+    /// `expr_eq` is reflexive on them, while `-0.0` and `0.0` stay distinct.
+    pub fn intern_float(&mut self, value: f64) -> FloatId {
+        let bits = if value.is_nan() {
+            f64::NAN.to_bits()
+        } else {
+            value.to_bits()
+        };
+
+        if self.float_map.len() != self.floats.len() {
+            self.float_map = (0..self.floats.len())
+                .map(|idx| (self.floats[idx], FloatId::new(idx)))
+                .collect();
+        }
+        if let Some(&id) = self.float_map.get(&bits) {
+            id
+        } else {
+            let id = FloatId::new(self.floats.len());
+            self.floats.push(bits);
+            self.float_map.insert(bits, id);
+            id
+        }
+    }
+
+    pub fn float_value(&self, id: FloatId) -> f64 {
+        f64::from_bits(self.floats[id.0])
+    }
+
+    /// Returns the value of a numeric literal node.
+    pub fn numeric_value(&self, expr_id: ExprId) -> Option<Numeric> {
+        match self.exprs.get(expr_id.0)? {
+            ExprNode::Number(n) => {
+                Some(Numeric::Exact(BigRational::from_integer(self[*n].clone())))
+            }
+            ExprNode::Rational(r) => Some(Numeric::Exact(self[*r].clone())),
+            ExprNode::Float(f) => Some(Numeric::Float(self.float_value(*f))),
+            _ => None,
+        }
+    }
+
+    pub fn exact_value(&self, number: ExactNumber) -> Numeric {
+        match number {
+            ExactNumber::Integer(n) => Numeric::Exact(BigRational::from_integer(self[n].clone())),
+            ExactNumber::Rational(r) => Numeric::Exact(self[r].clone()),
+        }
+    }
+
+    /// Returns the value of a numeric literal action node.
+    pub fn action_numeric_value(&self, action_id: ActionId) -> Option<Numeric> {
+        match self.actions.get(action_id.0)? {
+            Action::Number(n) => Some(Numeric::Exact(BigRational::from_integer(self[*n].clone()))),
+            Action::Rational(r) => Some(Numeric::Exact(self[*r].clone())),
+            Action::Float(f) => Some(Numeric::Float(self.float_value(*f))),
+            _ => None,
+        }
+    }
+
+    pub fn intern_numeric(&mut self, value: Numeric) -> ExprNode {
+        match value {
+            Numeric::Exact(value) => self.intern_exact(value).into(),
+            Numeric::Float(value) => ExprNode::Float(self.intern_float(value)),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::ast::*;
+use crate::idx::*;
+use crate::pool::Pool;
+
+/// Precedence of the built-in binary operators. Unary `-` and `+` bind
+/// tighter than all of them.
+pub const BUILTIN_OPERATORS: [(&str, Function, Fixity, u8); 5] = [
+    ("+", Function::Add, Fixity::InfixLeft, 6),
+    ("-", Function::Subtract, Fixity::InfixLeft, 6),
+    ("*", Function::Multiply, Fixity::InfixLeft, 7),
+    ("/", Function::Divide, Fixity::InfixLeft, 7),
+    ("^", Function::Power, Fixity::InfixRight, 8),
+];
+
+impl Pool {
+    /// Declares `symbol` as an operator. A later declaration of the same
+    /// symbol and kind (infix or prefix) overrides an earlier one.
+    pub fn declare_operator(&mut self, symbol: &str, fixity: Fixity, precedence: u8) -> FunctionId {
+        let name = self.intern_string(symbol.to_string());
+        let function = self.intern_function(Function::Custom(name));
+        self.operators.push(OperatorDecl {
+            symbol: symbol.to_string(),
+            fixity,
+            precedence,
+            function,
+        });
+        function
+    }
+
+    /// Looks up a binary operator, built-in or declared.
+    pub fn infix_operator(&mut self, symbol: &str) -> Option<(FunctionId, Fixity, u8)> {
+        if let Some(&(_, function, fixity, precedence)) = BUILTIN_OPERATORS
+            .iter()
+            .find(|(builtin, ..)| *builtin == symbol)
+        {
+            return Some((self.intern_function(function), fixity, precedence));
+        }
+        self.operators
+            .iter()
+            .rev()
+            .find(|op| op.symbol == symbol && op.fixity != Fixity::Prefix)
+            .map(|op| (op.function, op.fixity, op.precedence))
+    }
+
+    /// Looks up a declared prefix operator.
+    pub fn prefix_operator(&self, symbol: &str) -> Option<(FunctionId, u8)> {
+        self.operators
+            .iter()
+            .rev()
+            .find(|op| op.symbol == symbol && op.fixity == Fixity::Prefix)
+            .map(|op| (op.function, op.precedence))
+    }
+
+    /// The declared operator that prints a call of `function` with `arity`
+    /// arguments, if any.
+    pub fn operator_for_call(&self, function: FunctionId, arity: usize) -> Option<&OperatorDecl> {
+        self.operators.iter().rev().find(|op| {
+            op.function == function
+                && match arity {
+                    1 => op.fixity == Fixity::Prefix,
+                    2 => op.fixity != Fixity::Prefix,
+                    _ => false,
+                }
+        })
+    }
+}
@@ -1,115 +1,28 @@
-use crate::{Action, ActionId, Function, Location, Pool};
+use crate::children::Children;
+use crate::{Action, ActionId, Function, FunctionId, Location, Pool};
 use pest::Parser;
 use pest::iterators::Pair;
 
 use crate::parser::action_parser::{ActionParser, Rule};
-use crate::parser::compute::parse_compute_expr;
+use crate::parser::compute::parse_compute_pair;
+use crate::parser::error::{ParseError, first_pair, next_child};
+use crate::parser::operators::parse_operator_chain;
+use crate::parser::{parse_float_literal, parse_integer_literal, parse_rational_literal};
 
-pub fn parse_action(input: &str, pool: &mut Pool) -> Result<ActionId, String> {
-    let pairs =
-        ActionParser::parse(Rule::action, input).map_err(|e| format!("Parse error: {}", e))?;
+pub fn parse_action(input: &str, pool: &mut Pool) -> Result<ActionId, ParseError> {
+    let pairs = ActionParser::parse(Rule::action_input, input)
+        .map_err(|e| ParseError::from_pest(e, input))?;
 
-    let pair = pairs.into_iter().next().unwrap();
+    let pair = first_pair(pairs, input)?;
     parse_action_pair(pair, pool)
 }
 
-pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId, String> {
+pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId, ParseError> {
     let span = pair.as_span();
     let location = Location::new(span.start(), span.end());
 
     match pair.as_rule() {
-        Rule::action => parse_action_pair(pair.into_inner().next().unwrap(), pool),
-        Rule::action_sum => {
-            let mut inner = pair.into_inner();
-            let mut action = parse_action_pair(inner.next().unwrap(), pool)?;
-
-            while let Some(op) = inner.next() {
-                let right = parse_action_pair(inner.next().unwrap(), pool)?;
-                let func = match op.as_str() {
-                    "+" => Function::Add,
-                    "-" => Function::Subtract,
-                    _ => return Err(format!("Unknown operator: {}", op.as_str())),
-                };
-                let fun_id = pool.intern_function(func);
-
-                let new_node_pos = pool.actions.len();
-                let first_child_pos = if action.0 < right.0 {
-                    action.0
-                } else {
-                    right.0
-                };
-                let last = new_node_pos - first_child_pos;
-
-                action = pool.add_action_with_location(
-                    Action::Call {
-                        fun: fun_id,
-                        last,
-                        arity: 2,
-                    },
-                    location.clone(),
-                );
-            }
-            Ok(action)
-        }
-        Rule::action_product => {
-            let mut inner = pair.into_inner();
-            let mut action = parse_action_pair(inner.next().unwrap(), pool)?;
-
-            while let Some(op) = inner.next() {
-                let right = parse_action_pair(inner.next().unwrap(), pool)?;
-                let func = match op.as_str() {
-                    "*" => Function::Multiply,
-                    "/" => Function::Divide,
-                    _ => return Err(format!("Unknown operator: {}", op.as_str())),
-                };
-                let fun_id = pool.intern_function(func);
-
-                let new_node_pos = pool.actions.len();
-                let first_child_pos = if action.0 < right.0 {
-                    action.0
-                } else {
-                    right.0
-                };
-                let last = new_node_pos - first_child_pos;
-
-                action = pool.add_action_with_location(
-                    Action::Call {
-                        fun: fun_id,
-                        last,
-                        arity: 2,
-                    },
-                    location.clone(),
-                );
-            }
-            Ok(action)
-        }
-        Rule::action_power => {
-            let mut inner = pair.into_inner();
-            let mut action = parse_action_pair(inner.next().unwrap(), pool)?;
-
-            for right_pair in inner {
-                let right = parse_action_pair(right_pair, pool)?;
-                let fun_id = pool.intern_function(Function::Power);
-
-                let new_node_pos = pool.actions.len();
-                let first_child_pos = if action.0 < right.0 {
-                    action.0
-                } else {
-                    right.0
-                };
-                let last = new_node_pos - first_child_pos;
-
-                action = pool.add_action_with_location(
-                    Action::Call {
-                        fun: fun_id,
-                        last,
-                        arity: 2,
-                    },
-                    location.clone(),
-                );
-            }
-            Ok(action)
-        }
+        Rule::action => parse_operator_chain(pair, pool, parse_action_pair, apply_action_function),
         Rule::action_value => {
             let inner = pair.into_inner();
             let mut ops = Vec::new();
@@ -129,7 +42,10 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
                 }
             }
 
-            let mut action = parse_action_pair(atom_pair.unwrap(), pool)?;
+            let mut action = parse_action_pair(
+                atom_pair.ok_or_else(|| ParseError::malformed(&span, "missing operand"))?,
+                pool,
+            )?;
 
             for func in ops.into_iter().rev() {
                 let fun_id = pool.intern_function(func);
@@ -137,7 +53,7 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
                 action = pool.add_action_with_location(
                     Action::Call {
                         fun: fun_id,
-                        last: pool.actions.len() - action.0,
+                        last: pool.actions.len() - (action.0 + 1 - pool.length(action)),
                         arity: 1,
                     },
                     location.clone(),
@@ -147,20 +63,48 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
             Ok(action)
         }
         Rule::number => {
-            let num = pair
-                .as_str()
-                .parse::<i32>()
-                .map_err(|_| format!("Invalid number: {}", pair.as_str()))?;
+            let num = parse_integer_literal(&pair)?;
+            let num = pool.intern_number(num);
             Ok(pool.add_action_with_location(Action::Number(num), location))
         }
+        Rule::float => {
+            let value = parse_float_literal(&pair)?;
+            let float = pool.intern_float(value);
+            Ok(pool.add_action_with_location(Action::Float(float), location))
+        }
+        Rule::rational => {
+            let value = parse_rational_literal(&pair)?;
+            let number = pool.intern_exact(value);
+            Ok(pool.add_action_with_location(number.into(), location))
+        }
         Rule::variable => {
             let var_name = pair.as_str().to_string();
             let var_id = pool.intern_string(var_name);
             Ok(pool.add_action_with_location(Action::Variable(var_id), location))
         }
+        Rule::splice_variable => {
+            let name = pair.as_str().trim_end_matches("...");
+            let var_id = pool.intern_string(name.to_string());
+            Ok(pool.add_action_with_location(Action::Splice(var_id), location))
+        }
+        Rule::fresh_variable => {
+            let base = next_child(&mut pair.into_inner(), &span)?
+                .as_str()
+                .to_string();
+            let base_id = pool.intern_string(base);
+            Ok(pool.add_action_with_location(Action::Fresh(base_id), location))
+        }
+        Rule::plug_context => {
+            let mut inner = pair.into_inner();
+            let var = next_child(&mut inner, &span)?.as_str().to_string();
+            let var = pool.intern_string(var);
+            let hole = parse_action_pair(next_child(&mut inner, &span)?, pool)?;
+            let last = pool.length(hole);
+            Ok(pool.add_action_with_location(Action::Plug { var, last }, location))
+        }
         Rule::action_function_call => {
             let mut inner = pair.into_inner();
-            let func_name = inner.next().unwrap().as_str().to_string();
+            let func_name = next_child(&mut inner, &span)?.as_str().to_string();
             let name_id = pool.intern_string(func_name);
             let func_id = pool.intern_function(crate::Function::Custom(name_id));
 
@@ -181,11 +125,11 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
                     location,
                 ))
             } else {
-                let args_start = args.last().copied().unwrap();
+                let args_start = args[0].0 + 1 - pool.length(args[0]);
                 Ok(pool.add_action_with_location(
                     Action::Call {
                         fun: func_id,
-                        last: pool.actions.len() - args_start.0,
+                        last: pool.actions.len() - args_start,
                         arity: args.len(),
                     },
                     location,
@@ -194,7 +138,7 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
         }
         Rule::action_struct_expr => {
             let mut inner = pair.into_inner();
-            let struct_name = inner.next().unwrap().as_str().to_string();
+            let struct_name = next_child(&mut inner, &span)?.as_str().to_string();
             let name_id = pool.intern_string(struct_name);
 
             let mut fields = Vec::new();
@@ -214,11 +158,11 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
                     location,
                 ))
             } else {
-                let fields_start = fields.last().copied().unwrap();
+                let fields_start = fields[0].0 + 1 - pool.length(fields[0]);
                 Ok(pool.add_action_with_location(
                     Action::Struct {
                         name: name_id,
-                        last: pool.actions.len() - fields_start.0,
+                        last: pool.actions.len() - fields_start,
                         arity: fields.len(),
                     },
                     location,
@@ -227,7 +171,7 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
         }
         Rule::var_action_function_call => {
             let mut inner = pair.into_inner();
-            let var_name = inner.next().unwrap().as_str()[1..].to_string();
+            let var_name = next_child(&mut inner, &span)?.as_str()[1..].to_string();
             let var_id = pool.intern_string(var_name);
 
             let mut args = Vec::new();
@@ -247,11 +191,11 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
                     location,
                 ))
             } else {
-                let args_start = args.last().copied().unwrap();
+                let args_start = args[0].0 + 1 - pool.length(args[0]);
                 Ok(pool.add_action_with_location(
                     Action::VarCallName {
                         var: var_id,
-                        last: pool.actions.len() - args_start.0,
+                        last: pool.actions.len() - args_start,
                         arity: args.len(),
                     },
                     location,
@@ -260,7 +204,7 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
         }
         Rule::var_action_struct_expr => {
             let mut inner = pair.into_inner();
-            let var_name = inner.next().unwrap().as_str()[1..].to_string();
+            let var_name = next_child(&mut inner, &span)?.as_str()[1..].to_string();
             let var_id = pool.intern_string(var_name);
 
             let mut fields = Vec::new();
@@ -280,11 +224,11 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
                     location,
                 ))
             } else {
-                let fields_start = fields.last().copied().unwrap();
+                let fields_start = fields[0].0 + 1 - pool.length(fields[0]);
                 Ok(pool.add_action_with_location(
                     Action::VarStructName {
                         var: var_id,
-                        last: pool.actions.len() - fields_start.0,
+                        last: pool.actions.len() - fields_start,
                         arity: fields.len(),
                     },
                     location,
@@ -292,10 +236,30 @@ pub fn parse_action_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
             }
         }
         Rule::compute_expr => {
-            let mut inner = pair.into_inner();
-            let compute_inner = inner.next().unwrap();
-            parse_compute_expr(compute_inner.as_str(), pool)
+            let compute_inner = next_child(&mut pair.into_inner(), &span)?;
+            parse_compute_pair(compute_inner, pool)
         }
-        _ => Err(format!("Unexpected action rule: {:?}", pair.as_rule())),
+        _ => Err(ParseError::unexpected_rule(&pair)),
     }
 }
+
+fn apply_action_function(
+    pool: &mut Pool,
+    fun: FunctionId,
+    args: &[ActionId],
+    location: Location,
+) -> ActionId {
+    let first_child_pos = args
+        .iter()
+        .map(|arg| arg.0 + 1 - pool.length(*arg))
+        .min()
+        .unwrap_or(pool.actions.len());
+    pool.add_action_with_location(
+        Action::Call {
+            fun,
+            last: pool.actions.len() - first_child_pos,
+            arity: args.len(),
+        },
+        location,
+    )
+}
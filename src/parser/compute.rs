@@ -1,111 +1,98 @@
-use crate::{Action, ActionId, ComputeOp, Location, Pool};
+use crate::children::Children;
+use crate::{Action, ActionId, CompareOp, ComputeOp, Location, Pool};
 use pest::Parser;
 use pest::iterators::Pair;
 
 use crate::parser::compute_parser::{ComputeParser, Rule};
+use crate::parser::error::{ParseError, first_pair, next_child};
+use crate::parser::{parse_float_literal, parse_integer_literal, parse_rational_literal};
 
-pub fn parse_compute_expr(input: &str, pool: &mut Pool) -> Result<ActionId, String> {
-    let pairs = ComputeParser::parse(Rule::compute_inner, input)
-        .map_err(|e| format!("Parse error: {}", e))?;
+pub fn parse_compute_expr(input: &str, pool: &mut Pool) -> Result<ActionId, ParseError> {
+    let pairs = ComputeParser::parse(Rule::compute_input, input)
+        .map_err(|e| ParseError::from_pest(e, input))?;
 
-    let pair = pairs.into_iter().next().unwrap();
+    let pair = first_pair(pairs, input)?;
     parse_compute_pair(pair, pool)
 }
 
-pub fn parse_compute_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId, String> {
+pub fn parse_compute_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId, ParseError> {
     let span = pair.as_span();
     let location = Location::new(span.start(), span.end());
 
     match pair.as_rule() {
-        Rule::compute_inner => parse_compute_pair(pair.into_inner().next().unwrap(), pool),
-        Rule::compute_sum => {
+        Rule::compute_inner => parse_compute_pair(next_child(&mut pair.into_inner(), &span)?, pool),
+        Rule::compute_comparison | Rule::compute_sum | Rule::compute_product => {
             let mut inner = pair.into_inner();
-            let mut action = parse_compute_pair(inner.next().unwrap(), pool)?;
+            let mut action = parse_compute_pair(next_child(&mut inner, &span)?, pool)?;
 
             while let Some(op) = inner.next() {
-                let right = parse_compute_pair(inner.next().unwrap(), pool)?;
+                let right = parse_compute_pair(next_child(&mut inner, &span)?, pool)?;
                 let compute_op = match op.as_str() {
                     "+" => ComputeOp::Add,
                     "-" => ComputeOp::Subtract,
-                    _ => return Err(format!("Unknown compute operator: {}", op.as_str())),
-                };
-
-                let new_node_pos = pool.actions.len();
-                let first_child_pos = if action.0 < right.0 {
-                    action.0
-                } else {
-                    right.0
-                };
-                let last = new_node_pos - first_child_pos;
-
-                action = pool.add_action_with_location(
-                    Action::Compute {
-                        op: compute_op,
-                        last,
-                        arity: 2,
-                    },
-                    location.clone(),
-                );
-            }
-            Ok(action)
-        }
-        Rule::compute_product => {
-            let mut inner = pair.into_inner();
-            let mut action = parse_compute_pair(inner.next().unwrap(), pool)?;
-
-            while let Some(op) = inner.next() {
-                let right = parse_compute_pair(inner.next().unwrap(), pool)?;
-                let compute_op = match op.as_str() {
                     "*" => ComputeOp::Multiply,
                     "/" => ComputeOp::Divide,
-                    _ => return Err(format!("Unknown compute operator: {}", op.as_str())),
-                };
-
-                let new_node_pos = pool.actions.len();
-                let first_child_pos = if action.0 < right.0 {
-                    action.0
-                } else {
-                    right.0
+                    "%" => ComputeOp::Modulo,
+                    "==" => ComputeOp::Compare(CompareOp::Equal),
+                    "!=" => ComputeOp::Compare(CompareOp::NotEqual),
+                    "<" => ComputeOp::Compare(CompareOp::Less),
+                    "<=" => ComputeOp::Compare(CompareOp::LessEqual),
+                    ">" => ComputeOp::Compare(CompareOp::Greater),
+                    ">=" => ComputeOp::Compare(CompareOp::GreaterEqual),
+                    _ => {
+                        return Err(ParseError::malformed(
+                            &op.as_span(),
+                            format!("unknown compute operator {}", op.as_str()),
+                        ));
+                    }
                 };
-                let last = new_node_pos - first_child_pos;
-
-                action = pool.add_action_with_location(
-                    Action::Compute {
-                        op: compute_op,
-                        last,
-                        arity: 2,
-                    },
-                    location.clone(),
-                );
+                action = add_compute_node(pool, compute_op, &[action, right], location.clone());
             }
             Ok(action)
         }
         Rule::compute_power => {
             let mut inner = pair.into_inner();
-            let mut action = parse_compute_pair(inner.next().unwrap(), pool)?;
+            let mut action = parse_compute_pair(next_child(&mut inner, &span)?, pool)?;
 
             for right_pair in inner {
                 let right = parse_compute_pair(right_pair, pool)?;
-
-                let new_node_pos = pool.actions.len();
-                let first_child_pos = if action.0 < right.0 {
-                    action.0
-                } else {
-                    right.0
-                };
-                let last = new_node_pos - first_child_pos;
-
-                action = pool.add_action_with_location(
-                    Action::Compute {
-                        op: ComputeOp::Power,
-                        last,
-                        arity: 2,
-                    },
-                    location.clone(),
-                );
+                action =
+                    add_compute_node(pool, ComputeOp::Power, &[action, right], location.clone());
             }
             Ok(action)
         }
+        Rule::compute_call => {
+            let mut inner = pair.into_inner();
+            let name = next_child(&mut inner, &span)?;
+            let op = ComputeOp::from_function_name(name.as_str()).ok_or_else(|| {
+                ParseError::malformed(
+                    &name.as_span(),
+                    format!("unknown compute function {}", name.as_str()),
+                )
+            })?;
+            let args = inner
+                .map(|arg| parse_compute_pair(arg, pool))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let expected = match op {
+                ComputeOp::Abs => Some(1),
+                ComputeOp::Min | ComputeOp::Max => None,
+                ComputeOp::If => Some(3),
+                _ => Some(2),
+            };
+            if expected.is_some_and(|expected| expected != args.len()) {
+                return Err(ParseError::malformed(
+                    &span,
+                    format!(
+                        "{} takes {} arguments, not {}",
+                        name.as_str(),
+                        expected.unwrap_or_default(),
+                        args.len()
+                    ),
+                ));
+            }
+            Ok(add_compute_node(pool, op, &args, location))
+        }
         Rule::compute_value => {
             let inner = pair.into_inner();
             let mut ops = Vec::new();
@@ -125,33 +112,59 @@ pub fn parse_compute_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ActionId,
                 }
             }
 
-            let mut action = parse_compute_pair(atom_pair.unwrap(), pool)?;
+            let mut action = parse_compute_pair(
+                atom_pair.ok_or_else(|| ParseError::malformed(&span, "missing operand"))?,
+                pool,
+            )?;
 
             for compute_op in ops.into_iter().rev() {
-                action = pool.add_action_with_location(
-                    Action::Compute {
-                        op: compute_op,
-                        last: pool.actions.len() - action.0,
-                        arity: 1,
-                    },
-                    location.clone(),
-                );
+                action = add_compute_node(pool, compute_op, &[action], location.clone());
             }
 
             Ok(action)
         }
         Rule::number => {
-            let num = pair
-                .as_str()
-                .parse::<i32>()
-                .map_err(|_| format!("Invalid number: {}", pair.as_str()))?;
+            let num = parse_integer_literal(&pair)?;
+            let num = pool.intern_number(num);
             Ok(pool.add_action_with_location(Action::Number(num), location))
         }
+        Rule::float => {
+            let value = parse_float_literal(&pair)?;
+            let float = pool.intern_float(value);
+            Ok(pool.add_action_with_location(Action::Float(float), location))
+        }
+        Rule::rational => {
+            let value = parse_rational_literal(&pair)?;
+            let number = pool.intern_exact(value);
+            Ok(pool.add_action_with_location(number.into(), location))
+        }
         Rule::variable => {
             let var_name = pair.as_str().to_string();
             let var_id = pool.intern_string(var_name);
             Ok(pool.add_action_with_location(Action::Variable(var_id), location))
         }
-        _ => Err(format!("Unexpected compute rule: {:?}", pair.as_rule())),
+        _ => Err(ParseError::unexpected_rule(&pair)),
     }
 }
+
+/// Appends a compute node over `args`, which must be the most recently added
+/// action subtrees, in order.
+fn add_compute_node(
+    pool: &mut Pool,
+    op: ComputeOp,
+    args: &[ActionId],
+    location: Location,
+) -> ActionId {
+    let last = match args.first() {
+        Some(&first) => pool.actions.len() - (first.0 + 1 - pool.length(first)),
+        None => 0,
+    };
+    pool.add_action_with_location(
+        Action::Compute {
+            op,
+            last,
+            arity: args.len(),
+        },
+        location,
+    )
+}
@@ -0,0 +1,334 @@
+use pest::RuleType;
+use pest::error::{Error as PestError, ErrorVariant, InputLocation};
+use pest::iterators::{Pair, Pairs};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Byte range of a diagnostic together with its 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceSpan {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceSpan {
+    pub fn new(source: &str, start: usize, end: usize) -> Self {
+        let start = start.min(source.len());
+        let end = end.clamp(start, source.len());
+        let before = &source[..start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        let column = source[line_start..start].chars().count() + 1;
+        Self {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    fn from_pest(span: &pest::Span) -> Self {
+        Self::new(span.get_input(), span.start(), span.end())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ParseError {
+    /// The input does not match the grammar.
+    Syntax {
+        span: SourceSpan,
+        expected: Vec<String>,
+        snippet: String,
+    },
+    /// A literal matched the grammar but has no value, e.g. `1//0`.
+    InvalidLiteral {
+        span: SourceSpan,
+        message: String,
+        snippet: String,
+    },
+    /// The parse tree does not have the shape the builder expects.
+    MalformedTree {
+        span: SourceSpan,
+        message: String,
+        snippet: String,
+    },
+    /// A `use` or `extends` that names no ruleset or would form a cycle.
+    Import {
+        span: SourceSpan,
+        message: String,
+        snippet: String,
+    },
+    /// One side of a `<=>` rule captures a variable the other side does not.
+    UnboundVariable {
+        span: SourceSpan,
+        message: String,
+        snippet: String,
+    },
+    /// An error inside a named rule of a ruleset.
+    InRule {
+        rule_name: String,
+        error: Box<ParseError>,
+    },
+}
+
+impl ParseError {
+    pub fn from_pest<R: RuleType>(error: PestError<R>, source: &str) -> Self {
+        let (start, end) = match error.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span((start, end)) => (start, end),
+        };
+        let span = SourceSpan::new(source, start, end);
+        let expected = match &error.variant {
+            ErrorVariant::ParsingError { positives, .. } => {
+                let mut expected: Vec<_> =
+                    positives.iter().map(|rule| format!("{:?}", rule)).collect();
+                expected.dedup();
+                expected
+            }
+            ErrorVariant::CustomError { message } => vec![message.clone()],
+        };
+        let snippet = render_snippet(source, &span);
+        ParseError::Syntax {
+            span,
+            expected,
+            snippet,
+        }
+    }
+
+    pub fn expected_at(span: &pest::Span, expected: Vec<String>) -> Self {
+        let source_span = SourceSpan::from_pest(span);
+        ParseError::Syntax {
+            snippet: render_snippet(span.get_input(), &source_span),
+            span: source_span,
+            expected,
+        }
+    }
+
+    pub fn invalid_literal<R: RuleType>(pair: &Pair<R>, message: impl Into<String>) -> Self {
+        let span = pair.as_span();
+        let source_span = SourceSpan::from_pest(&span);
+        ParseError::InvalidLiteral {
+            snippet: render_snippet(span.get_input(), &source_span),
+            span: source_span,
+            message: message.into(),
+        }
+    }
+
+    pub fn malformed(span: &pest::Span, message: impl Into<String>) -> Self {
+        let source_span = SourceSpan::from_pest(span);
+        ParseError::MalformedTree {
+            snippet: render_snippet(span.get_input(), &source_span),
+            span: source_span,
+            message: message.into(),
+        }
+    }
+
+    pub fn import(span: &pest::Span, message: impl Into<String>) -> Self {
+        let source_span = SourceSpan::from_pest(span);
+        ParseError::Import {
+            snippet: render_snippet(span.get_input(), &source_span),
+            span: source_span,
+            message: message.into(),
+        }
+    }
+
+    pub fn unbound_variable(span: &pest::Span, message: impl Into<String>) -> Self {
+        let source_span = SourceSpan::from_pest(span);
+        ParseError::UnboundVariable {
+            snippet: render_snippet(span.get_input(), &source_span),
+            span: source_span,
+            message: message.into(),
+        }
+    }
+
+    pub fn unexpected_rule<R: RuleType>(pair: &Pair<R>) -> Self {
+        Self::malformed(
+            &pair.as_span(),
+            format!("unexpected rule {:?}", pair.as_rule()),
+        )
+    }
+
+    pub fn in_rule(self, rule_name: impl Into<String>) -> Self {
+        ParseError::InRule {
+            rule_name: rule_name.into(),
+            error: Box::new(self),
+        }
+    }
+
+    /// Moves an error reported for a fragment starting at byte `base` of
+    /// `source` so that its span and snippet refer to `source` itself.
+    pub fn relocate(self, base: usize, source: &str) -> Self {
+        match self {
+            ParseError::Syntax { span, expected, .. } => {
+                let span = SourceSpan::new(source, base + span.start, base + span.end);
+                ParseError::Syntax {
+                    snippet: render_snippet(source, &span),
+                    span,
+                    expected,
+                }
+            }
+            ParseError::InvalidLiteral { span, message, .. } => {
+                let span = SourceSpan::new(source, base + span.start, base + span.end);
+                ParseError::InvalidLiteral {
+                    snippet: render_snippet(source, &span),
+                    span,
+                    message,
+                }
+            }
+            ParseError::MalformedTree { span, message, .. } => {
+                let span = SourceSpan::new(source, base + span.start, base + span.end);
+                ParseError::MalformedTree {
+                    snippet: render_snippet(source, &span),
+                    span,
+                    message,
+                }
+            }
+            ParseError::Import { span, message, .. } => {
+                let span = SourceSpan::new(source, base + span.start, base + span.end);
+                ParseError::Import {
+                    snippet: render_snippet(source, &span),
+                    span,
+                    message,
+                }
+            }
+            ParseError::UnboundVariable { span, message, .. } => {
+                let span = SourceSpan::new(source, base + span.start, base + span.end);
+                ParseError::UnboundVariable {
+                    snippet: render_snippet(source, &span),
+                    span,
+                    message,
+                }
+            }
+            ParseError::InRule { rule_name, error } => ParseError::InRule {
+                rule_name,
+                error: Box::new(error.relocate(base, source)),
+            },
+        }
+    }
+
+    pub fn span(&self) -> &SourceSpan {
+        match self {
+            ParseError::Syntax { span, .. }
+            | ParseError::InvalidLiteral { span, .. }
+            | ParseError::MalformedTree { span, .. }
+            | ParseError::Import { span, .. }
+            | ParseError::UnboundVariable { span, .. } => span,
+            ParseError::InRule { error, .. } => error.span(),
+        }
+    }
+
+    pub fn expected(&self) -> &[String] {
+        match self {
+            ParseError::Syntax { expected, .. } => expected,
+            ParseError::InRule { error, .. } => error.expected(),
+            _ => &[],
+        }
+    }
+
+    /// Name of the ruleset rule the error occurred in, if any.
+    pub fn rule_name(&self) -> Option<&str> {
+        match self {
+            ParseError::InRule { rule_name, .. } => Some(rule_name),
+            _ => None,
+        }
+    }
+
+    /// The offending source line with a caret under the error span.
+    pub fn snippet(&self) -> &str {
+        match self {
+            ParseError::Syntax { snippet, .. }
+            | ParseError::InvalidLiteral { snippet, .. }
+            | ParseError::MalformedTree { snippet, .. }
+            | ParseError::Import { snippet, .. }
+            | ParseError::UnboundVariable { snippet, .. } => snippet,
+            ParseError::InRule { error, .. } => error.snippet(),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::Syntax { expected, .. } if expected.is_empty() => {
+                "unexpected input".to_string()
+            }
+            ParseError::Syntax { expected, .. } => {
+                format!("expected {}", expected.join(", "))
+            }
+            ParseError::InvalidLiteral { message, .. }
+            | ParseError::MalformedTree { message, .. }
+            | ParseError::Import { message, .. }
+            | ParseError::UnboundVariable { message, .. } => message.clone(),
+            ParseError::InRule { rule_name, error } => {
+                format!("in rule '{}': {}", rule_name, error.message())
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let span = self.span();
+        write!(
+            f,
+            "{}:{}: {}\n{}",
+            span.line,
+            span.column,
+            self.message(),
+            self.snippet()
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Renders the line containing `span` followed by a caret line, e.g.
+///
+/// ```text
+/// 1 | f(x +)
+///   |     ^
+/// ```
+fn render_snippet(source: &str, span: &SourceSpan) -> String {
+    let line_text = source.lines().nth(span.line - 1).unwrap_or("");
+    let line_chars = line_text.chars().count();
+    let span_chars = source[span.start..span.end].chars().count();
+    let caret_len = span_chars
+        .min((line_chars + 1).saturating_sub(span.column))
+        .max(1);
+
+    let gutter = span.line.to_string();
+    format!(
+        "{} | {}\n{} | {}{}",
+        gutter,
+        line_text,
+        " ".repeat(gutter.len()),
+        " ".repeat(span.column - 1),
+        "^".repeat(caret_len)
+    )
+}
+
+/// Takes the next child of a pair, reporting a malformed tree instead of
+/// panicking if the grammar produced fewer children than expected.
+pub(crate) fn next_child<'i, R: RuleType>(
+    pairs: &mut Pairs<'i, R>,
+    parent: &pest::Span<'i>,
+) -> Result<Pair<'i, R>, ParseError> {
+    pairs
+        .next()
+        .ok_or_else(|| ParseError::malformed(parent, "missing child node"))
+}
+
+/// Takes the single top-level pair produced by a successful parse of `input`.
+pub(crate) fn first_pair<'i, R: RuleType>(
+    mut pairs: impl Iterator<Item = Pair<'i, R>>,
+    input: &'i str,
+) -> Result<Pair<'i, R>, ParseError> {
+    pairs.next().ok_or_else(|| {
+        let span = SourceSpan::new(input, 0, input.len());
+        ParseError::MalformedTree {
+            snippet: render_snippet(input, &span),
+            span,
+            message: "empty parse tree".to_string(),
+        }
+    })
+}
@@ -1,15 +1,18 @@
 use crate::children::Children;
-use crate::{ExprId, ExprNode, Function, Location, Pool, Provenance};
+use crate::{ExprId, ExprNode, Function, FunctionId, Location, Pool, Provenance};
 use pest::Parser;
 use pest::iterators::Pair;
 
+use crate::parser::error::{ParseError, first_pair, next_child};
 use crate::parser::expr_parser::{ExprParser, Rule};
+use crate::parser::operators::parse_operator_chain;
+use crate::parser::{parse_float_literal, parse_integer_literal, parse_rational_literal};
 
-pub fn parse_expression(input: &str, pool: &mut Pool) -> Result<ExprId, String> {
-    let pairs =
-        ExprParser::parse(Rule::expression, input).map_err(|e| format!("Parse error: {}", e))?;
+pub fn parse_expression(input: &str, pool: &mut Pool) -> Result<ExprId, ParseError> {
+    let pairs = ExprParser::parse(Rule::expression_input, input)
+        .map_err(|e| ParseError::from_pest(e, input))?;
 
-    let pair = pairs.into_iter().next().unwrap();
+    let pair = first_pair(pairs, input)?;
     let expr_id = parse_expr_pair(pair, pool)?;
 
     pool.mark_expr_end(expr_id);
@@ -17,115 +20,12 @@ pub fn parse_expression(input: &str, pool: &mut Pool) -> Result<ExprId, String>
     Ok(expr_id)
 }
 
-pub fn parse_expr_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ExprId, String> {
+pub fn parse_expr_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ExprId, ParseError> {
     let span = pair.as_span();
     let location = Location::new(span.start(), span.end());
 
     match pair.as_rule() {
-        Rule::expression => parse_expr_pair(pair.into_inner().next().unwrap(), pool),
-        Rule::sum => {
-            let mut inner = pair.into_inner();
-            let mut left = parse_expr_pair(inner.next().unwrap(), pool)?;
-
-            while let Some(op) = inner.next() {
-                let right = parse_expr_pair(inner.next().unwrap(), pool)?;
-                let func = match op.as_str() {
-                    "+" => Function::Add,
-                    "-" => Function::Subtract,
-                    _ => return Err(format!("Unknown operator: {}", op.as_str())),
-                };
-                let fun_id = pool.intern_function(func);
-
-                let new_node_pos = pool.exprs.len();
-
-                let left_start = left.0 + 1 - pool.length(left);
-                let right_start = right.0 + 1 - pool.length(right);
-
-                let first_child_pos = if left_start < right_start {
-                    left_start
-                } else {
-                    right_start
-                };
-                let last = new_node_pos - first_child_pos;
-
-                left = pool.add_expr_with_provenance(
-                    ExprNode::Call {
-                        fun: fun_id,
-                        last,
-                        arity: 2,
-                    },
-                    Provenance::Parsed(location.clone()),
-                );
-            }
-            Ok(left)
-        }
-        Rule::product => {
-            let mut inner = pair.into_inner();
-            let mut left = parse_expr_pair(inner.next().unwrap(), pool)?;
-
-            while let Some(op) = inner.next() {
-                let right = parse_expr_pair(inner.next().unwrap(), pool)?;
-                let func = match op.as_str() {
-                    "*" => Function::Multiply,
-                    "/" => Function::Divide,
-                    _ => return Err(format!("Unknown operator: {}", op.as_str())),
-                };
-                let fun_id = pool.intern_function(func);
-
-                let new_node_pos = pool.exprs.len();
-
-                let left_start = left.0 + 1 - pool.length(left);
-                let right_start = right.0 + 1 - pool.length(right);
-
-                let first_child_pos = if left_start < right_start {
-                    left_start
-                } else {
-                    right_start
-                };
-                let last = new_node_pos - first_child_pos;
-
-                left = pool.add_expr_with_provenance(
-                    ExprNode::Call {
-                        fun: fun_id,
-                        last,
-                        arity: 2,
-                    },
-                    Provenance::Parsed(location.clone()),
-                );
-            }
-            Ok(left)
-        }
-        Rule::power => {
-            let mut inner = pair.into_inner();
-            let mut left = parse_expr_pair(inner.next().unwrap(), pool)?;
-
-            for right_pair in inner {
-                let right = parse_expr_pair(right_pair, pool)?;
-                let fun_id = pool.intern_function(Function::Power);
-
-                let new_node_pos = pool.exprs.len();
-
-                let left_start = left.0 + 1 - pool.length(left);
-                let right_start = right.0 + 1 - pool.length(right);
-
-                let first_child_pos = if left_start < right_start {
-                    left_start
-                } else {
-                    right_start
-                };
-                let last = new_node_pos - first_child_pos;
-
-                left = pool.add_expr_with_provenance(
-                    ExprNode::Call {
-                        fun: fun_id,
-                        last,
-                        arity: 2,
-                    },
-                    Provenance::Parsed(location.clone()),
-                );
-            }
-            Ok(left)
-        }
+        Rule::expression => parse_operator_chain(pair, pool, parse_expr_pair, apply_expr_function),
         Rule::value => {
             let inner = pair.into_inner();
             let mut ops = Vec::new();
@@ -144,7 +44,10 @@ pub fn parse_expr_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ExprId, Stri
                     }
                 }
             }
-            let mut expr = parse_expr_pair(atom_pair.unwrap(), pool)?;
+            let mut expr = parse_expr_pair(
+                atom_pair.ok_or_else(|| ParseError::malformed(&span, "missing operand"))?,
+                pool,
+            )?;
 
             for func in ops.into_iter().rev() {
                 let fun_id = pool.intern_function(func);
@@ -152,7 +55,7 @@ pub fn parse_expr_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ExprId, Stri
                 expr = pool.add_expr_with_provenance(
                     ExprNode::Call {
                         fun: fun_id,
-                        last: pool.exprs.len() - expr.0,
+                        last: pool.exprs.len() - (expr.0 + 1 - pool.length(expr)),
                         arity: 1,
                     },
                     Provenance::Parsed(location.clone()),
@@ -162,12 +65,20 @@ pub fn parse_expr_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ExprId, Stri
             Ok(expr)
         }
         Rule::number => {
-            let num = pair
-                .as_str()
-                .parse::<i32>()
-                .map_err(|_| format!("Invalid number: {}", pair.as_str()))?;
+            let num = parse_integer_literal(&pair)?;
+            let num = pool.intern_number(num);
             Ok(pool.add_expr_with_provenance(ExprNode::Number(num), Provenance::Parsed(location)))
         }
+        Rule::float => {
+            let value = parse_float_literal(&pair)?;
+            let float = pool.intern_float(value);
+            Ok(pool.add_expr_with_provenance(ExprNode::Float(float), Provenance::Parsed(location)))
+        }
+        Rule::rational => {
+            let value = parse_rational_literal(&pair)?;
+            let number = pool.intern_exact(value);
+            Ok(pool.add_expr_with_provenance(number.into(), Provenance::Parsed(location)))
+        }
         Rule::variable => {
             let var_name = pair.as_str().to_string();
             let var_id = pool.intern_string(var_name);
@@ -176,7 +87,7 @@ pub fn parse_expr_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ExprId, Stri
         }
         Rule::function_call => {
             let mut inner = pair.into_inner();
-            let func_name = inner.next().unwrap().as_str().to_string();
+            let func_name = next_child(&mut inner, &span)?.as_str().to_string();
             let name_id = pool.intern_string(func_name);
             let func_id = pool.intern_function(crate::Function::Custom(name_id));
 
@@ -190,7 +101,7 @@ pub fn parse_expr_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ExprId, Stri
             let last_offset = if args.is_empty() {
                 0
             } else {
-                pool.exprs.len() - args[0].0
+                pool.exprs.len() - (args[0].0 + 1 - pool.length(args[0]))
             };
 
             Ok(pool.add_expr_with_provenance(
@@ -204,7 +115,7 @@ pub fn parse_expr_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ExprId, Stri
         }
         Rule::struct_expr => {
             let mut inner = pair.into_inner();
-            let struct_name = inner.next().unwrap().as_str().to_string();
+            let struct_name = next_child(&mut inner, &span)?.as_str().to_string();
             let name_id = pool.intern_string(struct_name);
 
             let mut fields = Vec::new();
@@ -217,7 +128,7 @@ pub fn parse_expr_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ExprId, Stri
             let last_offset = if fields.is_empty() {
                 0
             } else {
-                pool.exprs.len() - fields[0].0
+                pool.exprs.len() - (fields[0].0 + 1 - pool.length(fields[0]))
             };
 
             Ok(pool.add_expr_with_provenance(
@@ -229,6 +140,27 @@ pub fn parse_expr_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<ExprId, Stri
                 Provenance::Parsed(location),
             ))
         }
-        _ => Err(format!("Unexpected rule: {:?}", pair.as_rule())),
+        _ => Err(ParseError::unexpected_rule(&pair)),
     }
 }
+
+fn apply_expr_function(
+    pool: &mut Pool,
+    fun: FunctionId,
+    args: &[ExprId],
+    location: Location,
+) -> ExprId {
+    let first_child_pos = args
+        .iter()
+        .map(|arg| arg.0 + 1 - pool.length(*arg))
+        .min()
+        .unwrap_or(pool.exprs.len());
+    pool.add_expr_with_provenance(
+        ExprNode::Call {
+            fun,
+            last: pool.exprs.len() - first_child_pos,
+            arity: args.len(),
+        },
+        Provenance::Parsed(location),
+    )
+}
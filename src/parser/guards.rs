@@ -0,0 +1,138 @@
+use crate::children::Children;
+use crate::{CompareOp, Guard, GuardId, GuardPredicate, LogicOp, Pool};
+use pest::Parser;
+use pest::iterators::Pair;
+
+use crate::parser::compute::parse_compute_pair;
+use crate::parser::error::{ParseError, first_pair, next_child};
+use crate::parser::guard_parser::{GuardParser, Rule};
+
+pub fn parse_guard(input: &str, pool: &mut Pool) -> Result<GuardId, ParseError> {
+    let pairs = GuardParser::parse(Rule::guard_input, input)
+        .map_err(|e| ParseError::from_pest(e, input))?;
+
+    let pair = first_pair(pairs, input)?;
+    parse_guard_pair(pair, pool)
+}
+
+pub fn parse_guard_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<GuardId, ParseError> {
+    let span = pair.as_span();
+
+    match pair.as_rule() {
+        Rule::guard | Rule::guard_atom => {
+            parse_guard_pair(next_child(&mut pair.into_inner(), &span)?, pool)
+        }
+        Rule::guard_or | Rule::guard_and => {
+            let op = if pair.as_rule() == Rule::guard_or {
+                LogicOp::Or
+            } else {
+                LogicOp::And
+            };
+            let mut inner = pair.into_inner();
+            let mut guard = parse_guard_pair(next_child(&mut inner, &span)?, pool)?;
+
+            for right_pair in inner {
+                let right = parse_guard_pair(right_pair, pool)?;
+                guard = add_guard_node(pool, guard, Some(right), |last, arity| Guard::Logic {
+                    op,
+                    last,
+                    arity,
+                });
+            }
+            Ok(guard)
+        }
+        Rule::guard_not => {
+            let mut negations = 0;
+            let mut atom_pair = None;
+            for p in pair.into_inner() {
+                match p.as_rule() {
+                    Rule::not_op => negations += 1,
+                    _ => atom_pair = Some(p),
+                }
+            }
+
+            let mut guard = parse_guard_pair(
+                atom_pair.ok_or_else(|| ParseError::malformed(&span, "missing condition"))?,
+                pool,
+            )?;
+            for _ in 0..negations {
+                guard = add_guard_node(pool, guard, None, |last, arity| Guard::Logic {
+                    op: LogicOp::Not,
+                    last,
+                    arity,
+                });
+            }
+            Ok(guard)
+        }
+        Rule::guard_predicate => {
+            let mut inner = pair.into_inner();
+            let name = next_child(&mut inner, &span)?;
+            let predicate = match name.as_str() {
+                "is_number" => GuardPredicate::IsNumber,
+                "is_variable" => GuardPredicate::IsVariable,
+                _ => {
+                    return Err(ParseError::malformed(
+                        &name.as_span(),
+                        format!("unknown predicate {}", name.as_str()),
+                    ));
+                }
+            };
+            let operand = parse_guard_pair(next_child(&mut inner, &span)?, pool)?;
+            Ok(add_guard_node(pool, operand, None, |last, arity| {
+                Guard::Predicate {
+                    predicate,
+                    last,
+                    arity,
+                }
+            }))
+        }
+        Rule::guard_comparison => {
+            let mut inner = pair.into_inner();
+            let left = parse_guard_pair(next_child(&mut inner, &span)?, pool)?;
+            let op_pair = next_child(&mut inner, &span)?;
+            let op = match op_pair.as_str() {
+                "==" => CompareOp::Equal,
+                "!=" => CompareOp::NotEqual,
+                "<" => CompareOp::Less,
+                "<=" => CompareOp::LessEqual,
+                ">" => CompareOp::Greater,
+                ">=" => CompareOp::GreaterEqual,
+                _ => {
+                    return Err(ParseError::malformed(
+                        &op_pair.as_span(),
+                        format!("unknown comparison {}", op_pair.as_str()),
+                    ));
+                }
+            };
+            let right = parse_guard_pair(next_child(&mut inner, &span)?, pool)?;
+            Ok(add_guard_node(pool, left, Some(right), |last, arity| {
+                Guard::Compare { op, last, arity }
+            }))
+        }
+        Rule::guard_operand | Rule::compute_expr => {
+            parse_guard_pair(next_child(&mut pair.into_inner(), &span)?, pool)
+        }
+        Rule::compute_inner | Rule::compute_sum => {
+            let action = parse_compute_pair(pair, pool)?;
+            Ok(pool.add_guard(Guard::Operand(action)))
+        }
+        _ => Err(ParseError::unexpected_rule(&pair)),
+    }
+}
+
+/// Appends a node over `left` and, for binary nodes, `right`, which must be
+/// the two most recently added guard subtrees.
+fn add_guard_node(
+    pool: &mut Pool,
+    left: GuardId,
+    right: Option<GuardId>,
+    node: impl FnOnce(usize, usize) -> Guard,
+) -> GuardId {
+    let mut first_child_pos = left.0 + 1 - pool.length(left);
+    if let Some(right) = right {
+        first_child_pos = first_child_pos.min(right.0 + 1 - pool.length(right));
+    }
+    let arity = if right.is_some() { 2 } else { 1 };
+    let last = pool.guards.len() - first_child_pos;
+    pool.add_guard(node(last, arity))
+}
@@ -1,51 +1,94 @@
-pub mod expr_parser {
+/// One parser for every input kind, so that rulesets can embed the pattern
+/// and action grammars and all parsers share the same `Rule` type.
+pub mod grammar {
     use pest_derive::Parser;
 
     #[derive(Parser)]
+    #[grammar = "parser/common.pest"]
     #[grammar = "parser/expressions.pest"]
-    pub struct ExprParser;
+    #[grammar = "parser/patterns.pest"]
+    #[grammar = "parser/compute.pest"]
+    #[grammar = "parser/actions.pest"]
+    #[grammar = "parser/guards.pest"]
+    #[grammar = "parser/rulesets.pest"]
+    pub struct Grammar;
 }
 
-pub mod pattern_parser {
-    use pest_derive::Parser;
+pub mod expr_parser {
+    pub use super::grammar::{Grammar as ExprParser, Rule};
+}
 
-    #[derive(Parser)]
-    #[grammar = "parser/patterns.pest"]
-    pub struct PatternParser;
+pub mod pattern_parser {
+    pub use super::grammar::{Grammar as PatternParser, Rule};
 }
 
 pub mod action_parser {
-    use pest_derive::Parser;
-
-    #[derive(Parser)]
-    #[grammar = "parser/actions.pest"]
-    pub struct ActionParser;
+    pub use super::grammar::{Grammar as ActionParser, Rule};
 }
 
 pub mod compute_parser {
-    use pest_derive::Parser;
+    pub use super::grammar::{Grammar as ComputeParser, Rule};
+}
 
-    #[derive(Parser)]
-    #[grammar = "parser/compute.pest"]
-    pub struct ComputeParser;
+pub mod guard_parser {
+    pub use super::grammar::{Grammar as GuardParser, Rule};
 }
 
 pub mod ruleset_parser {
-    use pest_derive::Parser;
-
-    #[derive(Parser)]
-    #[grammar = "parser/rulesets.pest"]
-    pub struct RulesetParser;
+    pub use super::grammar::{Grammar as RulesetParser, Rule};
 }
 
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::Zero;
+use pest::RuleType;
+use pest::iterators::Pair;
+
 pub mod actions;
 pub mod compute;
+pub mod error;
 pub mod expr;
+pub mod guards;
+mod operators;
 pub mod patterns;
 pub mod rules;
 
 pub use actions::parse_action_pair;
 pub use compute::parse_compute_expr;
+pub use error::{ParseError, SourceSpan};
 pub use expr::*;
+pub use guards::parse_guard;
 pub use patterns::parse_pattern_pair;
 pub use rules::*;
+
+/// Parses a decimal integer literal.
+pub(crate) fn parse_integer_literal<R: RuleType>(pair: &Pair<R>) -> Result<BigInt, ParseError> {
+    pair.as_str()
+        .parse::<BigInt>()
+        .map_err(|_| ParseError::invalid_literal(pair, format!("invalid number {}", pair.as_str())))
+}
+
+/// Parses a `numerator//denominator` literal into its normalized value.
+pub(crate) fn parse_rational_literal<R: RuleType>(
+    pair: &Pair<R>,
+) -> Result<BigRational, ParseError> {
+    let text = pair.as_str();
+    let invalid = || ParseError::invalid_literal(pair, format!("invalid rational {}", text));
+    let (numer, denom) = text.split_once("//").ok_or_else(invalid)?;
+    let numer = numer.parse::<BigInt>().map_err(|_| invalid())?;
+    let denom = denom.parse::<BigInt>().map_err(|_| invalid())?;
+    if denom.is_zero() {
+        return Err(ParseError::invalid_literal(
+            pair,
+            format!("zero denominator in rational {}", text),
+        ));
+    }
+    Ok(BigRational::new(numer, denom))
+}
+
+/// Parses a decimal or exponent literal such as `0.5` or `1e-3`.
+pub(crate) fn parse_float_literal<R: RuleType>(pair: &Pair<R>) -> Result<f64, ParseError> {
+    pair.as_str()
+        .parse::<f64>()
+        .map_err(|_| ParseError::invalid_literal(pair, format!("invalid float {}", pair.as_str())))
+}
@@ -0,0 +1,109 @@
+use crate::{Fixity, FunctionId, Location, Pool};
+use pest::iterators::Pair;
+use std::iter::Peekable;
+
+use crate::parser::error::ParseError;
+use crate::parser::grammar::Rule;
+
+/// Builds the tree of an operator chain such as `a <+> ~b * c` by precedence
+/// climbing, using the built-in and declared operators of the pool.
+///
+/// `parse_value` builds one operand and `apply` adds a call of an operator
+/// over operands that were built last, so the result stays in postfix order.
+pub(crate) fn parse_operator_chain<'i, Id: Copy>(
+    pair: Pair<'i, Rule>,
+    pool: &mut Pool,
+    parse_value: fn(Pair<'i, Rule>, &mut Pool) -> Result<Id, ParseError>,
+    apply: fn(&mut Pool, FunctionId, &[Id], Location) -> Id,
+) -> Result<Id, ParseError> {
+    let span = pair.as_span();
+    let mut tokens = Vec::new();
+    for part in pair.into_inner() {
+        match part.as_rule() {
+            Rule::infix_operator => tokens.push(part),
+            _ => tokens.extend(part.into_inner()),
+        }
+    }
+
+    let mut chain = OperatorChain {
+        tokens: tokens.into_iter().peekable(),
+        span,
+        parse_value,
+        apply,
+    };
+    chain.climb(pool, 0)
+}
+
+struct OperatorChain<'i, Id> {
+    tokens: Peekable<std::vec::IntoIter<Pair<'i, Rule>>>,
+    span: pest::Span<'i>,
+    parse_value: fn(Pair<'i, Rule>, &mut Pool) -> Result<Id, ParseError>,
+    apply: fn(&mut Pool, FunctionId, &[Id], Location) -> Id,
+}
+
+impl<'i, Id: Copy> OperatorChain<'i, Id> {
+    fn location(&self) -> Location {
+        Location::new(self.span.start(), self.span.end())
+    }
+
+    /// Parses operands joined by infix operators that bind at least as
+    /// tightly as `min_precedence`.
+    fn climb(&mut self, pool: &mut Pool, min_precedence: u8) -> Result<Id, ParseError> {
+        let mut left = self.prefixed(pool)?;
+        let mut non_associative = None;
+
+        while let Some(op) = self.tokens.peek() {
+            let (function, fixity, precedence) =
+                pool.infix_operator(op.as_str()).ok_or_else(|| {
+                    ParseError::malformed(
+                        &op.as_span(),
+                        format!("{} is not declared as an infix operator", op.as_str()),
+                    )
+                })?;
+            if precedence < min_precedence {
+                break;
+            }
+            if non_associative == Some(precedence) {
+                return Err(ParseError::malformed(
+                    &op.as_span(),
+                    format!(
+                        "{} is non-associative and needs parentheses here",
+                        op.as_str()
+                    ),
+                ));
+            }
+            self.tokens.next();
+
+            let next_min = match fixity {
+                Fixity::InfixRight => precedence,
+                _ => precedence + 1,
+            };
+            let right = self.climb(pool, next_min)?;
+            left = (self.apply)(pool, function, &[left, right], self.location());
+            non_associative = (fixity == Fixity::Infix).then_some(precedence);
+        }
+
+        Ok(left)
+    }
+
+    /// Parses one operand with its prefix operators. A prefix operator
+    /// applies to everything to its right that binds more tightly than it.
+    fn prefixed(&mut self, pool: &mut Pool) -> Result<Id, ParseError> {
+        let token = self
+            .tokens
+            .next()
+            .ok_or_else(|| ParseError::malformed(&self.span, "missing operand"))?;
+        if token.as_rule() != Rule::prefix_operator {
+            return (self.parse_value)(token, pool);
+        }
+
+        let (function, precedence) = pool.prefix_operator(token.as_str()).ok_or_else(|| {
+            ParseError::malformed(
+                &token.as_span(),
+                format!("{} is not declared as a prefix operator", token.as_str()),
+            )
+        })?;
+        let operand = self.climb(pool, precedence + 1)?;
+        Ok((self.apply)(pool, function, &[operand], self.location()))
+    }
+}
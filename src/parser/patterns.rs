@@ -1,101 +1,77 @@
-use crate::{Function, Pattern, PatternId, Pool};
+use crate::children::Children;
+use crate::{CaptureKind, Function, FunctionId, Location, Pattern, PatternId, Pool};
 use pest::Parser;
 use pest::iterators::Pair;
 
+use crate::parser::error::{ParseError, first_pair, next_child};
+use crate::parser::operators::parse_operator_chain;
 use crate::parser::pattern_parser::{PatternParser, Rule};
+use crate::parser::{parse_float_literal, parse_integer_literal, parse_rational_literal};
 
-pub fn parse_pattern(input: &str, pool: &mut Pool) -> Result<PatternId, String> {
-    let pairs =
-        PatternParser::parse(Rule::pattern, input).map_err(|e| format!("Parse error: {}", e))?;
+pub fn parse_pattern(input: &str, pool: &mut Pool) -> Result<PatternId, ParseError> {
+    let pairs = PatternParser::parse(Rule::pattern_input, input)
+        .map_err(|e| ParseError::from_pest(e, input))?;
 
-    let pair = pairs.into_iter().next().unwrap();
+    let pair = first_pair(pairs, input)?;
     parse_pattern_pair(pair, pool)
 }
 
-pub fn parse_pattern_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<PatternId, String> {
-    match pair.as_rule() {
-        Rule::pattern => parse_pattern_pair(pair.into_inner().next().unwrap(), pool),
-        Rule::pattern_sum => {
-            let mut inner = pair.into_inner();
-            let mut pattern = parse_pattern_pair(inner.next().unwrap(), pool)?;
-
-            while let Some(op) = inner.next() {
-                let right = parse_pattern_pair(inner.next().unwrap(), pool)?;
-                let func = match op.as_str() {
-                    "+" => Function::Add,
-                    "-" => Function::Subtract,
-                    _ => return Err(format!("Unknown operator: {}", op.as_str())),
-                };
-                let fun_id = pool.intern_function(func);
+pub fn parse_pattern_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<PatternId, ParseError> {
+    let span = pair.as_span();
 
-                let new_node_pos = pool.patterns.len();
-                let first_child_pos = if pattern.0 < right.0 {
-                    pattern.0
-                } else {
-                    right.0
-                };
-                let last = new_node_pos - first_child_pos;
-
-                pattern = pool.add_pattern(Pattern::Call {
-                    fun: fun_id,
-                    last,
-                    arity: 2,
-                });
-            }
-            Ok(pattern)
+    match pair.as_rule() {
+        Rule::pattern => {
+            parse_operator_chain(pair, pool, parse_pattern_pair, apply_pattern_function)
         }
-        Rule::pattern_product => {
+        Rule::constrained_pattern => {
             let mut inner = pair.into_inner();
-            let mut pattern = parse_pattern_pair(inner.next().unwrap(), pool)?;
-
-            while let Some(op) = inner.next() {
-                let right = parse_pattern_pair(inner.next().unwrap(), pool)?;
-                let func = match op.as_str() {
-                    "*" => Function::Multiply,
-                    "/" => Function::Divide,
-                    _ => return Err(format!("Unknown operator: {}", op.as_str())),
-                };
-                let fun_id = pool.intern_function(func);
-
-                let new_node_pos = pool.patterns.len();
-                let first_child_pos = if pattern.0 < right.0 {
-                    pattern.0
-                } else {
-                    right.0
-                };
-                let last = new_node_pos - first_child_pos;
-
-                pattern = pool.add_pattern(Pattern::Call {
-                    fun: fun_id,
-                    last,
-                    arity: 2,
-                });
+            let pattern_pair = next_child(&mut inner, &span)?;
+            let bound: Vec<_> = captures(&pattern_pair)
+                .iter()
+                .map(|p| capture_name(p).to_string())
+                .collect();
+            let pattern = parse_pattern_pair(pattern_pair, pool)?;
+
+            let mut arity = 1;
+            for constraint in inner.filter(|p| p.as_rule() == Rule::pattern_constraint) {
+                let constraint_span = constraint.as_span();
+                let mut parts = constraint.into_inner();
+                let var = next_child(&mut parts, &constraint_span)?;
+                if !bound.iter().any(|name| name == capture_name(&var)) {
+                    return Err(ParseError::unbound_variable(
+                        &var.as_span(),
+                        format!("{} is not bound by the pattern", var.as_str()),
+                    ));
+                }
+                let other = parse_pattern_pair(next_child(&mut parts, &constraint_span)?, pool)?;
+                let var = pool.intern_string(capture_name(&var).to_string());
+                let last = pool.length(other);
+                pool.add_pattern(Pattern::Distinct { var, last });
+                arity += 1;
             }
-            Ok(pattern)
+            if arity == 1 {
+                return Ok(pattern);
+            }
+
+            let start = pattern.0 + 1 - pool.length(pattern);
+            Ok(pool.add_pattern(Pattern::Where {
+                last: pool.patterns.len() - start,
+                arity,
+            }))
         }
-        Rule::pattern_power => {
+        Rule::context_pattern => {
             let mut inner = pair.into_inner();
-            let mut pattern = parse_pattern_pair(inner.next().unwrap(), pool)?;
-
-            for right_pair in inner {
-                let right = parse_pattern_pair(right_pair, pool)?;
-                let fun_id = pool.intern_function(Function::Power);
-
-                let new_node_pos = pool.patterns.len();
-                let first_child_pos = if pattern.0 < right.0 {
-                    pattern.0
-                } else {
-                    right.0
-                };
-                let last = new_node_pos - first_child_pos;
-
-                pattern = pool.add_pattern(Pattern::Call {
-                    fun: fun_id,
-                    last,
-                    arity: 2,
-                });
-            }
-            Ok(pattern)
+            let var = next_child(&mut inner, &span)?.as_str().to_string();
+            let var = pool.intern_string(var);
+            let hole = parse_pattern_pair(next_child(&mut inner, &span)?, pool)?;
+            let last = pool.length(hole);
+            Ok(pool.add_pattern(Pattern::Context { var, last }))
+        }
+        Rule::negated_pattern => {
+            let inner = next_child(&mut pair.into_inner(), &span)?;
+            let inner = parse_pattern_pair(inner, pool)?;
+            let last = pool.length(inner);
+            Ok(pool.add_pattern(Pattern::Not { last }))
         }
         Rule::pattern_value => {
             let inner = pair.into_inner();
@@ -115,14 +91,17 @@ pub fn parse_pattern_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<PatternId
                     }
                 }
             }
-            let mut pattern = parse_pattern_pair(atom_pair.unwrap(), pool)?;
+            let mut pattern = parse_pattern_pair(
+                atom_pair.ok_or_else(|| ParseError::malformed(&span, "missing operand"))?,
+                pool,
+            )?;
 
             for func in ops.into_iter().rev() {
                 let fun_id = pool.intern_function(func);
 
                 pattern = pool.add_pattern(Pattern::Call {
                     fun: fun_id,
-                    last: pool.patterns.len() - pattern.0,
+                    last: pool.patterns.len() - (pattern.0 + 1 - pool.length(pattern)),
                     arity: 1,
                 });
             }
@@ -139,13 +118,46 @@ pub fn parse_pattern_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<PatternId
             let var_id = pool.intern_string(name.to_string());
             Ok(pool.add_pattern(Pattern::Wildcard(var_id)))
         }
+        Rule::typed_variable => {
+            let mut inner = pair.into_inner();
+            let name = next_child(&mut inner, &span)?.as_str()[1..].to_string();
+            let kind = next_child(&mut inner, &span)?;
+            let kind = match kind.as_str() {
+                "var" => CaptureKind::Variable,
+                "call" => CaptureKind::Call,
+                "struct" => CaptureKind::Struct,
+                "ground" => CaptureKind::Ground,
+                "nonnum" => CaptureKind::NonNumber,
+                _ => {
+                    return Err(ParseError::malformed(
+                        &kind.as_span(),
+                        format!("unknown capture kind {}", kind.as_str()),
+                    ));
+                }
+            };
+            let name = pool.intern_string(name);
+            Ok(pool.add_pattern(Pattern::Typed { name, kind }))
+        }
+        Rule::sequence_variable => {
+            let name = pair.as_str()[1..].trim_end_matches("...");
+            let var_id = pool.intern_string(name.to_string());
+            Ok(pool.add_pattern(Pattern::Sequence(var_id)))
+        }
         Rule::number => {
-            let num = pair
-                .as_str()
-                .parse::<i32>()
-                .map_err(|_| format!("Invalid number: {}", pair.as_str()))?;
+            let num = parse_integer_literal(&pair)?;
+            let num = pool.intern_number(num);
             Ok(pool.add_pattern(Pattern::Number(num)))
         }
+        Rule::float => {
+            let value = parse_float_literal(&pair)?;
+            let float = pool.intern_float(value);
+            Ok(pool.add_pattern(Pattern::Float(float)))
+        }
+        Rule::rational => {
+            let value = parse_rational_literal(&pair)?;
+            let number = pool.intern_exact(value);
+            Ok(pool.add_pattern(number.into()))
+        }
         Rule::variable => {
             let var_name = pair.as_str().to_string();
             let var_id = pool.intern_string(var_name);
@@ -153,7 +165,7 @@ pub fn parse_pattern_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<PatternId
         }
         Rule::pattern_function_call => {
             let mut inner = pair.into_inner();
-            let func_name = inner.next().unwrap().as_str().to_string();
+            let func_name = next_child(&mut inner, &span)?.as_str().to_string();
             let name_id = pool.intern_string(func_name);
             let func_id = pool.intern_function(crate::Function::Custom(name_id));
 
@@ -164,17 +176,17 @@ pub fn parse_pattern_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<PatternId
                 args.push(arg_pattern);
             }
 
-            let args_start = args.last().copied().unwrap();
+            let args_start = args[0].0 + 1 - pool.length(args[0]);
 
             Ok(pool.add_pattern(Pattern::Call {
                 fun: func_id,
-                last: pool.patterns.len() - args_start.0,
+                last: pool.patterns.len() - args_start,
                 arity: args.len(),
             }))
         }
         Rule::pattern_struct_expr => {
             let mut inner = pair.into_inner();
-            let struct_name = inner.next().unwrap().as_str().to_string();
+            let struct_name = next_child(&mut inner, &span)?.as_str().to_string();
             let name_id = pool.intern_string(struct_name);
 
             let mut fields = Vec::new();
@@ -184,17 +196,17 @@ pub fn parse_pattern_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<PatternId
                 fields.push(field_pattern);
             }
 
-            let fields_start = fields.last().copied().unwrap();
+            let fields_start = fields[0].0 + 1 - pool.length(fields[0]);
 
             Ok(pool.add_pattern(Pattern::Struct {
                 name: name_id,
-                last: pool.patterns.len() - fields_start.0,
+                last: pool.patterns.len() - fields_start,
                 arity: fields.len(),
             }))
         }
         Rule::var_function_call => {
             let mut inner = pair.into_inner();
-            let var_name = inner.next().unwrap().as_str()[1..].to_string();
+            let var_name = next_child(&mut inner, &span)?.as_str()[1..].to_string();
             let var_id = pool.intern_string(var_name);
 
             let mut args = Vec::new();
@@ -204,17 +216,17 @@ pub fn parse_pattern_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<PatternId
                 args.push(arg_pattern);
             }
 
-            let args_start = args.last().copied().unwrap();
+            let args_start = args[0].0 + 1 - pool.length(args[0]);
 
             Ok(pool.add_pattern(Pattern::VarCallName {
                 var: var_id,
-                last: pool.patterns.len() - args_start.0,
+                last: pool.patterns.len() - args_start,
                 arity: args.len(),
             }))
         }
         Rule::var_struct_expr => {
             let mut inner = pair.into_inner();
-            let var_name = inner.next().unwrap().as_str()[1..].to_string();
+            let var_name = next_child(&mut inner, &span)?.as_str()[1..].to_string();
             let var_id = pool.intern_string(var_name);
 
             let mut fields = Vec::new();
@@ -224,14 +236,58 @@ pub fn parse_pattern_pair(pair: Pair<Rule>, pool: &mut Pool) -> Result<PatternId
                 fields.push(field_pattern);
             }
 
-            let fields_start = fields.last().copied().unwrap();
+            let fields_start = fields[0].0 + 1 - pool.length(fields[0]);
 
             Ok(pool.add_pattern(Pattern::VarStructName {
                 var: var_id,
-                last: pool.patterns.len() - fields_start.0,
+                last: pool.patterns.len() - fields_start,
                 arity: fields.len(),
             }))
         }
-        _ => Err(format!("Unexpected pattern rule: {:?}", pair.as_rule())),
+        _ => Err(ParseError::unexpected_rule(&pair)),
+    }
+}
+
+/// The `?x`, `#x`, `?xs...` and `C[..]` captures a pattern binds, leaving
+/// out those local to a `not(...)` and those a `where` constraint compares
+/// against.
+pub(crate) fn captures<'i>(pair: &Pair<'i, Rule>) -> Vec<Pair<'i, Rule>> {
+    let mut found = Vec::new();
+    for part in pair.clone().into_inner() {
+        match part.as_rule() {
+            Rule::named_variable
+            | Rule::any_number
+            | Rule::sequence_variable
+            | Rule::context_name => found.push(part),
+            Rule::negated_pattern | Rule::pattern_constraint => {}
+            _ => found.extend(captures(&part)),
+        }
     }
+    found
+}
+
+/// The name of a `?x`, `#x`, `?xs...` or `C[..]` capture.
+pub(crate) fn capture_name<'i>(capture: &Pair<'i, Rule>) -> &'i str {
+    match capture.as_rule() {
+        Rule::context_name => capture.as_str(),
+        _ => capture.as_str()[1..].trim_end_matches("..."),
+    }
+}
+
+fn apply_pattern_function(
+    pool: &mut Pool,
+    fun: FunctionId,
+    args: &[PatternId],
+    _location: Location,
+) -> PatternId {
+    let first_child_pos = args
+        .iter()
+        .map(|arg| arg.0 + 1 - pool.length(*arg))
+        .min()
+        .unwrap_or(pool.patterns.len());
+    pool.add_pattern(Pattern::Call {
+        fun,
+        last: pool.patterns.len() - first_child_pos,
+        arity: args.len(),
+    })
 }
@@ -1,26 +1,339 @@
 use crate::ast::Rule;
-use crate::parser::actions::parse_action;
-use crate::parser::patterns::parse_pattern;
-use crate::{Location, Pool, RuleId, Ruleset, RulesetId};
+use crate::parser::actions::parse_action_pair;
+use crate::parser::error::{ParseError, first_pair, next_child};
+use crate::parser::guards::parse_guard_pair;
+use crate::parser::patterns::{capture_name, captures, parse_pattern_pair};
+use crate::parser::{parse_integer_literal, parse_rational_literal};
+use crate::theories::Theory;
+use crate::{Fixity, Location, Pool, RuleId, Ruleset, RulesetId};
 use pest::Parser;
 use pest::iterators::Pair;
 
 use crate::parser::ruleset_parser::{Rule as PestRule, RulesetParser};
 
-pub fn parse_ruleset(input: &str, pool: &mut Pool) -> Result<RulesetId, String> {
-    let pairs = RulesetParser::parse(PestRule::ruleset, input)
-        .map_err(|e| format!("Parse error: {}", e))?;
+/// Outcome of parsing a ruleset with error recovery.
+///
+/// `ruleset` is `None` only if the header or braces could not be parsed;
+/// otherwise it holds every rule that parsed, and `errors` lists the rules
+/// that were skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RulesetParse {
+    pub ruleset: Option<RulesetId>,
+    pub errors: Vec<ParseError>,
+}
+
+impl RulesetParse {
+    pub fn is_ok(&self) -> bool {
+        self.ruleset.is_some() && self.errors.is_empty()
+    }
+}
+
+/// Parses a ruleset, failing on the first broken rule or import without
+/// registering the ruleset.
+pub fn parse_ruleset(input: &str, pool: &mut Pool) -> Result<RulesetId, ParseError> {
+    let (pairs, span) = parse_top_level(PestRule::ruleset_input, input)?;
+    let rulesets = into_strict(parse_ruleset_pairs(pairs, pool, false))?;
+    rulesets
+        .first()
+        .copied()
+        .ok_or_else(|| ParseError::malformed(&span, "ruleset was not registered"))
+}
+
+/// Parses a ruleset, skipping broken rules and imports. The valid rules are
+/// registered and returned as a partial ruleset together with one diagnostic
+/// per skipped rule.
+pub fn parse_ruleset_with_diagnostics(input: &str, pool: &mut Pool) -> RulesetParse {
+    let failed = |error| RulesetParse {
+        ruleset: None,
+        errors: vec![error],
+    };
+    match parse_top_level(PestRule::ruleset_input, input) {
+        Ok((pairs, span)) => parse_ruleset_pairs(pairs, pool, true)
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| failed(ParseError::malformed(&span, "ruleset was not registered"))),
+        Err(error) => failed(error),
+    }
+}
 
-    let pair = pairs.into_iter().next().unwrap();
+/// Parses a file of zero or more rulesets. Fails on the first broken rule or
+/// import, in which case none of the file's rulesets are registered.
+pub fn parse_rulesets(input: &str, pool: &mut Pool) -> Result<Vec<RulesetId>, ParseError> {
+    let pairs = RulesetParser::parse(PestRule::ruleset_file, input)
+        .map_err(|e| ParseError::from_pest(e, input))?;
+    let rulesets = pairs.filter(|pair| pair.as_rule() != PestRule::EOI);
+    into_strict(parse_ruleset_pairs(rulesets, pool, false))
+}
+
+/// Parses a file of zero or more rulesets with error recovery, returning one
+/// result per ruleset. If the file structure itself is broken, the only
+/// result carries that error and no ruleset.
+pub fn parse_rulesets_with_diagnostics(input: &str, pool: &mut Pool) -> Vec<RulesetParse> {
+    match RulesetParser::parse(PestRule::ruleset_file, input) {
+        Ok(pairs) => {
+            let rulesets = pairs.filter(|pair| pair.as_rule() != PestRule::EOI);
+            parse_ruleset_pairs(rulesets, pool, true)
+        }
+        Err(e) => vec![RulesetParse {
+            ruleset: None,
+            errors: vec![ParseError::from_pest(e, input)],
+        }],
+    }
+}
+
+/// Parses a single ruleset with its leading operator declarations, returning
+/// their pairs and the span of the ruleset.
+fn parse_top_level<'i>(
+    entry: PestRule,
+    input: &'i str,
+) -> Result<(Vec<Pair<'i, PestRule>>, pest::Span<'i>), ParseError> {
+    let pairs = RulesetParser::parse(entry, input).map_err(|e| ParseError::from_pest(e, input))?;
+    let pairs: Vec<_> = pairs
+        .filter(|pair| pair.as_rule() != PestRule::EOI)
+        .collect();
+    let span = first_pair(pairs.iter().rev().cloned(), input)?.as_span();
+    Ok((pairs, span))
+}
+
+fn into_strict(results: Vec<RulesetParse>) -> Result<Vec<RulesetId>, ParseError> {
+    let mut rulesets = Vec::new();
+    for result in results {
+        if let Some(error) = result.errors.into_iter().next() {
+            return Err(error);
+        }
+        rulesets.extend(result.ruleset);
+    }
+    Ok(rulesets)
+}
+
+/// Registers the rulesets, operator and theory declarations of one input in order,
+/// then resolves the imports. Imports are resolved only once every ruleset of
+/// the input is registered, so they may refer to rulesets defined further
+/// down.
+///
+/// Without `recover` the first error unregisters everything the input added
+/// (rulesets, rules with their patterns, actions and guards, declarations)
+/// and is returned as the only result.
+fn parse_ruleset_pairs<'i>(
+    pairs: impl IntoIterator<Item = Pair<'i, PestRule>>,
+    pool: &mut Pool,
+    recover: bool,
+) -> Vec<RulesetParse> {
+    let rulesets_before = pool.rulesets.len();
+    let operators_before = pool.operators.len();
+    let theories_before = pool.theories.len();
+    let rules_before = pool.rules.len();
+    let patterns_before = pool.patterns.len();
+    let actions_before = pool.actions.len();
+    let guards_before = pool.guards.len();
+    let rule_locations_before = pool.rule_locations.len();
+    let action_locations_before = pool.action_locations.len();
+    let failed = |pool: &mut Pool, error| {
+        pool.rulesets.truncate(rulesets_before);
+        pool.operators.truncate(operators_before);
+        pool.theories.truncate(theories_before);
+        pool.rules.truncate(rules_before);
+        pool.patterns.truncate(patterns_before);
+        pool.actions.truncate(actions_before);
+        pool.guards.truncate(guards_before);
+        pool.rule_locations.truncate(rule_locations_before);
+        pool.action_locations.truncate(action_locations_before);
+        pool.rule_reverses
+            .retain(|forward, reverse| forward.0 < rules_before && reverse.0 < rules_before);
+        pool.rebuild_rule_index();
+        vec![RulesetParse {
+            ruleset: None,
+            errors: vec![error],
+        }]
+    };
+
+    let mut results = Vec::new();
+    let mut pending_imports = Vec::new();
+    let mut declaration_errors = Vec::new();
+    for pair in pairs {
+        if pair.as_rule() == PestRule::operator_decl {
+            declare_operator(pair, pool);
+            continue;
+        }
+        if pair.as_rule() == PestRule::theory_decl {
+            match declare_theory(pair, pool) {
+                Ok(()) => {}
+                Err(error) if recover => declaration_errors.push(error),
+                Err(error) => return failed(pool, error),
+            }
+            continue;
+        }
+        let (mut result, imports) = parse_ruleset_pair(pair, pool, recover);
+        if !recover && let Some(error) = result.errors.first() {
+            return failed(pool, error.clone());
+        }
+        // A broken declaration is reported with the ruleset that follows it.
+        result.errors.splice(0..0, declaration_errors.drain(..));
+        results.push(result);
+        pending_imports.push(imports);
+    }
+    if !declaration_errors.is_empty() {
+        results.push(RulesetParse {
+            ruleset: None,
+            errors: declaration_errors,
+        });
+        pending_imports.push(Vec::new());
+    }
+
+    for (result, imports) in results.iter_mut().zip(pending_imports) {
+        let Some(ruleset_id) = result.ruleset else {
+            continue;
+        };
+        for import in imports {
+            match resolve_import(pool, ruleset_id, &import) {
+                // `extends t` and `use t;` may name the same ruleset.
+                Ok(target) if pool.rulesets[ruleset_id.0].imports.contains(&target) => {}
+                Ok(target) => pool.rulesets[ruleset_id.0].imports.push(target),
+                Err(error) if recover => result.errors.push(error),
+                Err(error) => return failed(pool, error),
+            }
+        }
+    }
+
+    results
+}
+
+fn declare_operator(pair: Pair<PestRule>, pool: &mut Pool) {
+    let mut fixity = Fixity::InfixLeft;
+    let mut precedence = 0;
+    let mut symbol = "";
+    for part in pair.into_inner() {
+        match part.as_rule() {
+            PestRule::fixity => {
+                fixity = match part.as_str() {
+                    "infixr" => Fixity::InfixRight,
+                    "infix" => Fixity::Infix,
+                    "prefix" => Fixity::Prefix,
+                    _ => Fixity::InfixLeft,
+                }
+            }
+            PestRule::operator_precedence => precedence = part.as_str().parse().unwrap_or(0),
+            _ => symbol = part.as_str(),
+        }
+    }
+    pool.declare_operator(symbol, fixity, precedence);
+}
+
+fn declare_theory(pair: Pair<PestRule>, pool: &mut Pool) -> Result<(), ParseError> {
+    let mut theory = Theory::default();
+    let mut symbol = "";
+    for part in pair.into_inner() {
+        match part.as_rule() {
+            PestRule::theory_kind => {
+                theory.associative = part.as_str() != "comm";
+                theory.commutative = part.as_str() != "assoc";
+            }
+            PestRule::theory_symbol => symbol = part.as_str(),
+            _ => {
+                for literal in part.into_inner() {
+                    theory.identity = match literal.as_rule() {
+                        PestRule::rational => {
+                            Some(pool.intern_exact(parse_rational_literal(&literal)?))
+                        }
+                        PestRule::number => {
+                            Some(pool.intern_exact(parse_integer_literal(&literal)?.into()))
+                        }
+                        _ => theory.identity,
+                    };
+                }
+            }
+        }
+    }
+    pool.declare_theory(symbol, theory);
+    Ok(())
+}
+
+fn resolve_import(
+    pool: &Pool,
+    ruleset_id: RulesetId,
+    import: &Pair<PestRule>,
+) -> Result<RulesetId, ParseError> {
+    let name = import.as_str();
+    let target = pool.find_ruleset_by_name(name).ok_or_else(|| {
+        ParseError::import(&import.as_span(), format!("unknown ruleset '{}'", name))
+    })?;
+
+    if let Some(path) = pool.ruleset_import_path(target, ruleset_id) {
+        let cycle: Vec<_> = std::iter::once(ruleset_id)
+            .chain(path)
+            .map(|id| pool.display_name(pool[id].name))
+            .collect();
+        return Err(ParseError::import(
+            &import.as_span(),
+            format!("circular import {}", cycle.join(" -> ")),
+        ));
+    }
 
+    Ok(target)
+}
+
+fn parse_ruleset_pair<'i>(
+    pair: Pair<'i, PestRule>,
+    pool: &mut Pool,
+    recover: bool,
+) -> (RulesetParse, Vec<Pair<'i, PestRule>>) {
+    let failed = |error| RulesetParse {
+        ruleset: None,
+        errors: vec![error],
+    };
+
+    let span = pair.as_span();
     let mut inner = pair.into_inner();
-    let name = inner.next().unwrap().as_str();
+    let name = match next_child(&mut inner, &span) {
+        Ok(name) => name.as_str(),
+        Err(error) => return (failed(error), Vec::new()),
+    };
     let name_id = pool.intern_string(name.to_string());
 
     let rules_start = pool.get_rules_len();
+    let mut errors = Vec::new();
+    let mut imports = Vec::new();
 
-    for rule_pair in inner {
-        parse_rule_pair(rule_pair, pool)?;
+    let mut rule_pairs = inner.peekable();
+    while let Some(rule_pair) = rule_pairs.next() {
+        let result = match rule_pair.as_rule() {
+            PestRule::extends_clause | PestRule::use_decl => {
+                imports.extend(rule_pair.into_inner());
+                continue;
+            }
+            PestRule::operator_decl => {
+                declare_operator(rule_pair, pool);
+                continue;
+            }
+            PestRule::theory_decl => declare_theory(rule_pair, pool),
+            PestRule::malformed_rule => Err(malformed_rule_error(rule_pair.as_span())),
+            _ => match rule_pairs.next_if(|next| continues_line(&rule_pair, next)) {
+                // Leftover text on the same line means the rule itself is broken
+                Some(rest) => {
+                    let line = rule_pair
+                        .as_span()
+                        .start_pos()
+                        .span(&rest.as_span().end_pos());
+                    Err(malformed_rule_error(line))
+                }
+                None if rule_pair.as_rule() == PestRule::equation => {
+                    parse_equation_pair(rule_pair, pool).map(|_| ())
+                }
+                None => parse_rule_pair(rule_pair, pool).map(|_| ()),
+            },
+        };
+        if let Err(error) = result {
+            errors.push(error);
+            if !recover {
+                return (
+                    RulesetParse {
+                        ruleset: None,
+                        errors,
+                    },
+                    Vec::new(),
+                );
+            }
+        }
     }
 
     let rules_end = pool.get_rules_len();
@@ -29,23 +342,37 @@ pub fn parse_ruleset(input: &str, pool: &mut Pool) -> Result<RulesetId, String>
         name: name_id,
         rules_start,
         rules_end,
+        imports: Vec::new(),
     };
 
-    Ok(pool.add_ruleset(ruleset))
+    (
+        RulesetParse {
+            ruleset: Some(pool.add_ruleset(ruleset)),
+            errors,
+        },
+        imports,
+    )
 }
 
-pub fn parse_rule_pair(pair: Pair<PestRule>, pool: &mut Pool) -> Result<RuleId, String> {
+pub fn parse_rule_pair(pair: Pair<PestRule>, pool: &mut Pool) -> Result<RuleId, ParseError> {
     let span = pair.as_span();
-    let location = Location::new(span.start(), span.end());
+    let location = Location::new(span.start(), span.start() + span.as_str().trim_end().len());
 
     let mut inner = pair.into_inner();
-    let rule_name = inner.next().unwrap().as_str();
-    let pattern_str = inner.next().unwrap().as_str();
-    let action_str = inner.next().unwrap().as_str();
+    let rule_name = next_child(&mut inner, &span)?.as_str();
+    let pattern_pair = next_child(&mut inner, &span)?;
+    let action_pair = next_child(&mut inner, &span)?;
+    let guard_pair =
+        guard_of_clause(inner.next(), &pattern_pair).map_err(|e| e.in_rule(rule_name))?;
+
+    let pattern_id = parse_pattern_pair(pattern_pair, pool).map_err(|e| e.in_rule(rule_name))?;
 
-    let pattern_id = parse_pattern(pattern_str, pool)?;
+    let action_id = parse_action_pair(action_pair, pool).map_err(|e| e.in_rule(rule_name))?;
 
-    let action_id = parse_action(action_str, pool)?;
+    let guard = guard_pair
+        .map(|pair| parse_guard_pair(pair, pool))
+        .transpose()
+        .map_err(|e| e.in_rule(rule_name))?;
 
     let name_id = pool.intern_string(rule_name.to_string());
 
@@ -53,7 +380,159 @@ pub fn parse_rule_pair(pair: Pair<PestRule>, pool: &mut Pool) -> Result<RuleId,
         name: name_id,
         pattern: pattern_id,
         action: action_id,
+        guard,
     };
 
     Ok(pool.add_rule_with_location(rule, location))
 }
+
+/// Parses `name: lhs <=> rhs` into the rule `name: lhs => rhs` and its
+/// reverse `b_name: rhs => lhs`, returned in that order and linked in the
+/// pool. Both sides must capture the same variables, since each one is the
+/// other's action.
+pub fn parse_equation_pair(
+    pair: Pair<PestRule>,
+    pool: &mut Pool,
+) -> Result<(RuleId, RuleId), ParseError> {
+    let span = pair.as_span();
+    let location = Location::new(span.start(), span.start() + span.as_str().trim_end().len());
+
+    let mut inner = pair.into_inner();
+    let rule_name = next_child(&mut inner, &span)?.as_str();
+    let lhs_pair = next_child(&mut inner, &span)?;
+    let rhs_pair = next_child(&mut inner, &span)?;
+
+    check_captures_bound(&rhs_pair, &lhs_pair, "left").map_err(|e| e.in_rule(rule_name))?;
+    check_captures_bound(&lhs_pair, &rhs_pair, "right").map_err(|e| e.in_rule(rule_name))?;
+    let guard_pair = guard_of_clause(inner.next(), &lhs_pair).map_err(|e| e.in_rule(rule_name))?;
+
+    let (lhs_span, rhs_span) = (lhs_pair.as_span(), rhs_pair.as_span());
+    let negated = |side: &pest::Span| {
+        ParseError::malformed(
+            side,
+            "not(...) cannot be used in an equation, since each side is the other's action",
+        )
+        .in_rule(rule_name)
+    };
+    let lhs = parse_pattern_pair(lhs_pair, pool).map_err(|e| e.in_rule(rule_name))?;
+    let rhs = parse_pattern_pair(rhs_pair, pool).map_err(|e| e.in_rule(rule_name))?;
+    let lhs_action = pool
+        .pattern_to_action(lhs, location.clone())
+        .ok_or_else(|| negated(&lhs_span))?;
+    let rhs_action = pool
+        .pattern_to_action(rhs, location.clone())
+        .ok_or_else(|| negated(&rhs_span))?;
+    // Both sides capture the same variables, so one guard serves both rules.
+    let guard = guard_pair
+        .map(|pair| parse_guard_pair(pair, pool))
+        .transpose()
+        .map_err(|e| e.in_rule(rule_name))?;
+
+    let forward = Rule {
+        name: pool.intern_string(rule_name.to_string()),
+        pattern: lhs,
+        action: rhs_action,
+        guard,
+    };
+    let reverse = Rule {
+        name: pool.intern_string(format!("b_{}", rule_name)),
+        pattern: rhs,
+        action: lhs_action,
+        guard,
+    };
+    let forward = pool.add_rule_with_location(forward, location.clone());
+    let reverse = pool.add_rule_with_location(reverse, location);
+    pool.link_reverse_rules(forward, reverse);
+
+    Ok((forward, reverse))
+}
+
+/// Reports the first `?x`, `#x` or `?xs...` of `side` that `other` does not capture.
+fn check_captures_bound(
+    side: &Pair<PestRule>,
+    other: &Pair<PestRule>,
+    other_name: &str,
+) -> Result<(), ParseError> {
+    let bound: Vec<_> = captures(other)
+        .iter()
+        .map(|p| capture_name(p).to_string())
+        .collect();
+
+    match captures(side)
+        .into_iter()
+        .find(|p| !bound.iter().any(|name| name == capture_name(p)))
+    {
+        Some(unbound) => Err(ParseError::unbound_variable(
+            &unbound.as_span(),
+            format!(
+                "{} is not bound by the {}-hand side",
+                unbound.as_str(),
+                other_name
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Takes the guard out of an optional `when` clause, reporting the first
+/// variable it uses that `pattern` does not capture.
+fn guard_of_clause<'i>(
+    clause: Option<Pair<'i, PestRule>>,
+    pattern: &Pair<'i, PestRule>,
+) -> Result<Option<Pair<'i, PestRule>>, ParseError> {
+    let Some(clause) = clause else {
+        return Ok(None);
+    };
+    let span = clause.as_span();
+    let guard = clause
+        .into_inner()
+        .find(|p| p.as_rule() == PestRule::guard)
+        .ok_or_else(|| ParseError::malformed(&span, "missing guard"))?;
+
+    let bound: Vec<_> = captures(pattern)
+        .iter()
+        .map(|p| capture_name(p).to_string())
+        .collect();
+    let unbound = guard
+        .clone()
+        .into_inner()
+        .flatten()
+        .find(|p| p.as_rule() == PestRule::variable && !bound.iter().any(|b| b == p.as_str()));
+    match unbound {
+        Some(unbound) => Err(ParseError::unbound_variable(
+            &unbound.as_span(),
+            format!("{} is not bound by the pattern", unbound.as_str()),
+        )),
+        None => Ok(Some(guard)),
+    }
+}
+
+/// Whether `next` is skipped text on the line where `rule` ends.
+fn continues_line(rule: &Pair<PestRule>, next: &Pair<PestRule>) -> bool {
+    let text = rule.as_str();
+    next.as_rule() == PestRule::malformed_rule && !text[text.trim_end().len()..].contains('\n')
+}
+
+/// Explains why a line was skipped by re-parsing it as a single rule, which
+/// yields the expected tokens at the point where it went wrong.
+fn malformed_rule_error(line: pest::Span) -> ParseError {
+    let text = line.as_str();
+
+    let rule_name = text
+        .split_once(':')
+        .map(|(name, _)| name.trim())
+        .filter(|name| {
+            name.starts_with(|c: char| c.is_ascii_alphabetic())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+    let Some(rule_name) = rule_name else {
+        return ParseError::expected_at(&line, vec!["rule".to_string()]);
+    };
+
+    match RulesetParser::parse(PestRule::rule_input, text) {
+        Err(e) => ParseError::from_pest(e, text)
+            .relocate(line.start(), line.get_input())
+            .in_rule(rule_name),
+        Ok(_) => ParseError::expected_at(&line, vec!["rule".to_string()]).in_rule(rule_name),
+    }
+}
@@ -1,8 +1,12 @@
 use crate::ast::*;
 use crate::children::Children;
 use crate::idx::*;
-use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use crate::rule_index::RuleIndex;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::ops::Index;
 
 pub struct AncestorIterator<'a> {
@@ -117,17 +121,68 @@ impl std::fmt::Display for Coordinate {
 pub struct Pool {
     pub exprs: Vec<ExprNode>,
     pub expr_ends: std::collections::BTreeSet<usize>,
+    /// The hash-cons tables: the Merkle hash of every node and its canonical
+    /// id, the first node with the same structure. Identical subtrees share
+    /// a canonical id, not storage, see `add_expr_tree`. The tables are
+    /// rebuilt from `exprs` by `rebuild_hash_cons`.
+    #[serde(skip)]
+    pub expr_hashes: Vec<u64>,
+    #[serde(skip)]
+    pub canonical_exprs: Vec<ExprId>,
+    #[serde(skip)]
+    pub hash_cons: HashMap<u64, Vec<ExprId>>,
+    #[serde(skip)]
+    pub interned_roots: HashMap<ExprId, ExprId>,
     pub names: Vec<String>,
     pub name_map: HashMap<String, NameId>,
+    #[serde(default)]
+    pub numbers: Vec<BigInt>,
+    // JSON maps need string keys, so this is rebuilt from `numbers` on demand.
+    #[serde(skip)]
+    pub number_map: HashMap<BigInt, NumberId>,
+    #[serde(default)]
+    pub rationals: Vec<BigRational>,
+    #[serde(skip)]
+    pub rational_map: HashMap<BigRational, RationalId>,
+    // Bit patterns rather than `f64`, so NaN and -0.0 survive JSON export.
+    #[serde(default)]
+    pub floats: Vec<u64>,
+    #[serde(skip)]
+    pub float_map: HashMap<u64, FloatId>,
     pub functions: Vec<Function>,
     pub function_map: HashMap<Function, FunctionId>,
     pub patterns: Vec<Pattern>,
     pub rules: Vec<Rule>,
+    /// Rebuilt from `rules` by `update_rule_index`.
+    #[serde(skip)]
+    pub rule_index: RuleIndex,
     pub rulesets: Vec<Ruleset>,
     pub actions: Vec<Action>,
+    #[serde(default)]
+    pub guards: Vec<Guard>,
+    /// Operators declared with `infixl`, `infixr`, `infix` and `prefix`.
+    #[serde(default)]
+    pub operators: Vec<OperatorDecl>,
+    /// Declared with `assoc`, `comm` and `ac`.
+    #[serde(default)]
+    pub theories: Vec<TheoryDecl>,
     pub locations: Vec<Provenance>,
     pub rule_locations: Vec<Location>,
     pub action_locations: Vec<Location>,
+    /// Links the two directions of each `lhs <=> rhs` rule, both ways.
+    #[serde(default)]
+    pub rule_reverses: HashMap<RuleId, RuleId>,
+    /// Suffix of the variables that `fresh(n)` produces in the current rule
+    /// application, see `reserve_fresh_names`.
+    #[serde(default)]
+    pub fresh_counter: usize,
+    /// Set by matching when a `?x` operand of an associative symbol was not
+    /// split because too many operands were left, see `find_matches_with_limits`.
+    #[serde(skip)]
+    pub(crate) split_limit_hit: Cell<bool>,
+    /// Steps taken by the current match, see `MAX_MATCH_ATTEMPTS`.
+    #[serde(skip)]
+    pub(crate) match_attempts: Cell<usize>,
 
     pub outgoing: HashMap<ExprId, Vec<(ExprId, RuleId)>>,
     pub incoming: HashMap<ExprId, Vec<(ExprId, RuleId)>>,
@@ -135,6 +190,9 @@ pub struct Pool {
 
     pub equivalence_groups: Vec<std::collections::HashSet<ExprId>>,
     pub expr_to_group: HashMap<ExprId, EquivalenceGroupId>,
+    /// The group of each canonical id, rebuilt by `rebuild_hash_cons`.
+    #[serde(default)]
+    pub canonical_groups: HashMap<ExprId, EquivalenceGroupId>,
 
     pub equivalence_outgoing: HashMap<EquivalenceGroupId, Vec<(EquivalenceGroupId, RuleId)>>,
     pub equivalence_incoming: HashMap<EquivalenceGroupId, Vec<(EquivalenceGroupId, RuleId)>>,
@@ -156,17 +214,35 @@ impl Pool {
         let mut pool = Self {
             exprs: Vec::new(),
             expr_ends: std::collections::BTreeSet::new(),
+            expr_hashes: Vec::new(),
+            canonical_exprs: Vec::new(),
+            hash_cons: HashMap::new(),
+            interned_roots: HashMap::new(),
             names: Vec::new(),
             name_map: HashMap::new(),
+            numbers: Vec::new(),
+            number_map: HashMap::new(),
+            rationals: Vec::new(),
+            rational_map: HashMap::new(),
+            floats: Vec::new(),
+            float_map: HashMap::new(),
             functions: Vec::new(),
             function_map: HashMap::new(),
             patterns: Vec::new(),
             rules: Vec::new(),
+            rule_index: RuleIndex::default(),
             rulesets: Vec::new(),
             actions: Vec::new(),
+            guards: Vec::new(),
+            operators: Vec::new(),
+            theories: Vec::new(),
             locations: Vec::new(),
             rule_locations: Vec::new(),
             action_locations: Vec::new(),
+            rule_reverses: HashMap::new(),
+            fresh_counter: 0,
+            split_limit_hit: Cell::new(false),
+            match_attempts: Cell::new(0),
 
             outgoing: HashMap::new(),
             incoming: HashMap::new(),
@@ -174,6 +250,7 @@ impl Pool {
 
             equivalence_groups: Vec::new(),
             expr_to_group: HashMap::new(),
+            canonical_groups: HashMap::new(),
 
             equivalence_outgoing: HashMap::new(),
             equivalence_incoming: HashMap::new(),
@@ -195,20 +272,26 @@ impl Pool {
     }
 
     pub fn add_expr(&mut self, node: ExprNode) -> ExprId {
-        let id = self.exprs.len();
+        let id = ExprId::new(self.exprs.len());
         self.exprs.push(node);
-        ExprId::new(id)
+        self.intern_expr_node(id);
+        id
     }
 
     pub fn add_expr_with_provenance(&mut self, node: ExprNode, provenance: Provenance) -> ExprId {
-        let id = self.exprs.len();
+        let id = ExprId::new(self.exprs.len());
         self.exprs.push(node);
         self.locations.push(provenance);
-        ExprId::new(id)
+        self.intern_expr_node(id);
+        id
     }
 
     pub fn mark_expr_end(&mut self, expr_id: ExprId) {
         self.expr_ends.insert(expr_id.0);
+        if self.is_interned(expr_id) {
+            let canonical = self.canonical(expr_id);
+            self.interned_roots.entry(canonical).or_insert(expr_id);
+        }
     }
 
     pub fn find_root(&self, expr_id: ExprId) -> Option<ExprId> {
@@ -234,12 +317,12 @@ impl Pool {
             let candidate_node = &self.exprs[candidate_idx];
 
             match candidate_node {
-                ExprNode::Call { last, arity, .. } | ExprNode::Struct { last, arity, .. } => {
-                    if *arity > 0 {
-                        let first_child_pos = candidate_idx - last;
-                        if first_child_pos <= node_idx && node_idx <= candidate_idx {
-                            return Some(candidate_id);
-                        }
+                ExprNode::Call { last, arity, .. } | ExprNode::Struct { last, arity, .. }
+                    if *arity > 0 =>
+                {
+                    let first_child_pos = candidate_idx - last;
+                    if first_child_pos <= node_idx && node_idx <= candidate_idx {
+                        return Some(candidate_id);
                     }
                 }
                 _ => {}
@@ -248,7 +331,7 @@ impl Pool {
         None
     }
 
-    pub fn ancestors(&self, expr_id: ExprId) -> AncestorIterator {
+    pub fn ancestors(&self, expr_id: ExprId) -> AncestorIterator<'_> {
         AncestorIterator {
             pool: self,
             current: Some(expr_id),
@@ -281,6 +364,48 @@ impl Pool {
         }
     }
 
+    pub fn intern_number(&mut self, value: BigInt) -> NumberId {
+        if self.number_map.len() != self.numbers.len() {
+            self.number_map = (0..self.numbers.len())
+                .map(|idx| (self.numbers[idx].clone(), NumberId::new(idx)))
+                .collect();
+        }
+        if let Some(&id) = self.number_map.get(&value) {
+            id
+        } else {
+            let id = NumberId::new(self.numbers.len());
+            self.numbers.push(value.clone());
+            self.number_map.insert(value, id);
+            id
+        }
+    }
+
+    pub fn intern_rational(&mut self, value: BigRational) -> RationalId {
+        if self.rational_map.len() != self.rationals.len() {
+            self.rational_map = (0..self.rationals.len())
+                .map(|idx| (self.rationals[idx].clone(), RationalId::new(idx)))
+                .collect();
+        }
+        if let Some(&id) = self.rational_map.get(&value) {
+            id
+        } else {
+            let id = RationalId::new(self.rationals.len());
+            self.rationals.push(value.clone());
+            self.rational_map.insert(value, id);
+            id
+        }
+    }
+
+    /// Interns an exact value, as an integer whenever it has no fractional
+    /// part.
+    pub fn intern_exact(&mut self, value: BigRational) -> ExactNumber {
+        if value.is_integer() {
+            ExactNumber::Integer(self.intern_number(value.to_integer()))
+        } else {
+            ExactNumber::Rational(self.intern_rational(value))
+        }
+    }
+
     pub fn intern_function(&mut self, func: Function) -> FunctionId {
         if let Some(&id) = self.function_map.get(&func) {
             id
@@ -305,6 +430,7 @@ impl Pool {
     pub fn add_rule(&mut self, rule: Rule) -> RuleId {
         let id = self.rules.len();
         self.rules.push(rule);
+        self.update_rule_index();
         RuleId::new(id)
     }
 
@@ -312,9 +438,31 @@ impl Pool {
         let rule_id = self.rules.len();
         self.rules.push(rule);
         self.rule_locations.push(location);
+        self.update_rule_index();
         RuleId::new(rule_id)
     }
 
+    /// Records that `forward` and `reverse` are the two directions of one
+    /// bidirectional rule.
+    pub fn link_reverse_rules(&mut self, forward: RuleId, reverse: RuleId) {
+        self.rule_reverses.insert(forward, reverse);
+        self.rule_reverses.insert(reverse, forward);
+    }
+
+    pub fn reverse_rule(&self, rule_id: RuleId) -> Option<RuleId> {
+        self.rule_reverses.get(&rule_id).copied()
+    }
+
+    /// The `(forward, reverse)` rules of the equation `rule_id` belongs to.
+    pub fn rule_equation(&self, rule_id: RuleId) -> Option<(RuleId, RuleId)> {
+        let other = self.reverse_rule(rule_id)?;
+        Some(if rule_id.0 < other.0 {
+            (rule_id, other)
+        } else {
+            (other, rule_id)
+        })
+    }
+
     pub fn get_rule_location(&self, rule_id: RuleId) -> Option<&Location> {
         self.rule_locations.get(rule_id.0)
     }
@@ -325,21 +473,46 @@ impl Pool {
         RulesetId::new(id)
     }
 
+    /// Rules of a ruleset together with those of everything it imports,
+    /// transitively. Imported rules come first and each rule appears once;
+    /// import cycles are cut at the first repeated ruleset.
+    pub fn ruleset_rule_ids(&self, ruleset_id: RulesetId) -> Vec<RuleId> {
+        let mut visited = HashSet::new();
+        let mut rule_ids = Vec::new();
+        self.collect_ruleset_rules(ruleset_id, &mut visited, &mut rule_ids);
+        rule_ids
+    }
+
+    fn collect_ruleset_rules(
+        &self,
+        ruleset_id: RulesetId,
+        visited: &mut HashSet<RulesetId>,
+        rule_ids: &mut Vec<RuleId>,
+    ) {
+        let Some(ruleset) = self.rulesets.get(ruleset_id.0) else {
+            return;
+        };
+        if !visited.insert(ruleset_id) {
+            return;
+        }
+        for &import in &ruleset.imports {
+            self.collect_ruleset_rules(import, visited, rule_ids);
+        }
+        for idx in ruleset.rules_start..ruleset.rules_end.min(self.rules.len()) {
+            let rule_id = RuleId::new(idx);
+            if !rule_ids.contains(&rule_id) {
+                rule_ids.push(rule_id);
+            }
+        }
+    }
+
     pub fn get_ruleset_rules(
         &self,
         ruleset_id: RulesetId,
     ) -> impl Iterator<Item = &crate::ast::Rule> {
-        let start = self
-            .rulesets
-            .get(ruleset_id.0)
-            .map(|r| r.rules_start)
-            .unwrap_or(0);
-        let end = self
-            .rulesets
-            .get(ruleset_id.0)
-            .map(|r| r.rules_end)
-            .unwrap_or(0);
-        (start..end).filter_map(move |i| self.rules.get(i))
+        self.ruleset_rule_ids(ruleset_id)
+            .into_iter()
+            .map(move |rule_id| &self.rules[rule_id.0])
     }
 
     pub fn get_rules_len(&self) -> usize {
@@ -347,11 +520,50 @@ impl Pool {
     }
 
     pub fn get_ruleset_rule_count(&self, ruleset_id: RulesetId) -> usize {
-        if let Some(ruleset) = self.rulesets.get(ruleset_id.0) {
-            ruleset.rules_end - ruleset.rules_start
-        } else {
-            0
+        self.ruleset_rule_ids(ruleset_id).len()
+    }
+
+    /// The most recently registered ruleset with the given name.
+    pub fn find_ruleset_by_name(&self, name: &str) -> Option<RulesetId> {
+        self.rulesets
+            .iter()
+            .rposition(|ruleset| self.display_name(ruleset.name) == name)
+            .map(RulesetId::new)
+    }
+
+    /// The chain of imports leading from `from` to `to`, both included, if
+    /// `from` is `to` or imports it transitively. Adding an import of `from`
+    /// to `to` would close a cycle exactly when this is `Some`.
+    pub fn ruleset_import_path(&self, from: RulesetId, to: RulesetId) -> Option<Vec<RulesetId>> {
+        let mut visited = HashSet::new();
+        let mut path = Vec::new();
+        self.find_import_path(from, to, &mut visited, &mut path)
+            .then_some(path)
+    }
+
+    fn find_import_path(
+        &self,
+        current: RulesetId,
+        to: RulesetId,
+        visited: &mut HashSet<RulesetId>,
+        path: &mut Vec<RulesetId>,
+    ) -> bool {
+        if !visited.insert(current) {
+            return false;
+        }
+        path.push(current);
+        if current == to {
+            return true;
         }
+        if let Some(ruleset) = self.rulesets.get(current.0) {
+            for &import in &ruleset.imports {
+                if self.find_import_path(import, to, visited, path) {
+                    return true;
+                }
+            }
+        }
+        path.pop();
+        false
     }
 
     pub fn add_action(&mut self, action: crate::ast::Action) -> ActionId {
@@ -371,6 +583,59 @@ impl Pool {
         ActionId::new(action_id)
     }
 
+    pub fn add_guard(&mut self, guard: Guard) -> GuardId {
+        let id = self.guards.len();
+        self.guards.push(guard);
+        GuardId::new(id)
+    }
+
+    /// Copies a pattern into the action table with every capture turned into
+    /// a reference to it, so a pattern can serve as the other side's action.
+    /// `None` if the pattern uses `not(...)` or `where`, which no action can
+    /// build.
+    pub fn pattern_to_action(
+        &mut self,
+        pattern_id: PatternId,
+        location: Location,
+    ) -> Option<ActionId> {
+        let start = pattern_id.0 + 1 - self.calculate_tree_size(pattern_id);
+        if self.patterns[start..=pattern_id.0].iter().any(|pattern| {
+            matches!(
+                pattern,
+                Pattern::Not { .. } | Pattern::Where { .. } | Pattern::Distinct { .. }
+            )
+        }) {
+            return None;
+        }
+        let mut action_id = ActionId::new(self.actions.len());
+        for idx in start..=pattern_id.0 {
+            let action = match self.patterns[idx] {
+                Pattern::Number(n) => Action::Number(n),
+                Pattern::Rational(r) => Action::Rational(r),
+                Pattern::Float(f) => Action::Float(f),
+                Pattern::Variable(name)
+                | Pattern::AnyNumber(name)
+                | Pattern::Wildcard(name)
+                | Pattern::Typed { name, .. } => Action::Variable(name),
+                Pattern::Sequence(name) => Action::Splice(name),
+                Pattern::Call { fun, last, arity } => Action::Call { fun, last, arity },
+                Pattern::Struct { name, last, arity } => Action::Struct { name, last, arity },
+                Pattern::VarCallName { var, last, arity } => {
+                    Action::VarCallName { var, last, arity }
+                }
+                Pattern::VarStructName { var, last, arity } => {
+                    Action::VarStructName { var, last, arity }
+                }
+                Pattern::Context { var, last } => Action::Plug { var, last },
+                Pattern::Not { .. } | Pattern::Where { .. } | Pattern::Distinct { .. } => {
+                    return None;
+                }
+            };
+            action_id = self.add_action_with_location(action, location.clone());
+        }
+        Some(action_id)
+    }
+
     pub fn get_action_location(&self, action_id: ActionId) -> Option<&Location> {
         self.action_locations.get(action_id.0)
     }
@@ -378,17 +643,33 @@ impl Pool {
     pub fn reset(&mut self) {
         self.exprs.clear();
         self.expr_ends.clear();
+        self.expr_hashes.clear();
+        self.canonical_exprs.clear();
+        self.hash_cons.clear();
+        self.interned_roots.clear();
         self.names.clear();
         self.name_map.clear();
+        self.numbers.clear();
+        self.number_map.clear();
+        self.rationals.clear();
+        self.rational_map.clear();
+        self.floats.clear();
+        self.float_map.clear();
         self.functions.clear();
         self.function_map.clear();
         self.patterns.clear();
         self.rules.clear();
+        self.rule_index = RuleIndex::default();
         self.rulesets.clear();
         self.actions.clear();
+        self.guards.clear();
+        self.operators.clear();
+        self.theories.clear();
         self.locations.clear();
         self.rule_locations.clear();
         self.action_locations.clear();
+        self.rule_reverses.clear();
+        self.fresh_counter = 0;
 
         self.outgoing.clear();
         self.incoming.clear();
@@ -396,6 +677,7 @@ impl Pool {
 
         self.equivalence_groups.clear();
         self.expr_to_group.clear();
+        self.canonical_groups.clear();
 
         self.equivalence_outgoing.clear();
         self.equivalence_incoming.clear();
@@ -431,6 +713,20 @@ impl Index<NameId> for Pool {
     }
 }
 
+impl Index<NumberId> for Pool {
+    type Output = BigInt;
+    fn index(&self, id: NumberId) -> &Self::Output {
+        &self.numbers[id.0]
+    }
+}
+
+impl Index<RationalId> for Pool {
+    type Output = BigRational;
+    fn index(&self, id: RationalId) -> &Self::Output {
+        &self.rationals[id.0]
+    }
+}
+
 impl Index<FunctionId> for Pool {
     type Output = Function;
     fn index(&self, id: FunctionId) -> &Self::Output {
@@ -466,6 +762,13 @@ impl Index<ActionId> for Pool {
     }
 }
 
+impl Index<GuardId> for Pool {
+    type Output = Guard;
+    fn index(&self, id: GuardId) -> &Self::Output {
+        &self.guards[id.0]
+    }
+}
+
 use std::ops::Range;
 
 impl Index<Range<ExprId>> for Pool {
@@ -482,6 +785,13 @@ impl Index<Range<ActionId>> for Pool {
     }
 }
 
+impl Index<Range<GuardId>> for Pool {
+    type Output = [Guard];
+    fn index(&self, range: Range<GuardId>) -> &Self::Output {
+        &self.guards[range.start.0..range.end.0]
+    }
+}
+
 impl Index<Range<PatternId>> for Pool {
     type Output = [Pattern];
     fn index(&self, range: Range<PatternId>) -> &Self::Output {
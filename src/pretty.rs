@@ -0,0 +1,818 @@
+use crate::ast::*;
+use crate::children::Children;
+use crate::idx::*;
+use crate::operators::BUILTIN_OPERATORS;
+use crate::pool::Pool;
+
+/// Prints nodes as source text for the parsers.
+///
+/// Unlike `DisplayNode`, which brackets every operator, the printer only adds
+/// the parentheses the grammar needs, using the precedence and associativity
+/// of the built-in and declared operators. The text parses back to the same
+/// tree, except for literals the grammar cannot spell: negative numbers,
+/// `-0.0`, infinity and NaN print as the negations and divisions that compute
+/// them, and parse back as those calls. Either way the parsed tree prints as
+/// the same text again.
+pub trait PrettyNode<Id> {
+    /// Prints the node on one line.
+    fn pretty(&self, node_id: Id) -> String {
+        self.pretty_width(node_id, usize::MAX)
+    }
+
+    /// Prints the node, breaking argument lists and operator chains over
+    /// several lines where a line would grow past `width` characters.
+    fn pretty_width(&self, node_id: Id, width: usize) -> String;
+}
+
+impl PrettyNode<ExprId> for Pool {
+    fn pretty_width(&self, node_id: ExprId, width: usize) -> String {
+        layout(self.expr_term(node_id), width)
+    }
+}
+
+impl PrettyNode<PatternId> for Pool {
+    fn pretty_width(&self, node_id: PatternId, width: usize) -> String {
+        layout(self.pattern_term(node_id), width)
+    }
+}
+
+impl PrettyNode<ActionId> for Pool {
+    fn pretty_width(&self, node_id: ActionId, width: usize) -> String {
+        layout(self.action_term(node_id), width)
+    }
+}
+
+impl PrettyNode<GuardId> for Pool {
+    fn pretty_width(&self, node_id: GuardId, width: usize) -> String {
+        layout(self.guard_term(node_id), width)
+    }
+}
+
+impl Pool {
+    /// A rule in ruleset syntax, e.g. `div_self: ?x / ?x => 1 when x != 0`.
+    pub fn rule_to_source(&self, rule_id: RuleId) -> String {
+        let rule = &self[rule_id];
+        format!(
+            "{}: {} => {}{}",
+            self.display_name(rule.name),
+            self.pretty(rule.pattern),
+            self.pretty(rule.action),
+            self.guard_source(rule.guard)
+        )
+    }
+
+    /// Regenerates the source of a ruleset from its stored rules, so that
+    /// parsing the text into an empty pool gives back the same rules. The
+    /// text starts with the pool's operator declarations, and equations are
+    /// written as `<=>` rules again. Imported rulesets are referred to by
+    /// name with `use`, so they must be loaded first.
+    pub fn ruleset_to_source(&self, ruleset_id: RulesetId) -> String {
+        let mut out = self.declarations_to_source();
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&self.ruleset_body_to_source(ruleset_id));
+        out
+    }
+
+    /// The source of every ruleset in the pool, in registration order, which
+    /// `parse_rulesets` reads back into an equivalent pool.
+    pub fn rulesets_to_source(&self) -> String {
+        let mut parts = Vec::new();
+        let declarations = self.declarations_to_source();
+        if !declarations.is_empty() {
+            parts.push(declarations);
+        }
+        parts.extend(
+            (0..self.rulesets.len()).map(|id| self.ruleset_body_to_source(RulesetId::new(id))),
+        );
+        parts.join("\n")
+    }
+
+    /// Operator declarations followed by theory declarations, which may name
+    /// the declared operators.
+    fn declarations_to_source(&self) -> String {
+        let theories = self.theories.iter().map(|theory| {
+            let kind = match (theory.associative, theory.commutative) {
+                (true, true) => "ac",
+                (true, false) => "assoc",
+                (false, _) => "comm",
+            };
+            match theory.identity {
+                Some(identity) => format!(
+                    "{} {} identity {}\n",
+                    kind,
+                    theory.symbol,
+                    self.display_exact(identity)
+                ),
+                None => format!("{} {}\n", kind, theory.symbol),
+            }
+        });
+        self.operators
+            .iter()
+            .map(|op| {
+                let fixity = match op.fixity {
+                    Fixity::InfixLeft => "infixl",
+                    Fixity::InfixRight => "infixr",
+                    Fixity::Infix => "infix",
+                    Fixity::Prefix => "prefix",
+                };
+                format!("{} {} {}\n", fixity, op.precedence, op.symbol)
+            })
+            .chain(theories)
+            .collect()
+    }
+
+    fn ruleset_body_to_source(&self, ruleset_id: RulesetId) -> String {
+        let ruleset = &self[ruleset_id];
+        let mut out = format!("{} {{\n", self.display_name(ruleset.name));
+        for &import in &ruleset.imports {
+            out.push_str(&format!(
+                "  use {};\n",
+                self.display_name(self[import].name)
+            ));
+        }
+
+        let own_rules = ruleset.rules_start..ruleset.rules_end.min(self.rules.len());
+        for idx in own_rules.clone() {
+            let rule_id = RuleId::new(idx);
+            // Both directions of an equation are written as one line, in
+            // place of the forward rule.
+            let equation = self
+                .rule_equation(rule_id)
+                .filter(|(forward, reverse)| {
+                    own_rules.contains(&forward.0) && own_rules.contains(&reverse.0)
+                })
+                .and_then(|(forward, _)| Some((forward, self.equation_to_source(rule_id)?)));
+            let line = match equation {
+                Some((forward, _)) if forward != rule_id => continue,
+                Some((_, equation)) => equation,
+                None => self.rule_to_source(rule_id),
+            };
+            out.push_str("  ");
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// The `name: lhs <=> rhs` form of an equation, if `rule_id` is one
+    /// direction of an equation that parsing it would give back.
+    fn equation_to_source(&self, rule_id: RuleId) -> Option<String> {
+        let (forward, reverse) = self.rule_equation(rule_id)?;
+        let name = self.display_name(self[forward].name);
+        let reverse_name = self.display_name(self[reverse].name);
+        if reverse_name != format!("b_{}", name) || self[forward].guard != self[reverse].guard {
+            return None;
+        }
+        Some(format!(
+            "{}: {} <=> {}{}",
+            name,
+            self.pretty(self[forward].pattern),
+            self.pretty(self[reverse].pattern),
+            self.guard_source(self[forward].guard)
+        ))
+    }
+
+    fn guard_source(&self, guard: Option<GuardId>) -> String {
+        guard
+            .map(|guard| format!(" when {}", self.pretty(guard)))
+            .unwrap_or_default()
+    }
+}
+
+/// A node as it is written, before parentheses are added.
+#[derive(Debug, Clone)]
+enum Term {
+    Atom(String),
+    /// `-x` or `+x`, which apply to a single value.
+    Unary(&'static str, Box<Term>),
+    /// A declared prefix operator, which applies to everything to its right
+    /// that binds more tightly than it.
+    Prefix {
+        op: String,
+        precedence: u8,
+        operand: Box<Term>,
+    },
+    Infix {
+        op: String,
+        fixity: Fixity,
+        precedence: u8,
+        left: Box<Term>,
+        right: Box<Term>,
+    },
+    /// `f(a, b)` or `S{a, b}`.
+    Call {
+        head: String,
+        brace: bool,
+        args: Vec<Term>,
+    },
+    /// A `[...]` compute block of an action.
+    Compute(Box<Term>),
+    Paren(Box<Term>),
+}
+
+/// Where a term is printed: the weakest operator it may expose without
+/// parentheses, the operator printed right after it, and whether only a
+/// single value fits.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    min_precedence: u8,
+    follow: Option<u8>,
+    value_only: bool,
+}
+
+impl Slot {
+    const OPEN: Slot = Slot {
+        min_precedence: 0,
+        follow: None,
+        value_only: false,
+    };
+}
+
+impl Pool {
+    fn expr_term(&self, node_id: ExprId) -> Term {
+        let args = || self.child_terms(node_id, |child| self.expr_term(child));
+        match self[node_id] {
+            ExprNode::Number(n) => self.number_term(n),
+            ExprNode::Rational(r) => self.number_term_text(self.display_rational(r)),
+            ExprNode::Float(f) => self.float_term(f),
+            ExprNode::Variable(name) => Term::Atom(self.display_name(name)),
+            ExprNode::Call { fun, .. } => self.call_term(fun, args()),
+            ExprNode::Struct { name, .. } => Term::Call {
+                head: self.display_name(name),
+                brace: true,
+                args: args(),
+            },
+        }
+    }
+
+    fn pattern_term(&self, node_id: PatternId) -> Term {
+        let args = || self.child_terms(node_id, |child| self.pattern_term(child));
+        match self[node_id] {
+            Pattern::Number(n) => self.number_term(n),
+            Pattern::Rational(r) => self.number_term_text(self.display_rational(r)),
+            Pattern::Float(f) => self.float_term(f),
+            Pattern::Variable(name) => Term::Atom(self.display_name(name)),
+            Pattern::AnyNumber(name) => Term::Atom(format!("#{}", self.display_name(name))),
+            Pattern::Wildcard(name) => Term::Atom(format!("?{}", self.display_name(name))),
+            Pattern::Typed { name, kind } => {
+                Term::Atom(format!("?{}:{}", self.display_name(name), kind))
+            }
+            Pattern::Sequence(name) => Term::Atom(format!("?{}...", self.display_name(name))),
+            Pattern::Call { fun, .. } => self.call_term(fun, args()),
+            Pattern::Struct { name, .. } => Term::Call {
+                head: self.display_name(name),
+                brace: true,
+                args: args(),
+            },
+            Pattern::VarCallName { var, .. } | Pattern::VarStructName { var, .. } => Term::Call {
+                head: format!("?{}", self.display_name(var)),
+                brace: matches!(self[node_id], Pattern::VarStructName { .. }),
+                args: args(),
+            },
+            Pattern::Not { .. } => Term::Call {
+                head: "not".to_string(),
+                brace: false,
+                args: args(),
+            },
+            // Only found at the root of a rule's pattern, and kept on one line.
+            Pattern::Where { .. } => {
+                let mut parts: Vec<_> = self
+                    .children(node_id)
+                    .map(|child| self.pretty(child))
+                    .collect();
+                let pattern = parts.pop().unwrap_or_default();
+                parts.reverse();
+                Term::Atom(format!("{} where {}", pattern, parts.join(", ")))
+            }
+            Pattern::Context { var, .. } => self.plug_term(var, args()),
+            Pattern::Distinct { var, .. } => {
+                let other = self
+                    .children(node_id)
+                    .next()
+                    .map(|child| self.pretty(child));
+                Term::Atom(format!(
+                    "?{} != {}",
+                    self.display_name(var),
+                    other.unwrap_or_default()
+                ))
+            }
+        }
+    }
+
+    fn action_term(&self, node_id: ActionId) -> Term {
+        match self[node_id] {
+            Action::Compute { .. } => Term::Compute(Box::new(self.compute_term(node_id))),
+            _ => self.action_value_term(node_id),
+        }
+    }
+
+    fn action_value_term(&self, node_id: ActionId) -> Term {
+        let args = || self.child_terms(node_id, |child| self.action_term(child));
+        match self[node_id] {
+            Action::Number(n) => self.number_term(n),
+            Action::Rational(r) => self.number_term_text(self.display_rational(r)),
+            Action::Float(f) => self.float_term(f),
+            Action::Variable(name) => Term::Atom(self.display_name(name)),
+            Action::Fresh(name) => Term::Atom(format!("fresh({})", self.display_name(name))),
+            Action::Splice(name) => Term::Atom(format!("{}...", self.display_name(name))),
+            Action::Call { fun, .. } => self.call_term(fun, args()),
+            Action::Struct { name, .. } => Term::Call {
+                head: self.display_name(name),
+                brace: true,
+                args: args(),
+            },
+            Action::VarCallName { var, .. } | Action::VarStructName { var, .. } => Term::Call {
+                head: format!("?{}", self.display_name(var)),
+                brace: matches!(self[node_id], Action::VarStructName { .. }),
+                args: args(),
+            },
+            Action::Plug { var, .. } => self.plug_term(var, args()),
+            Action::Compute { .. } => self.compute_term(node_id),
+        }
+    }
+
+    /// `C[p]` or `C[a]`, whose only child is what fills the hole.
+    fn plug_term(&self, var: NameId, args: Vec<Term>) -> Term {
+        let hole = args
+            .into_iter()
+            .next()
+            .map(|term| layout(term, usize::MAX))
+            .unwrap_or_default();
+        Term::Atom(format!("{}[{}]", self.display_name(var), hole))
+    }
+
+    /// The inside of a compute block, where nested computations are written
+    /// without brackets.
+    fn compute_term(&self, node_id: ActionId) -> Term {
+        let Action::Compute { op, .. } = self[node_id] else {
+            return self.action_value_term(node_id);
+        };
+        let mut args = self.child_terms(node_id, |child| self.compute_term(child));
+        let builtin = match op {
+            ComputeOp::Add => Function::Add,
+            ComputeOp::Subtract => Function::Subtract,
+            ComputeOp::Multiply => Function::Multiply,
+            ComputeOp::Divide => Function::Divide,
+            ComputeOp::Power => Function::Power,
+            ComputeOp::Negate => Function::Negate,
+            // `%` binds like `*`, and comparisons more loosely than `+`, see
+            // compute.pest.
+            ComputeOp::Modulo => return infix_term(op.to_string(), Fixity::InfixLeft, 7, args),
+            ComputeOp::Compare(_) => return infix_term(op.to_string(), Fixity::Infix, 5, args),
+            _ => return call_of(&op.to_string(), args),
+        };
+        builtin_term(builtin, &mut args).unwrap_or_else(|| call_of(&op.to_string(), args))
+    }
+
+    /// Guards are written with the operator precedences of guards.pest:
+    /// comparisons bind more loosely than arithmetic and more tightly than
+    /// `&&`, which binds more tightly than `||`.
+    fn guard_term(&self, guard_id: GuardId) -> Term {
+        let args = self.child_terms(guard_id, |child| self.guard_term(child));
+        let (op, fixity, precedence) = match self[guard_id] {
+            // Guard operands are sums, so a computed comparison needs
+            // parentheses.
+            Guard::Operand(action) => {
+                return match self[action] {
+                    Action::Compute {
+                        op: ComputeOp::Compare(_),
+                        ..
+                    } => Term::Paren(Box::new(self.compute_term(action))),
+                    _ => self.compute_term(action),
+                };
+            }
+            Guard::Predicate { predicate, .. } => return call_of(&predicate.to_string(), args),
+            Guard::Logic {
+                op: LogicOp::Not, ..
+            } => {
+                return match <[Term; 1]>::try_from(args) {
+                    Ok([operand]) => Term::Unary("!", Box::new(operand)),
+                    Err(args) => call_of("!", args),
+                };
+            }
+            Guard::Compare { op, .. } => (op.to_string(), Fixity::Infix, 3),
+            Guard::Logic {
+                op: LogicOp::And, ..
+            } => ("&&".to_string(), Fixity::InfixLeft, 2),
+            Guard::Logic {
+                op: LogicOp::Or, ..
+            } => ("||".to_string(), Fixity::InfixLeft, 1),
+        };
+        infix_term(op, fixity, precedence, args)
+    }
+
+    fn child_terms<Id>(&self, node_id: Id, term: impl Fn(Id) -> Term) -> Vec<Term>
+    where
+        Pool: Children<Id>,
+        Id: Copy + From<usize> + Into<usize>,
+    {
+        let mut args: Vec<_> = self.children(node_id).map(term).collect();
+        args.reverse();
+        args
+    }
+
+    fn number_term(&self, number_id: NumberId) -> Term {
+        self.number_term_text(self.display_number(number_id))
+    }
+
+    /// Infinity and NaN have no literal, so they are printed as the divisions
+    /// that compute them, see `display_float`. They parse back as `Divide`
+    /// calls rather than floats.
+    fn float_term(&self, float_id: FloatId) -> Term {
+        let value = match self.floats.get(float_id.0) {
+            Some(&bits) if !f64::from_bits(bits).is_finite() => f64::from_bits(bits),
+            _ => return self.number_term_text(self.display_float(float_id)),
+        };
+        let numerator = if value.is_nan() { "0.0" } else { "1.0" };
+        let mut args = vec![
+            Term::Atom(numerator.to_string()),
+            Term::Atom("0.0".to_string()),
+        ];
+        let division = builtin_term(Function::Divide, &mut args).expect("`/` is a builtin");
+        if value < 0.0 {
+            Term::Unary("-", Box::new(division))
+        } else {
+            division
+        }
+    }
+
+    /// The grammar has no negative literals, so `-3` is printed as the
+    /// negation it parses to, which is a `Negate` call rather than a number.
+    fn number_term_text(&self, text: String) -> Term {
+        match text.strip_prefix('-') {
+            Some(magnitude) => Term::Unary("-", Box::new(Term::Atom(magnitude.to_string()))),
+            None => Term::Atom(text),
+        }
+    }
+
+    fn call_term(&self, fun: FunctionId, mut args: Vec<Term>) -> Term {
+        let Some(&function) = self.get(fun) else {
+            return call_of(&self.display_function(fun), args);
+        };
+        if let Some(term) = builtin_term(function, &mut args) {
+            return term;
+        }
+        let Some(decl) = self.operator_for_call(fun, args.len()) else {
+            return call_of(&self.display_function(fun), args);
+        };
+        let mut args = args.into_iter();
+        match (decl.fixity, args.next(), args.next()) {
+            (Fixity::Prefix, Some(operand), None) => Term::Prefix {
+                op: decl.symbol.clone(),
+                precedence: decl.precedence,
+                operand: Box::new(operand),
+            },
+            (fixity, Some(left), Some(right)) => Term::Infix {
+                op: decl.symbol.clone(),
+                fixity,
+                precedence: decl.precedence,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            _ => unreachable!("operator_for_call checks the arity"),
+        }
+    }
+}
+
+/// An infix operator over two operands, or a call for any other count.
+fn infix_term(op: String, fixity: Fixity, precedence: u8, args: Vec<Term>) -> Term {
+    match <[Term; 2]>::try_from(args) {
+        Ok([left, right]) => Term::Infix {
+            op,
+            fixity,
+            precedence,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+        Err(args) => call_of(&op, args),
+    }
+}
+
+fn call_of(head: &str, args: Vec<Term>) -> Term {
+    Term::Call {
+        head: head.to_string(),
+        brace: false,
+        args,
+    }
+}
+
+/// Writes a call of a built-in function in operator form. Leaves `args`
+/// alone and returns `None` when the arity does not fit an operator.
+fn builtin_term(function: Function, args: &mut Vec<Term>) -> Option<Term> {
+    match (function, args.len()) {
+        (Function::Negate, 1) => Some(Term::Unary("-", Box::new(args.pop()?))),
+        (Function::Plus, 1) => Some(Term::Unary("+", Box::new(args.pop()?))),
+        (_, 2) => {
+            let &(symbol, _, fixity, precedence) = BUILTIN_OPERATORS
+                .iter()
+                .find(|(_, builtin, ..)| *builtin == function)?;
+            let right = args.pop()?;
+            let left = args.pop()?;
+            Some(Term::Infix {
+                op: symbol.to_string(),
+                fixity,
+                precedence,
+                left: Box::new(left),
+                right: Box::new(right),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Adds the parentheses `term` needs to parse back as itself in `slot`.
+fn parenthesize(term: Term, slot: Slot) -> Term {
+    let fits = match &term {
+        Term::Atom(_) | Term::Call { .. } | Term::Compute(_) | Term::Paren(_) => true,
+        Term::Unary(..) => true,
+        // The operand of a prefix operator extends over every operator that
+        // binds more tightly, so it must not be followed by one.
+        Term::Prefix { precedence, .. } => {
+            !slot.value_only && slot.follow.is_none_or(|follow| follow <= *precedence)
+        }
+        Term::Infix { precedence, .. } => !slot.value_only && *precedence >= slot.min_precedence,
+    };
+    if !fits {
+        return Term::Paren(Box::new(parenthesize(term, Slot::OPEN)));
+    }
+
+    match term {
+        Term::Unary(op, operand) => {
+            let operand_slot = Slot {
+                value_only: true,
+                ..Slot::OPEN
+            };
+            Term::Unary(op, Box::new(parenthesize(*operand, operand_slot)))
+        }
+        Term::Prefix {
+            op,
+            precedence,
+            operand,
+        } => {
+            let operand_slot = Slot {
+                min_precedence: precedence + 1,
+                follow: slot.follow,
+                value_only: false,
+            };
+            Term::Prefix {
+                op,
+                precedence,
+                operand: Box::new(parenthesize(*operand, operand_slot)),
+            }
+        }
+        Term::Infix {
+            op,
+            fixity,
+            precedence,
+            left,
+            right,
+        } => {
+            let left = parenthesize_operand(*left, fixity, precedence, Side::Left, slot);
+            let right = parenthesize_operand(*right, fixity, precedence, Side::Right, slot);
+            Term::Infix {
+                op,
+                fixity,
+                precedence,
+                left: Box::new(left),
+                right: Box::new(right),
+            }
+        }
+        Term::Call { head, brace, args } => Term::Call {
+            head,
+            brace,
+            args: args
+                .into_iter()
+                .map(|arg| parenthesize(arg, Slot::OPEN))
+                .collect(),
+        },
+        Term::Compute(inner) => Term::Compute(Box::new(parenthesize(*inner, Slot::OPEN))),
+        Term::Paren(inner) => Term::Paren(Box::new(parenthesize(*inner, Slot::OPEN))),
+        Term::Atom(_) => term,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// Parenthesizes one side of an infix operator. An operand at the same
+/// precedence needs no parentheses on the left if it is left-associative, and
+/// on the right if the operator is right-associative.
+fn parenthesize_operand(
+    operand: Term,
+    fixity: Fixity,
+    precedence: u8,
+    side: Side,
+    slot: Slot,
+) -> Term {
+    let same_level_fits = match side {
+        Side::Left => matches!(
+            operand,
+            Term::Infix {
+                fixity: Fixity::InfixLeft,
+                ..
+            }
+        ),
+        Side::Right => fixity == Fixity::InfixRight,
+    };
+    let operand_slot = Slot {
+        min_precedence: if same_level_fits {
+            precedence
+        } else {
+            precedence + 1
+        },
+        follow: match side {
+            Side::Left => Some(precedence),
+            Side::Right => slot.follow,
+        },
+        value_only: false,
+    };
+    parenthesize(operand, operand_slot)
+}
+
+fn layout(term: Term, width: usize) -> String {
+    let term = parenthesize(term, Slot::OPEN);
+    let measure = Measure::of(&term);
+    let mut out = String::new();
+    write_term(&term, &measure, width, 0, &mut out);
+    out
+}
+
+const INDENT: usize = 4;
+
+/// The length of a term printed on one line, and of each of its subterms,
+/// so that laying out a tree measures every node once.
+struct Measure {
+    width: usize,
+    /// Whether the one-line text starts with an operator character, see
+    /// `prefix_separator`.
+    operator_first: bool,
+    children: Vec<Measure>,
+}
+
+impl Measure {
+    fn of(term: &Term) -> Measure {
+        let children: Vec<_> = match term {
+            Term::Atom(_) => Vec::new(),
+            Term::Unary(_, operand)
+            | Term::Prefix { operand, .. }
+            | Term::Compute(operand)
+            | Term::Paren(operand) => vec![Measure::of(operand)],
+            Term::Infix { left, right, .. } => vec![Measure::of(left), Measure::of(right)],
+            Term::Call { args, .. } => args.iter().map(Measure::of).collect(),
+        };
+        let (width, operator_first) = match term {
+            Term::Atom(text) => (text.len(), starts_with_operator(text)),
+            Term::Unary(op, _) => (op.len() + children[0].width, true),
+            Term::Prefix { op, .. } => (
+                op.len() + prefix_separator(&children[0]).len() + children[0].width,
+                true,
+            ),
+            Term::Infix { op, .. } => (
+                children[0].width + op.len() + 2 + children[1].width,
+                children[0].operator_first,
+            ),
+            Term::Call { head, .. } => {
+                let args: usize = children.iter().map(|arg| arg.width).sum();
+                let separators = 2 * children.len().saturating_sub(1);
+                (
+                    head.len() + 2 + args + separators,
+                    starts_with_operator(head),
+                )
+            }
+            Term::Compute(_) | Term::Paren(_) => (2 + children[0].width, false),
+        };
+        Measure {
+            width,
+            operator_first,
+            children,
+        }
+    }
+}
+
+/// Writes `term` on the current line if it fits in `width`, otherwise breaks
+/// its argument lists or operator chain and indents the continuation lines.
+fn write_term(term: &Term, measure: &Measure, width: usize, indent: usize, out: &mut String) {
+    let column = out.len() - out.rfind('\n').map_or(0, |newline| newline + 1);
+    if column + measure.width <= width {
+        write_flat(term, measure, out);
+        return;
+    }
+
+    let children = &measure.children;
+    match term {
+        Term::Atom(text) => out.push_str(text),
+        Term::Unary(op, operand) => {
+            out.push_str(op);
+            write_term(operand, &children[0], width, indent, out);
+        }
+        Term::Prefix { op, operand, .. } => {
+            out.push_str(op);
+            out.push_str(prefix_separator(&children[0]));
+            write_term(operand, &children[0], width, indent, out);
+        }
+        Term::Infix {
+            op, left, right, ..
+        } => {
+            write_term(left, &children[0], width, indent, out);
+            out.push('\n');
+            push_indent(indent + INDENT, out);
+            out.push_str(op);
+            out.push(' ');
+            write_term(right, &children[1], width, indent + INDENT, out);
+        }
+        Term::Call { head, brace, args } => {
+            let (open, close) = if *brace { ('{', '}') } else { ('(', ')') };
+            out.push_str(head);
+            out.push(open);
+            for (i, (arg, arg_measure)) in args.iter().zip(children).enumerate() {
+                out.push('\n');
+                push_indent(indent + INDENT, out);
+                write_term(arg, arg_measure, width, indent + INDENT, out);
+                if i + 1 < args.len() {
+                    out.push(',');
+                }
+            }
+            out.push('\n');
+            push_indent(indent, out);
+            out.push(close);
+        }
+        Term::Compute(inner) => {
+            out.push('[');
+            write_term(inner, &children[0], width, indent, out);
+            out.push(']');
+        }
+        Term::Paren(inner) => {
+            out.push('(');
+            write_term(inner, &children[0], width, indent, out);
+            out.push(')');
+        }
+    }
+}
+
+fn push_indent(indent: usize, out: &mut String) {
+    out.extend(std::iter::repeat_n(' ', indent));
+}
+
+/// Writes `term` on one line.
+fn write_flat(term: &Term, measure: &Measure, out: &mut String) {
+    let children = &measure.children;
+    match term {
+        Term::Atom(text) => out.push_str(text),
+        Term::Unary(op, operand) => {
+            out.push_str(op);
+            write_flat(operand, &children[0], out);
+        }
+        Term::Prefix { op, operand, .. } => {
+            out.push_str(op);
+            out.push_str(prefix_separator(&children[0]));
+            write_flat(operand, &children[0], out);
+        }
+        Term::Infix {
+            op, left, right, ..
+        } => {
+            write_flat(left, &children[0], out);
+            out.push(' ');
+            out.push_str(op);
+            out.push(' ');
+            write_flat(right, &children[1], out);
+        }
+        Term::Call { head, brace, args } => {
+            let (open, close) = if *brace { ('{', '}') } else { ('(', ')') };
+            out.push_str(head);
+            out.push(open);
+            for (i, (arg, arg_measure)) in args.iter().zip(children).enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_flat(arg, arg_measure, out);
+            }
+            out.push(close);
+        }
+        Term::Compute(inner) => {
+            out.push('[');
+            write_flat(inner, &children[0], out);
+            out.push(']');
+        }
+        Term::Paren(inner) => {
+            out.push('(');
+            write_flat(inner, &children[0], out);
+            out.push(')');
+        }
+    }
+}
+
+/// Operators are lexed greedily, so `~` before `-x` or `~x` needs a space to
+/// stay a separate token.
+fn prefix_separator(operand: &Measure) -> &'static str {
+    if operand.operator_first { " " } else { "" }
+}
+
+fn starts_with_operator(text: &str) -> bool {
+    text.starts_with(|c: char| "+-*/^<>|&~!@$%.=".contains(c))
+}
@@ -0,0 +1,124 @@
+use crate::ast::*;
+use crate::children::Children;
+use crate::idx::*;
+use crate::pool::Pool;
+use std::collections::HashMap;
+
+/// The head a node must have for a pattern to match it, taken from the
+/// pattern's root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum RuleKey {
+    Number(NumberId),
+    Rational(RationalId),
+    Float(FloatId),
+    Variable(NameId),
+    /// `?x:var`
+    AnyVariable,
+    /// `#a`, which matches every number.
+    AnyNumber,
+    Call(FunctionId),
+    Struct(NameId),
+    /// `?f(..)`
+    AnyCall,
+    /// `?S{..}`
+    AnyStruct,
+}
+
+/// Rules grouped by the head of their pattern, so `find_matches` only tries
+/// the rules that could match a node. The arity is left out of the key,
+/// since sequence arguments and theories let a pattern match other arities.
+#[derive(Debug, Clone, Default)]
+pub struct RuleIndex {
+    keyed: HashMap<RuleKey, Vec<RuleId>>,
+    /// Rules whose pattern is a `?x`, `?x:ground`, `?x:nonnum`, `not(..)` or
+    /// `C[..]` and may match any kind of node.
+    unkeyed: Vec<RuleId>,
+    /// How many of the pool's rules are indexed.
+    len: usize,
+}
+
+impl Pool {
+    /// Indexes the rules added since the last update. Rules added through
+    /// `add_rule` are indexed right away; this catches up after rules were
+    /// pushed directly or the pool was deserialized.
+    pub fn update_rule_index(&mut self) {
+        for idx in self.rule_index.len..self.rules.len() {
+            let rule_id = RuleId::new(idx);
+            match self.pattern_key(self.rules[idx].pattern) {
+                Some(key) => self.rule_index.keyed.entry(key).or_default().push(rule_id),
+                None => self.rule_index.unkeyed.push(rule_id),
+            }
+        }
+        self.rule_index.len = self.rules.len();
+    }
+
+    /// Indexes every rule from scratch, for when rules were removed.
+    pub fn rebuild_rule_index(&mut self) {
+        self.rule_index = RuleIndex::default();
+        self.update_rule_index();
+    }
+
+    /// The rules that may match `node_id`, in rule order. Falls back to every
+    /// rule while the index is out of date.
+    pub fn candidate_rules(&self, node_id: ExprId) -> Vec<RuleId> {
+        if self.rule_index.len != self.rules.len() {
+            return (0..self.rules.len()).map(RuleId::new).collect();
+        }
+        let Some(node) = self.get(node_id) else {
+            return Vec::new();
+        };
+
+        let mut keys = match *node {
+            ExprNode::Number(n) => vec![RuleKey::Number(n), RuleKey::AnyNumber],
+            ExprNode::Rational(r) => vec![RuleKey::Rational(r), RuleKey::AnyNumber],
+            ExprNode::Float(f) => vec![RuleKey::Float(f), RuleKey::AnyNumber],
+            ExprNode::Variable(v) => vec![RuleKey::Variable(v), RuleKey::AnyVariable],
+            ExprNode::Call { fun, .. } => vec![RuleKey::Call(fun), RuleKey::AnyCall],
+            ExprNode::Struct { name, .. } => vec![RuleKey::Struct(name), RuleKey::AnyStruct],
+        };
+        // A call pattern of a symbol with an identity also matches a lone
+        // operand, e.g. `#a * ?x` matches `y` with `ac * identity 1`.
+        keys.extend(
+            self.theories
+                .iter()
+                .filter(|theory| theory.identity.is_some())
+                .map(|theory| RuleKey::Call(theory.function)),
+        );
+
+        let mut rule_ids = self.rule_index.unkeyed.clone();
+        for key in keys {
+            if let Some(rules) = self.rule_index.keyed.get(&key) {
+                rule_ids.extend_from_slice(rules);
+            }
+        }
+        rule_ids.sort_unstable_by_key(|rule_id| rule_id.0);
+        rule_ids.dedup();
+        rule_ids
+    }
+
+    fn pattern_key(&self, pattern_id: PatternId) -> Option<RuleKey> {
+        match *self.get(pattern_id)? {
+            Pattern::Number(n) => Some(RuleKey::Number(n)),
+            Pattern::Rational(r) => Some(RuleKey::Rational(r)),
+            Pattern::Float(f) => Some(RuleKey::Float(f)),
+            Pattern::Variable(v) => Some(RuleKey::Variable(v)),
+            Pattern::AnyNumber(_) => Some(RuleKey::AnyNumber),
+            Pattern::Call { fun, .. } => Some(RuleKey::Call(fun)),
+            Pattern::Struct { name, .. } => Some(RuleKey::Struct(name)),
+            Pattern::VarCallName { .. } => Some(RuleKey::AnyCall),
+            Pattern::VarStructName { .. } => Some(RuleKey::AnyStruct),
+            Pattern::Typed { kind, .. } => match kind {
+                CaptureKind::Variable => Some(RuleKey::AnyVariable),
+                CaptureKind::Call => Some(RuleKey::AnyCall),
+                CaptureKind::Struct => Some(RuleKey::AnyStruct),
+                CaptureKind::Ground | CaptureKind::NonNumber => None,
+            },
+            Pattern::Where { .. } => self.pattern_key(self.constrained_pattern(pattern_id)),
+            Pattern::Wildcard(_)
+            | Pattern::Sequence(_)
+            | Pattern::Not { .. }
+            | Pattern::Distinct { .. }
+            | Pattern::Context { .. } => None,
+        }
+    }
+}
@@ -1,16 +1,86 @@
 use crate::children::Children;
+use crate::numeric::Numeric;
+use crate::theories::{MAX_MATCH_ATTEMPTS, MAX_SPLIT_OPERANDS};
 use crate::{
-    Action, ActionId, ComputeOp, ExprId, ExprNode, FunctionId, NameId, Pattern, PatternId, Pool,
-    RuleId,
+    Action, ActionId, CaptureKind, CompareOp, ComputeOp, DisplayNode, ExactNumber, ExprId,
+    ExprNode, Function, FunctionId, Guard, GuardId, GuardPredicate, LogicOp, NameId, Pattern,
+    PatternId, Pool, RuleId, RulesetId,
 };
-use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CapturedValue {
     Expression(ExprId),
     Function(FunctionId),
     StructName(NameId),
+    /// Several operands of an associative symbol, standing for `fun` applied
+    /// to them from left to right, or for the identity of `fun` if there are
+    /// none. See theories.rs.
+    Operands {
+        fun: FunctionId,
+        args: Vec<ExprId>,
+    },
+    /// The consecutive arguments matched by `?xs...`.
+    Sequence(Vec<ExprId>),
+    /// The term matched by `C[p]`, with the path of argument indices from
+    /// `root` down to the subterm `p` matched, its hole.
+    Context {
+        root: ExprId,
+        path: Vec<usize>,
+    },
+}
+
+/// What `captured_eq` compares a capture by: canonical ids, and operands of
+/// a commutative symbol in sorted order. Equal captures have equal keys.
+#[derive(PartialEq, Eq, Hash)]
+enum CaptureKey {
+    Expr(ExprId),
+    Operands(FunctionId, Vec<ExprId>),
+    Function(FunctionId),
+    StructName(NameId),
+    Sequence(Vec<ExprId>),
+    Context(ExprId, Vec<usize>),
+}
+
+/// Why a rule could not be applied to a match.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RewriteError {
+    DivisionByZero,
+    /// Zero raised to a negative power.
+    NegativeExponent,
+    /// An exact power whose exponent does not fit in 32 bits or whose result
+    /// would exceed `MAX_POW_BITS`.
+    Overflow,
+    /// A variable that the match did not capture.
+    UnboundVariable(NameId),
+    /// An operand of the wrong kind, e.g. a capture that is not a number or
+    /// a fraction passed to `gcd`.
+    TypeMismatch(String),
+}
+
+impl fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RewriteError::DivisionByZero => write!(f, "division by zero"),
+            RewriteError::NegativeExponent => write!(f, "zero raised to a negative power"),
+            RewriteError::Overflow => write!(f, "exponent is too large"),
+            RewriteError::UnboundVariable(_) => write!(f, "variable is not captured"),
+            RewriteError::TypeMismatch(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RewriteError {}
+
+/// A guard operand: a captured expression, captured operands that do not
+/// compute to a number, or a computed number.
+enum GuardValue {
+    Expr(ExprId),
+    Operands(CapturedValue),
+    Number(Numeric),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -22,29 +92,323 @@ pub struct Match {
     pub captures: HashMap<NameId, CapturedValue>,
 }
 
+/// Distinct matches `find_matches` keeps per rule and node. Modulo
+/// associativity and commutativity `?x + ?y` matches a sum of `n` operands
+/// in `2^n - 2` ways; past this many the rest are dropped and reported as
+/// `MatchLimit::TooManyMatches`.
+pub const MAX_MATCHES_PER_RULE: usize = 64;
+
+/// A rule whose matches at a node were not all found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchLimit {
+    /// More than `MAX_MATCHES_PER_RULE` matches, or more ways to try than
+    /// `MAX_MATCH_ATTEMPTS`; only the matches found first are kept.
+    TooManyMatches { offset: ExprId, rule_id: RuleId },
+    /// A `?x` operand faced more than `MAX_SPLIT_OPERANDS` operands of an
+    /// associative symbol, so the matches splitting them are missing.
+    TooManyOperands { offset: ExprId, rule_id: RuleId },
+}
+
+/// The rules that matching and search may use.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum RuleScope {
+    #[default]
+    All,
+    /// The rules of these rulesets, including the ones they import.
+    Rulesets(Vec<RulesetId>),
+    Rules(Vec<RuleId>),
+}
+
 impl Pool {
     pub fn find_matches(&self, root: ExprId) -> Vec<Match> {
+        self.find_matches_among(root, None)
+    }
+
+    /// Like `find_matches`, but only with the rules in `scope`.
+    pub fn find_matches_in(&self, root: ExprId, scope: &RuleScope) -> Vec<Match> {
+        self.find_matches_among(root, self.rules_in_scope(scope).as_ref())
+    }
+
+    /// Like `find_matches_in`, together with the rules whose matches were cut
+    /// short at some node.
+    pub fn find_matches_with_limits(
+        &self,
+        root: ExprId,
+        scope: &RuleScope,
+    ) -> (Vec<Match>, Vec<MatchLimit>) {
         let mut matches = Vec::new();
-        self.find_matches_recursive(root, &mut matches);
+        let mut limits = Vec::new();
+        let allowed = self.rules_in_scope(scope);
+        self.find_matches_recursive(root, allowed.as_ref(), &mut matches, &mut limits);
+        (matches, limits)
+    }
+
+    /// The rules in `scope`, or `None` if it is every rule.
+    pub fn rules_in_scope(&self, scope: &RuleScope) -> Option<HashSet<RuleId>> {
+        match scope {
+            RuleScope::All => None,
+            RuleScope::Rulesets(rulesets) => Some(
+                rulesets
+                    .iter()
+                    .flat_map(|&ruleset_id| self.ruleset_rule_ids(ruleset_id))
+                    .collect(),
+            ),
+            RuleScope::Rules(rules) => Some(rules.iter().copied().collect()),
+        }
+    }
+
+    /// Matches of the rules in `allowed`, or of every rule if it is `None`.
+    pub fn find_matches_among(
+        &self,
+        root: ExprId,
+        allowed: Option<&HashSet<RuleId>>,
+    ) -> Vec<Match> {
+        let mut matches = Vec::new();
+        self.find_matches_recursive(root, allowed, &mut matches, &mut Vec::new());
         matches
     }
 
-    fn find_matches_recursive(&self, node_id: ExprId, matches: &mut Vec<Match>) {
+    /// Like `find_matches`, but leaves out matches that `apply_rule` would
+    /// reject, e.g. `#a / #b => [a / b]` on `1 / 0`.
+    pub fn find_applicable_matches(&self, root: ExprId) -> Vec<Match> {
+        self.find_applicable_matches_in(root, &RuleScope::All)
+    }
+
+    /// Like `find_applicable_matches`, but only with the rules in `scope`.
+    pub fn find_applicable_matches_in(&self, root: ExprId, scope: &RuleScope) -> Vec<Match> {
+        let mut matches = self.find_matches_in(root, scope);
+        matches.retain(|m| {
+            self.check_action(self[m.rule_id].action, &m.captures)
+                .is_ok()
+        });
+        matches
+    }
+
+    /// Reports the error that building `action_id` for these captures would
+    /// run into, without adding anything to the pool.
+    pub fn check_action(
+        &self,
+        action_id: ActionId,
+        captures: &HashMap<NameId, CapturedValue>,
+    ) -> Result<(), RewriteError> {
+        match self[action_id] {
+            Action::Compute { .. } => self.evaluate_compute(action_id, captures).map(drop),
+            Action::VarCallName { var, .. } => {
+                self.captured_function(var, captures)?;
+                self.check_children(action_id, captures)
+            }
+            Action::VarStructName { var, .. } => {
+                self.captured_struct_name(var, captures)?;
+                self.check_children(action_id, captures)
+            }
+            Action::Call { .. } | Action::Struct { .. } => self.check_children(action_id, captures),
+            Action::Splice(var) => self.captured_sequence(var, captures).map(drop),
+            Action::Plug { var, .. } => {
+                self.captured_context(var, captures)?;
+                self.check_children(action_id, captures)
+            }
+            Action::Variable(var) => match captures.get(&var) {
+                Some(CapturedValue::Sequence(_)) => Err(RewriteError::TypeMismatch(format!(
+                    "{0} is a sequence, splice it with {0}...",
+                    self.display_name(var)
+                ))),
+                Some(CapturedValue::Context { .. }) => Err(RewriteError::TypeMismatch(format!(
+                    "{0} is a context, fill its hole with {0}[...]",
+                    self.display_name(var)
+                ))),
+                _ => Ok(()),
+            },
+            Action::Number(_) | Action::Rational(_) | Action::Float(_) | Action::Fresh(_) => Ok(()),
+        }
+    }
+
+    fn check_children(
+        &self,
+        action_id: ActionId,
+        captures: &HashMap<NameId, CapturedValue>,
+    ) -> Result<(), RewriteError> {
+        self.children(action_id)
+            .try_for_each(|child| self.check_action(child, captures))
+    }
+
+    fn captured_function(
+        &self,
+        var: NameId,
+        captures: &HashMap<NameId, CapturedValue>,
+    ) -> Result<FunctionId, RewriteError> {
+        match captures.get(&var) {
+            Some(CapturedValue::Function(fun)) => Ok(*fun),
+            Some(_) => Err(RewriteError::TypeMismatch(format!(
+                "{} is not bound to a function name",
+                self.display_name(var)
+            ))),
+            None => Err(RewriteError::UnboundVariable(var)),
+        }
+    }
+
+    fn captured_struct_name(
+        &self,
+        var: NameId,
+        captures: &HashMap<NameId, CapturedValue>,
+    ) -> Result<NameId, RewriteError> {
+        match captures.get(&var) {
+            Some(CapturedValue::StructName(name)) => Ok(*name),
+            Some(_) => Err(RewriteError::TypeMismatch(format!(
+                "{} is not bound to a struct name",
+                self.display_name(var)
+            ))),
+            None => Err(RewriteError::UnboundVariable(var)),
+        }
+    }
+
+    fn captured_sequence<'a>(
+        &self,
+        var: NameId,
+        captures: &'a HashMap<NameId, CapturedValue>,
+    ) -> Result<&'a [ExprId], RewriteError> {
+        match captures.get(&var) {
+            Some(CapturedValue::Sequence(items)) => Ok(items),
+            Some(_) => Err(RewriteError::TypeMismatch(format!(
+                "{} is not bound to a sequence",
+                self.display_name(var)
+            ))),
+            None => Err(RewriteError::UnboundVariable(var)),
+        }
+    }
+
+    fn captured_context<'a>(
+        &self,
+        var: NameId,
+        captures: &'a HashMap<NameId, CapturedValue>,
+    ) -> Result<(ExprId, &'a [usize]), RewriteError> {
+        match captures.get(&var) {
+            Some(CapturedValue::Context { root, path }) => Ok((*root, path)),
+            Some(_) => Err(RewriteError::TypeMismatch(format!(
+                "{} is not bound to a context",
+                self.display_name(var)
+            ))),
+            None => Err(RewriteError::UnboundVariable(var)),
+        }
+    }
+
+    /// Shows a capture as in the explorer's match details.
+    pub fn display_captured_value(&self, value: &CapturedValue) -> String {
+        match value {
+            CapturedValue::Expression(expr_id) => self.display_with_children(*expr_id),
+            CapturedValue::Function(fun_id) => format!("fn:{}", self.display_function(*fun_id)),
+            CapturedValue::StructName(name_id) => format!("struct:{}", self[*name_id]),
+            CapturedValue::Sequence(items) => items
+                .iter()
+                .map(|&item| self.display_with_children(item))
+                .collect::<Vec<_>>()
+                .join(", "),
+            CapturedValue::Operands { fun, args } => {
+                let Some(theory) = self.theory(*fun) else {
+                    return format!("{}(..)", self.display_function(*fun));
+                };
+                match (args.is_empty(), theory.identity) {
+                    (true, Some(identity)) => self.display_exact(identity),
+                    _ => args
+                        .iter()
+                        .map(|&arg| self.display_with_children(arg))
+                        .collect::<Vec<_>>()
+                        .join(&format!(" {} ", theory.symbol)),
+                }
+            }
+            CapturedValue::Context { root, path } => format!(
+                "{}, hole at {}",
+                self.display_with_children(*root),
+                self.display_with_children(self.context_hole(*root, path))
+            ),
+        }
+    }
+
+    /// A message for `limit` that names the rule and the node.
+    pub fn describe_match_limit(&self, limit: &MatchLimit) -> String {
+        match *limit {
+            MatchLimit::TooManyMatches { offset, rule_id } => format!(
+                "{} matches {} in too many ways, only the first found are listed (at most {} matches after {} attempts)",
+                self.display_name(self[rule_id].name),
+                self.display_with_children(offset),
+                MAX_MATCHES_PER_RULE,
+                MAX_MATCH_ATTEMPTS
+            ),
+            MatchLimit::TooManyOperands { offset, rule_id } => format!(
+                "{} may match {} in more ways: a capture was not split between more than {} operands",
+                self.display_name(self[rule_id].name),
+                self.display_with_children(offset),
+                MAX_SPLIT_OPERANDS
+            ),
+        }
+    }
+
+    /// A message for `error` that names the variables it refers to.
+    pub fn describe_rewrite_error(&self, error: &RewriteError) -> String {
+        match error {
+            RewriteError::UnboundVariable(var) => {
+                format!("{} is not captured", self.display_name(*var))
+            }
+            _ => error.to_string(),
+        }
+    }
+
+    fn find_matches_recursive(
+        &self,
+        node_id: ExprId,
+        allowed: Option<&HashSet<RuleId>>,
+        matches: &mut Vec<Match>,
+        limits: &mut Vec<MatchLimit>,
+    ) {
         let root = node_id;
         let mut stack = vec![(node_id)];
 
         while let Some(current_node_id) = stack.pop() {
             if let Some(node) = self.get(current_node_id) {
-                for (rule_id, rule) in self.rules.iter().enumerate() {
-                    let mut captures = HashMap::new();
-                    if self.pattern_matches(rule.pattern, current_node_id, &mut captures) {
+                for rule_id in self.candidate_rules(current_node_id) {
+                    if allowed.is_some_and(|allowed| !allowed.contains(&rule_id)) {
+                        continue;
+                    }
+                    let rule = self[rule_id];
+                    // A context pattern looks at every depth by itself.
+                    if current_node_id != root && self.is_context_pattern(rule.pattern) {
+                        continue;
+                    }
+                    self.split_limit_hit.set(false);
+                    let solutions =
+                        self.pattern_matches_all(rule.pattern, current_node_id, &HashMap::new());
+                    if self.split_limit_hit.get() {
+                        limits.push(MatchLimit::TooManyOperands {
+                            offset: current_node_id,
+                            rule_id,
+                        });
+                    }
+                    let mut cut_short = self.match_attempts_exhausted();
+                    let mut kept = 0;
+                    for captures in solutions {
+                        if rule
+                            .guard
+                            .is_some_and(|guard| !self.guard_holds(guard, &captures))
+                        {
+                            continue;
+                        }
+                        if kept == MAX_MATCHES_PER_RULE {
+                            cut_short = true;
+                            break;
+                        }
+                        kept += 1;
                         matches.push(Match {
                             root,
                             offset: current_node_id,
-                            rule_id: RuleId::new(rule_id),
+                            rule_id,
                             captures,
                         });
                     }
+                    if cut_short {
+                        limits.push(MatchLimit::TooManyMatches {
+                            offset: current_node_id,
+                            rule_id,
+                        });
+                    }
                 }
 
                 match node {
@@ -59,35 +423,94 @@ impl Pool {
         }
     }
 
+    /// Matches `pattern_id` against `node_id`, adding the captures of the
+    /// first match found. Calls of symbols with a declared theory match
+    /// modulo that theory, see `pattern_matches_all`.
     pub fn pattern_matches(
         &self,
         pattern_id: PatternId,
         node_id: ExprId,
         captures: &mut HashMap<NameId, CapturedValue>,
     ) -> bool {
+        self.match_attempts.set(0);
+        match self
+            .match_pattern(pattern_id, node_id, captures.clone())
+            .into_iter()
+            .next()
+        {
+            Some(found) => {
+                *captures = found;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every distinct way `pattern_id` matches `node_id`, each extending
+    /// `captures`. Without declared theories there is at most one; modulo
+    /// commutativity and associativity there can be several, e.g. `?x + ?y`
+    /// matches `a + b` with `x = a` and with `x = b` if `+` is commutative.
+    /// The search gives up after `MAX_MATCH_ATTEMPTS` steps.
+    pub fn pattern_matches_all(
+        &self,
+        pattern_id: PatternId,
+        node_id: ExprId,
+        captures: &HashMap<NameId, CapturedValue>,
+    ) -> Vec<HashMap<NameId, CapturedValue>> {
+        self.match_attempts.set(0);
+        let mut seen = HashSet::new();
+        self.match_pattern(pattern_id, node_id, captures.clone())
+            .into_iter()
+            .filter(|found| seen.insert(self.captures_key(found)))
+            .collect()
+    }
+
+    pub(crate) fn match_pattern(
+        &self,
+        pattern_id: PatternId,
+        node_id: ExprId,
+        mut captures: HashMap<NameId, CapturedValue>,
+    ) -> Vec<HashMap<NameId, CapturedValue>> {
         let (pattern, node) = (self[pattern_id], self[node_id]);
-        match (pattern, node) {
+        match pattern {
+            Pattern::Not { .. } => return self.match_not(pattern_id, node_id, captures),
+            Pattern::Where { .. } => return self.match_where(pattern_id, node_id, captures),
+            Pattern::Context { .. } => return self.match_context(pattern_id, node_id, captures),
+            _ => {}
+        }
+        if let Pattern::Call { fun, .. } = pattern
+            && let Some(theory) = self.theory(fun)
+            && self.sequence_arguments(pattern_id) == 0
+        {
+            return self.match_theory_call(pattern_id, node_id, theory, captures);
+        }
+
+        let matched = match (pattern, node) {
             (Pattern::Number(p_num), ExprNode::Number(n_num)) => p_num == n_num,
+            (Pattern::Rational(p_num), ExprNode::Rational(n_num)) => p_num == n_num,
+            (Pattern::Float(p_num), ExprNode::Float(n_num)) => p_num == n_num,
             (Pattern::Variable(p_var_id), ExprNode::Variable(n_var_id)) => p_var_id == n_var_id,
 
-            (Pattern::AnyNumber(capture_id), ExprNode::Number(_))
-                if !captures.contains_key(&capture_id) =>
-            {
-                captures.insert(capture_id, CapturedValue::Expression(node_id));
-                true
-            }
-            (Pattern::Wildcard(wildcard_id), _) if !captures.contains_key(&wildcard_id) => {
-                captures.insert(wildcard_id, CapturedValue::Expression(node_id));
-                true
-            }
-            (Pattern::AnyNumber(wildcard_id), ExprNode::Number(_))
-            | (Pattern::Wildcard(wildcard_id), _) => {
-                if let Some(CapturedValue::Expression(registered_id)) = captures.get(&wildcard_id) {
-                    self.expr_eq(*registered_id, node_id)
-                } else {
-                    false
+            (Pattern::Typed { kind, .. }, _) if !self.has_kind(node_id, kind) => false,
+            (
+                Pattern::AnyNumber(capture_id),
+                ExprNode::Number(_) | ExprNode::Rational(_) | ExprNode::Float(_),
+            )
+            | (Pattern::Wildcard(capture_id), _)
+            | (
+                Pattern::Typed {
+                    name: capture_id, ..
+                },
+                _,
+            ) => match captures.get(&capture_id) {
+                Some(registered) => {
+                    self.captured_eq(registered, &CapturedValue::Expression(node_id))
                 }
-            }
+                None => {
+                    captures.insert(capture_id, CapturedValue::Expression(node_id));
+                    true
+                }
+            },
 
             (
                 Pattern::Call {
@@ -100,18 +523,7 @@ impl Pool {
                     arity: n_len,
                     ..
                 },
-            ) => {
-                if p_fun != n_fun || p_len != n_len {
-                    return false;
-                }
-                let pattern_children = self.children(pattern_id);
-                let node_children = self.children(node_id);
-
-                pattern_children
-                    .into_iter()
-                    .zip(node_children)
-                    .all(|(p_child, n_child)| self.pattern_matches(p_child, n_child, captures))
-            }
+            ) => p_fun == n_fun && self.arity_fits(pattern_id, p_len, n_len),
 
             (
                 Pattern::Struct {
@@ -124,18 +536,7 @@ impl Pool {
                     arity: n_len,
                     ..
                 },
-            ) => {
-                if p_fun != n_fun || p_len != n_len {
-                    return false;
-                }
-                let pattern_children = self.children(pattern_id);
-                let node_children = self.children(node_id);
-
-                pattern_children
-                    .into_iter()
-                    .zip(node_children)
-                    .all(|(p_child, n_child)| self.pattern_matches(p_child, n_child, captures))
-            }
+            ) => p_fun == n_fun && self.arity_fits(pattern_id, p_len, n_len),
 
             (
                 Pattern::VarCallName {
@@ -149,26 +550,16 @@ impl Pool {
                     ..
                 },
             ) => {
-                if p_len != n_len {
-                    return false;
-                }
-                if let std::collections::hash_map::Entry::Vacant(e) = captures.entry(p_var) {
-                    e.insert(CapturedValue::Function(n_fun));
-                } else if let Some(CapturedValue::Function(captured_fun)) = captures.get(&p_var) {
-                    if captured_fun != &n_fun {
-                        return false;
+                self.arity_fits(pattern_id, p_len, n_len)
+                    && match captures.entry(p_var) {
+                        std::collections::hash_map::Entry::Vacant(e) => {
+                            e.insert(CapturedValue::Function(n_fun));
+                            true
+                        }
+                        std::collections::hash_map::Entry::Occupied(e) => {
+                            e.get() == &CapturedValue::Function(n_fun)
+                        }
                     }
-                } else {
-                    return false;
-                }
-
-                let pattern_children = self.children(pattern_id);
-                let node_children = self.children(node_id);
-
-                pattern_children
-                    .into_iter()
-                    .zip(node_children)
-                    .all(|(p_child, n_child)| self.pattern_matches(p_child, n_child, captures))
             }
 
             (
@@ -183,52 +574,415 @@ impl Pool {
                     ..
                 },
             ) => {
-                if p_len != n_len {
-                    return false;
+                self.arity_fits(pattern_id, p_len, n_len)
+                    && match captures.entry(p_var) {
+                        std::collections::hash_map::Entry::Vacant(e) => {
+                            e.insert(CapturedValue::StructName(n_name));
+                            true
+                        }
+                        std::collections::hash_map::Entry::Occupied(e) => {
+                            e.get() == &CapturedValue::StructName(n_name)
+                        }
+                    }
+            }
+
+            _ => false,
+        };
+        if !matched {
+            return Vec::new();
+        }
+
+        if self.sequence_arguments(pattern_id) > 0 {
+            let mut p_children: Vec<_> = self.children(pattern_id).collect();
+            let mut n_children: Vec<_> = self.children(node_id).collect();
+            p_children.reverse();
+            n_children.reverse();
+            let mut found = Vec::new();
+            self.match_arguments(&p_children, &n_children, captures, &mut found);
+            return found;
+        }
+
+        // Calls and structs whose heads matched still need every child to
+        // match, and a child may match in several ways.
+        let mut found = vec![captures];
+        for (p_child, n_child) in self.children(pattern_id).zip(self.children(node_id)) {
+            found = found
+                .into_iter()
+                .flat_map(|captures| self.match_pattern(p_child, n_child, captures))
+                .collect();
+            if found.is_empty() {
+                break;
+            }
+        }
+        found
+    }
+
+    /// Whether `node_id` is accepted by a `?x:kind` capture.
+    fn has_kind(&self, node_id: ExprId, kind: CaptureKind) -> bool {
+        match (kind, self[node_id]) {
+            (CaptureKind::Variable, ExprNode::Variable(_))
+            | (CaptureKind::Call, ExprNode::Call { .. })
+            | (CaptureKind::Struct, ExprNode::Struct { .. }) => true,
+            (CaptureKind::Ground, _) => {
+                let start = node_id.0 + 1 - self.length(node_id);
+                !self.exprs[start..=node_id.0]
+                    .iter()
+                    .any(|node| matches!(node, ExprNode::Variable(_)))
+            }
+            (CaptureKind::NonNumber, node) => !matches!(
+                node,
+                ExprNode::Number(_) | ExprNode::Rational(_) | ExprNode::Float(_)
+            ),
+            _ => false,
+        }
+    }
+
+    /// The number of `?xs...` arguments of a call or struct pattern.
+    fn sequence_arguments(&self, pattern_id: PatternId) -> usize {
+        self.children(pattern_id)
+            .filter(|&child| matches!(self[child], Pattern::Sequence(_)))
+            .count()
+    }
+
+    /// Whether a call or struct pattern with `p_len` arguments can match a
+    /// node with `n_len` arguments. Each `?xs...` argument stands for any
+    /// number of them, including none.
+    fn arity_fits(&self, pattern_id: PatternId, p_len: usize, n_len: usize) -> bool {
+        match self.sequence_arguments(pattern_id) {
+            0 => p_len == n_len,
+            sequences => n_len + sequences >= p_len,
+        }
+    }
+
+    /// Matches argument patterns against the arguments of a node, both left
+    /// to right, where each `?xs...` takes a run of consecutive arguments.
+    fn match_arguments(
+        &self,
+        patterns: &[PatternId],
+        args: &[ExprId],
+        captures: HashMap<NameId, CapturedValue>,
+        found: &mut Vec<HashMap<NameId, CapturedValue>>,
+    ) {
+        let Some((&first, rest)) = patterns.split_first() else {
+            if args.is_empty() {
+                found.push(captures);
+            }
+            return;
+        };
+        let Pattern::Sequence(name) = self[first] else {
+            if let Some((&arg, others)) = args.split_first() {
+                for captures in self.match_pattern(first, arg, captures) {
+                    self.match_arguments(rest, others, captures, found);
                 }
-                if let std::collections::hash_map::Entry::Vacant(e) = captures.entry(p_var) {
-                    e.insert(CapturedValue::StructName(n_name));
-                } else if let Some(CapturedValue::StructName(captured_name)) = captures.get(&p_var)
-                {
-                    if captured_name != &n_name {
-                        return false;
+            }
+            return;
+        };
+
+        let lengths = match captures.get(&name) {
+            // A sequence used twice must take the same arguments both times
+            Some(CapturedValue::Sequence(items)) => {
+                let len = items.len();
+                let same = len <= args.len()
+                    && self.captured_eq(
+                        &CapturedValue::Sequence(items.clone()),
+                        &CapturedValue::Sequence(args[..len].to_vec()),
+                    );
+                if same { len..len + 1 } else { 0..0 }
+            }
+            Some(_) => 0..0,
+            None => {
+                let fixed = rest
+                    .iter()
+                    .filter(|&&pattern| !matches!(self[pattern], Pattern::Sequence(_)))
+                    .count();
+                0..args.len().saturating_sub(fixed) + 1
+            }
+        };
+        for len in lengths {
+            let mut captures = captures.clone();
+            captures.insert(name, CapturedValue::Sequence(args[..len].to_vec()));
+            self.match_arguments(rest, &args[len..], captures, found);
+        }
+    }
+
+    /// Whether two captured values stand for the same thing. Operand lists
+    /// and calls of a symbol with a declared theory are compared modulo that
+    /// theory at the top level, so `a + b` equals `b + a` if `+` is
+    /// commutative; their operands are compared syntactically.
+    pub fn captured_eq(&self, a: &CapturedValue, b: &CapturedValue) -> bool {
+        match (a, b) {
+            (CapturedValue::Expression(x), CapturedValue::Expression(y)) => {
+                if self.expr_eq(*x, *y) {
+                    return true;
+                }
+                let ExprNode::Call { fun, .. } = self[*x] else {
+                    return false;
+                };
+                self.theory(fun).is_some_and(|theory| {
+                    match (
+                        self.captured_operands(a, theory),
+                        self.captured_operands(b, theory),
+                    ) {
+                        (Some(a), Some(b)) => self.operands_eq(theory, &a, &b),
+                        _ => false,
                     }
-                } else {
+                })
+            }
+            (CapturedValue::Function(a), CapturedValue::Function(b)) => a == b,
+            (CapturedValue::StructName(a), CapturedValue::StructName(b)) => a == b,
+            (CapturedValue::Sequence(a), CapturedValue::Sequence(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(&x, &y)| self.expr_eq(x, y))
+            }
+            (
+                CapturedValue::Context { root: a, path },
+                CapturedValue::Context {
+                    root: b,
+                    path: other,
+                },
+            ) => path == other && self.context_eq(*a, *b, path),
+            (CapturedValue::Operands { fun, .. }, _) | (_, CapturedValue::Operands { fun, .. }) => {
+                let Some(theory) = self.theory(*fun) else {
                     return false;
+                };
+                match (
+                    self.captured_operands(a, theory),
+                    self.captured_operands(b, theory),
+                ) {
+                    (Some(a), Some(b)) => self.operands_eq(theory, &a, &b),
+                    _ => false,
                 }
+            }
+            _ => false,
+        }
+    }
 
-                let pattern_children = self.children(pattern_id);
-                let node_children = self.children(node_id);
+    fn captures_key(&self, captures: &HashMap<NameId, CapturedValue>) -> Vec<(NameId, CaptureKey)> {
+        let mut key: Vec<_> = captures
+            .iter()
+            .map(|(&name, value)| (name, self.capture_key(value)))
+            .collect();
+        key.sort_by_key(|&(name, _)| name.0);
+        key
+    }
 
-                pattern_children
-                    .into_iter()
-                    .zip(node_children)
-                    .all(|(p_child, n_child)| self.pattern_matches(p_child, n_child, captures))
+    fn capture_key(&self, value: &CapturedValue) -> CaptureKey {
+        let theory = match value {
+            CapturedValue::Expression(expr_id) => match self[*expr_id] {
+                ExprNode::Call { fun, .. } => self.theory(fun).filter(|theory| theory.associative),
+                _ => None,
+            },
+            CapturedValue::Operands { fun, .. } => self.theory(*fun),
+            _ => None,
+        };
+        if let Some(theory) = theory
+            && let Some(operands) = self.captured_operands(value, theory)
+        {
+            let mut operands: Vec<_> = operands
+                .into_iter()
+                .map(|operand| self.canonical(operand))
+                .collect();
+            if let [operand] = operands[..] {
+                return CaptureKey::Expr(operand);
             }
+            if theory.commutative {
+                operands.sort_by_key(|operand| operand.0);
+            }
+            return CaptureKey::Operands(theory.function, operands);
+        }
+        match value {
+            CapturedValue::Expression(expr_id) => CaptureKey::Expr(self.canonical(*expr_id)),
+            CapturedValue::Function(fun) => CaptureKey::Function(*fun),
+            CapturedValue::StructName(name) => CaptureKey::StructName(*name),
+            CapturedValue::Operands { fun, args } => {
+                CaptureKey::Operands(*fun, args.iter().map(|&arg| self.canonical(arg)).collect())
+            }
+            CapturedValue::Sequence(items) => {
+                CaptureKey::Sequence(items.iter().map(|&item| self.canonical(item)).collect())
+            }
+            CapturedValue::Context { root, path } => {
+                CaptureKey::Context(self.canonical(*root), path.clone())
+            }
+        }
+    }
 
+    /// Evaluates a `when` clause against the captures of a match. A guard
+    /// whose operands cannot be evaluated, e.g. `[a / 0] > 1`, does not hold.
+    pub fn guard_holds(
+        &self,
+        guard_id: GuardId,
+        captures: &HashMap<NameId, CapturedValue>,
+    ) -> bool {
+        let children: Vec<_> = self.children(guard_id).collect();
+        match self[guard_id] {
+            Guard::Operand(_) => false,
+            Guard::Logic { op, .. } => match op {
+                LogicOp::And => children
+                    .iter()
+                    .all(|&child| self.guard_holds(child, captures)),
+                LogicOp::Or => children
+                    .iter()
+                    .any(|&child| self.guard_holds(child, captures)),
+                LogicOp::Not => !children
+                    .iter()
+                    .all(|&child| self.guard_holds(child, captures)),
+            },
+            Guard::Predicate { predicate, .. } => {
+                let Some(value) = children
+                    .first()
+                    .and_then(|&child| self.guard_operand(child, captures))
+                else {
+                    return false;
+                };
+                match (predicate, value) {
+                    (GuardPredicate::IsNumber, GuardValue::Number(_)) => true,
+                    (GuardPredicate::IsNumber, GuardValue::Expr(expr_id)) => {
+                        self.numeric_value(expr_id).is_some()
+                    }
+                    (GuardPredicate::IsVariable, GuardValue::Expr(expr_id)) => {
+                        matches!(self.get(expr_id), Some(ExprNode::Variable(_)))
+                    }
+                    (_, GuardValue::Operands(_)) => false,
+                    (GuardPredicate::IsVariable, GuardValue::Number(_)) => false,
+                }
+            }
+            Guard::Compare { op, .. } => {
+                // Children come right to left.
+                let (Some(&right), Some(&left)) = (children.first(), children.get(1)) else {
+                    return false;
+                };
+                let (Some(left), Some(right)) = (
+                    self.guard_operand(left, captures),
+                    self.guard_operand(right, captures),
+                ) else {
+                    return false;
+                };
+                self.compare_guard_values(op, left, right)
+            }
+        }
+    }
+
+    fn guard_operand(
+        &self,
+        guard_id: GuardId,
+        captures: &HashMap<NameId, CapturedValue>,
+    ) -> Option<GuardValue> {
+        let Guard::Operand(action_id) = self[guard_id] else {
+            return None;
+        };
+        match self[action_id] {
+            Action::Variable(var_id) => match captures.get(&var_id)? {
+                CapturedValue::Expression(expr_id) => Some(GuardValue::Expr(*expr_id)),
+                operands @ CapturedValue::Operands { .. } => {
+                    Some(self.evaluate_compute(action_id, captures).map_or_else(
+                        |_| GuardValue::Operands(operands.clone()),
+                        GuardValue::Number,
+                    ))
+                }
+                _ => None,
+            },
+            _ => self
+                .evaluate_compute(action_id, captures)
+                .ok()
+                .map(GuardValue::Number),
+        }
+    }
+
+    fn compare_guard_values(&self, op: CompareOp, left: GuardValue, right: GuardValue) -> bool {
+        let as_number = |value: &GuardValue| match value {
+            GuardValue::Number(number) => Some(number.clone()),
+            GuardValue::Expr(expr_id) => self.numeric_value(*expr_id),
+            GuardValue::Operands(_) => None,
+        };
+
+        if let (Some(a), Some(b)) = (as_number(&left), as_number(&right)) {
+            return a.compare(op, &b);
+        }
+
+        // Non-numeric operands are only equal if they are the same tree
+        let captured = |value| match value {
+            GuardValue::Expr(expr_id) => Some(CapturedValue::Expression(expr_id)),
+            GuardValue::Operands(operands) => Some(operands),
+            GuardValue::Number(_) => None,
+        };
+        let equal = match (captured(left), captured(right)) {
+            (Some(a), Some(b)) => self.captured_eq(&a, &b),
+            _ => false,
+        };
+        match op {
+            CompareOp::Equal => equal,
+            CompareOp::NotEqual => !equal,
             _ => false,
         }
     }
 
-    pub fn apply_rule(&mut self, match_: &Match) -> Option<ExprId> {
+    /// Evaluates a compute expression over the captures of a match without
+    /// adding anything to the pool. Captures may be bound to any expression
+    /// built from numbers and the arithmetic functions, e.g. `2 * 3`.
+    pub fn evaluate_compute(
+        &self,
+        action_id: ActionId,
+        captures: &HashMap<NameId, CapturedValue>,
+    ) -> Result<Numeric, RewriteError> {
+        match self[action_id] {
+            Action::Number(_) | Action::Rational(_) | Action::Float(_) => self
+                .action_numeric_value(action_id)
+                .ok_or_else(|| RewriteError::TypeMismatch("unknown number".to_string())),
+            Action::Variable(var_id) => match captures.get(&var_id) {
+                Some(CapturedValue::Expression(expr_id)) => {
+                    self.evaluate_numeric_expr(*expr_id, captures)
+                }
+                Some(CapturedValue::Operands { fun, args }) => {
+                    self.evaluate_operands(*fun, args, captures)
+                }
+                Some(_) => Err(RewriteError::TypeMismatch(format!(
+                    "{} is not bound to an expression",
+                    self.display_name(var_id)
+                ))),
+                None => Err(RewriteError::UnboundVariable(var_id)),
+            },
+            Action::Compute { op, .. } => {
+                let children: Vec<_> = self.children(action_id).collect();
+                // Only the chosen branch of a conditional is evaluated, so
+                // `if(b == 0, 0, a / b)` has a value for every `b`.
+                if let (ComputeOp::If, [otherwise, then, condition]) = (op, children.as_slice()) {
+                    let branch = if self.evaluate_compute(*condition, captures)?.is_true() {
+                        *then
+                    } else {
+                        *otherwise
+                    };
+                    return self.evaluate_compute(branch, captures);
+                }
+                let args = children
+                    .into_iter()
+                    .rev()
+                    .map(|child| self.evaluate_compute(child, captures))
+                    .collect::<Result<Vec<_>, _>>()?;
+                compute_operation(op, &args)
+            }
+            _ => Err(RewriteError::TypeMismatch(format!(
+                "{} cannot be computed",
+                self.display_with_children(action_id)
+            ))),
+        }
+    }
+
+    /// Rewrites the matched subexpression and returns the new root. Nothing
+    /// is added to the pool if the action cannot be built. The new root is a
+    /// full copy of the old one with the match replaced, see `add_expr_tree`.
+    pub fn apply_rule(&mut self, match_: &Match) -> Result<ExprId, RewriteError> {
         let rule = self[match_.rule_id];
+        self.check_action(rule.action, &match_.captures)?;
+        self.reserve_fresh_names(rule.action);
 
         let mut replacement_vec = Vec::new();
-        self.build_action_simple(rule.action, &match_.captures, &mut replacement_vec);
-
-        if replacement_vec.is_empty() {
-            return None;
-        }
+        self.build_action_simple(rule.action, &match_.captures, &mut replacement_vec)?;
 
         if match_.root == match_.offset {
-            for (node, prov) in replacement_vec.drain(..) {
-                self.exprs.push(node);
-                self.locations.push(prov);
-            }
-            let new_root = ExprId(self.exprs.len() - 1);
-            self.mark_expr_end(new_root);
+            let new_root = self
+                .add_expr_tree(replacement_vec)
+                .expect("every action builds at least one node");
             self.add_transformation(match_.root, new_root, match_.rule_id);
-            return Some(new_root);
+            return Ok(new_root);
         }
 
         let mut root_vec: Vec<(ExprNode, crate::pool::Provenance)> = Vec::new();
@@ -250,16 +1004,13 @@ impl Pool {
             self.fix_indices_after_splice(&mut root_vec, target_start, size_delta);
         }
 
-        for (node, prov) in root_vec.drain(..) {
-            self.exprs.push(node);
-            self.locations.push(prov);
-        }
-        let new_root = ExprId(self.exprs.len() - 1);
-        self.mark_expr_end(new_root);
+        let new_root = self
+            .add_expr_tree(root_vec)
+            .expect("every action builds at least one node");
 
         self.add_transformation(match_.root, new_root, match_.rule_id);
 
-        Some(new_root)
+        Ok(new_root)
     }
 
     fn fix_indices_after_splice(
@@ -270,15 +1021,13 @@ impl Pool {
     ) {
         for (i, (node, _)) in expr_vec.iter_mut().enumerate() {
             match node {
-                ExprNode::Call { last, .. } | ExprNode::Struct { last, .. } => {
-                    if *last <= i {
-                        let first_child_pos = i - *last;
-                        if first_child_pos < splice_start && i >= splice_start {
-                            if size_delta > 0 {
-                                *last += size_delta as usize;
-                            } else {
-                                *last = last.saturating_sub((-size_delta) as usize);
-                            }
+                ExprNode::Call { last, .. } | ExprNode::Struct { last, .. } if *last <= i => {
+                    let first_child_pos = i - *last;
+                    if first_child_pos < splice_start && i >= splice_start {
+                        if size_delta > 0 {
+                            *last += size_delta as usize;
+                        } else {
+                            *last = last.saturating_sub((-size_delta) as usize);
                         }
                     }
                 }
@@ -292,7 +1041,7 @@ impl Pool {
         action_id: ActionId,
         captures: &HashMap<NameId, CapturedValue>,
         output: &mut Vec<(ExprNode, crate::pool::Provenance)>,
-    ) {
+    ) -> Result<(), RewriteError> {
         let action = self[action_id];
 
         let provenance = crate::pool::Provenance::Rule {
@@ -306,125 +1055,137 @@ impl Pool {
                 output.push((ExprNode::Number(n), provenance));
             }
 
-            Action::Variable(var_id) => {
-                if let Some(CapturedValue::Expression(expr_id)) = captures.get(&var_id) {
+            Action::Rational(r) => {
+                output.push((ExprNode::Rational(r), provenance));
+            }
+
+            Action::Float(f) => {
+                output.push((ExprNode::Float(f), provenance));
+            }
+
+            Action::Variable(var_id) => match captures.get(&var_id) {
+                Some(CapturedValue::Expression(expr_id)) => {
                     self.copy_expression_to_vec(*expr_id, output);
-                } else {
-                    output.push((ExprNode::Variable(var_id), provenance));
                 }
+                Some(CapturedValue::Operands { fun, args }) => {
+                    self.copy_operands_to_vec(*fun, args, output, provenance)?;
+                }
+                _ => output.push((ExprNode::Variable(var_id), provenance)),
+            },
+
+            Action::Fresh(base) => {
+                let name = self.fresh_name(base);
+                let name = self.intern_string(name);
+                output.push((ExprNode::Variable(name), provenance));
             }
 
-            Action::Call { fun, arity, .. } => {
-                let start_pos = output.len();
-                let children: Box<[_]> = self.children(action_id).collect();
-                for child_id in children.into_iter().rev() {
-                    self.build_action_simple(child_id, captures, output);
+            // A spliced sequence contributes all of its items to the
+            // enclosing call or struct, see `build_arguments`.
+            Action::Splice(var) => {
+                for &item in self.captured_sequence(var, captures)? {
+                    self.copy_expression_to_vec(item, output);
                 }
-                let last = if arity > 0 {
-                    output.len() - start_pos
-                } else {
-                    0
-                };
+            }
+
+            Action::Call { fun, .. } => {
+                let (last, arity) = self.build_arguments(action_id, captures, output)?;
                 output.push((ExprNode::Call { fun, last, arity }, provenance));
             }
 
-            Action::Struct { name, arity, .. } => {
-                let start_pos = output.len();
-                let children: Box<_> = self.children(action_id).collect();
-                for child_id in children.into_iter().rev() {
-                    self.build_action_simple(child_id, captures, output);
-                }
-                let last = if arity > 0 {
-                    output.len() - start_pos
-                } else {
-                    0
-                };
+            Action::Struct { name, .. } => {
+                let (last, arity) = self.build_arguments(action_id, captures, output)?;
                 output.push((ExprNode::Struct { name, last, arity }, provenance));
             }
 
-            Action::VarCallName { var, arity, .. } => {
-                if let Some(CapturedValue::Function(fun)) = captures.get(&var) {
-                    let start_pos = output.len();
-                    let children: Box<_> = self.children(action_id).collect();
-                    for child_id in children.into_iter().rev() {
-                        self.build_action_simple(child_id, captures, output);
-                    }
-                    let last = if arity > 0 {
-                        output.len() - start_pos
-                    } else {
-                        0
-                    };
-                    output.push((
-                        ExprNode::Call {
-                            fun: *fun,
-                            last,
-                            arity,
-                        },
-                        provenance,
-                    ));
-                }
+            Action::VarCallName { var, .. } => {
+                let fun = self.captured_function(var, captures)?;
+                let (last, arity) = self.build_arguments(action_id, captures, output)?;
+                output.push((ExprNode::Call { fun, last, arity }, provenance));
             }
 
-            Action::VarStructName { var, arity, .. } => {
-                if let Some(CapturedValue::StructName(name)) = captures.get(&var) {
-                    let start_pos = output.len();
-                    let children: Box<_> = self.children(action_id).collect();
-                    for child_id in children.into_iter().rev() {
-                        self.build_action_simple(child_id, captures, output);
-                    }
-                    let last = if arity > 0 {
-                        output.len() - start_pos
-                    } else {
-                        0
-                    };
-                    output.push((
-                        ExprNode::Struct {
-                            name: *name,
-                            last,
-                            arity,
-                        },
-                        provenance,
-                    ));
-                }
+            Action::VarStructName { var, .. } => {
+                let name = self.captured_struct_name(var, captures)?;
+                let (last, arity) = self.build_arguments(action_id, captures, output)?;
+                output.push((ExprNode::Struct { name, last, arity }, provenance));
             }
 
-            Action::Compute { op, arity, .. } => {
-                let children: Box<_> = self.children(action_id).collect();
-                if children.len() == arity {
-                    let mut args = Vec::new();
-                    for child_id in children {
-                        let child_action = self[child_id];
-                        match child_action {
-                            Action::Number(n) => {
-                                args.push(n);
-                            }
-                            Action::Variable(var_id) => {
-                                if let Some(CapturedValue::Expression(expr_id)) =
-                                    captures.get(&var_id)
-                                {
-                                    if let Some(value) =
-                                        self.evaluate_numeric_expr(*expr_id, captures)
-                                    {
-                                        args.push(value);
-                                    } else {
-                                        return;
-                                    }
-                                } else {
-                                    return;
-                                }
-                            }
-                            _ => return,
-                        }
-                    }
-                    if let Some(result) = self.compute_operation_simple(op, &args) {
-                        output.push((ExprNode::Number(result), provenance));
-                    }
-                }
+            Action::Plug { var, .. } => {
+                let (root, path) = self.captured_context(var, captures)?;
+                let path = path.to_vec();
+                let hole = self
+                    .children(action_id)
+                    .next()
+                    .expect("a plug action has one child");
+                self.build_plugged(root, &path, hole, captures, output)?;
+            }
+
+            Action::Compute { .. } => {
+                let value = self.evaluate_compute(action_id, captures)?;
+                let result = self.intern_numeric(value);
+                output.push((result, provenance));
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the arguments of a call or struct action and returns the `last`
+    /// offset and arity of the node they belong to. The arity differs from
+    /// the action's if it splices sequences.
+    fn build_arguments(
+        &mut self,
+        action_id: ActionId,
+        captures: &HashMap<NameId, CapturedValue>,
+        output: &mut Vec<(ExprNode, crate::pool::Provenance)>,
+    ) -> Result<(usize, usize), RewriteError> {
+        let start_pos = output.len();
+        let children: Box<[_]> = self.children(action_id).collect();
+        let mut arity = 0;
+        for child_id in children.into_iter().rev() {
+            arity += match self[child_id] {
+                Action::Splice(var) => self.captured_sequence(var, captures)?.len(),
+                _ => 1,
+            };
+            self.build_action_simple(child_id, captures, output)?;
+        }
+        let last = if arity > 0 {
+            output.len() - start_pos
+        } else {
+            0
+        };
+        Ok((last, arity))
+    }
+
+    /// Advances `fresh_counter` until every `fresh(n)` in the action names a
+    /// variable the pool has not seen, so each application gets new ones.
+    /// Within one application, `fresh(n)` always names the same variable.
+    fn reserve_fresh_names(&mut self, action_id: ActionId) {
+        let bases: Vec<_> = self
+            .get_full_slice(action_id)
+            .iter()
+            .filter_map(|action| match action {
+                Action::Fresh(base) => Some(*base),
+                _ => None,
+            })
+            .collect();
+        if bases.is_empty() {
+            return;
+        }
+        loop {
+            self.fresh_counter += 1;
+            if bases
+                .iter()
+                .all(|&base| !self.name_map.contains_key(&self.fresh_name(base)))
+            {
+                return;
             }
         }
     }
 
-    fn copy_expression_to_vec(
+    fn fresh_name(&self, base: NameId) -> String {
+        format!("{}_{}", self.display_name(base), self.fresh_counter)
+    }
+
+    pub(crate) fn copy_expression_to_vec(
         &self,
         expr_id: ExprId,
         output: &mut Vec<(ExprNode, crate::pool::Provenance)>,
@@ -438,35 +1199,156 @@ impl Pool {
         }
     }
 
-    fn compute_operation_simple(&self, op: ComputeOp, args: &[i32]) -> Option<i32> {
-        match op {
-            ComputeOp::Add => Some(args.iter().sum()),
-            ComputeOp::Subtract if args.len() == 2 => Some(args[0] - args[1]),
-            ComputeOp::Multiply => Some(args.iter().product()),
-            ComputeOp::Divide if args.len() == 2 && args[1] != 0 => Some(args[0] / args[1]),
-            ComputeOp::Power if args.len() == 2 && args[1] >= 0 => {
-                Some(args[0].pow(args[1] as u32))
-            }
-            ComputeOp::Negate if args.len() == 1 => Some(-args[0]),
-            _ => None,
+    /// The compute operation that evaluates calls of `fun`, if any.
+    fn function_compute_op(&self, fun: FunctionId) -> Option<ComputeOp> {
+        match self.get(fun)? {
+            Function::Add | Function::Plus => Some(ComputeOp::Add),
+            Function::Subtract => Some(ComputeOp::Subtract),
+            Function::Multiply => Some(ComputeOp::Multiply),
+            Function::Divide => Some(ComputeOp::Divide),
+            Function::Power => Some(ComputeOp::Power),
+            Function::Negate => Some(ComputeOp::Negate),
+            Function::Custom(name) => ComputeOp::from_function_name(&self.display_name(*name)),
         }
     }
 
+    fn theory_identity(&self, fun: FunctionId) -> Result<ExactNumber, RewriteError> {
+        self.theory(fun)
+            .and_then(|theory| theory.identity)
+            .ok_or_else(|| {
+                RewriteError::TypeMismatch(format!(
+                    "{} has no identity",
+                    self.display_function(fun)
+                ))
+            })
+    }
+
+    /// Evaluates captured operands as `fun` applied to them from left to
+    /// right.
+    fn evaluate_operands(
+        &self,
+        fun: FunctionId,
+        args: &[ExprId],
+        captures: &HashMap<NameId, CapturedValue>,
+    ) -> Result<Numeric, RewriteError> {
+        let mut values = args
+            .iter()
+            .map(|&arg| self.evaluate_numeric_expr(arg, captures));
+        let Some(first) = values.next() else {
+            return Ok(self.exact_value(self.theory_identity(fun)?));
+        };
+        let op = self.function_compute_op(fun).ok_or_else(|| {
+            RewriteError::TypeMismatch(format!("{} cannot be computed", self.display_function(fun)))
+        })?;
+        values.try_fold(first?, |acc, value| compute_operation(op, &[acc, value?]))
+    }
+
+    /// Copies captured operands as a left-nested chain of `fun` calls, or the
+    /// identity of `fun` if there are none.
+    fn copy_operands_to_vec(
+        &self,
+        fun: FunctionId,
+        args: &[ExprId],
+        output: &mut Vec<(ExprNode, crate::pool::Provenance)>,
+        provenance: crate::pool::Provenance,
+    ) -> Result<(), RewriteError> {
+        let Some((&first, rest)) = args.split_first() else {
+            output.push((self.theory_identity(fun)?.into(), provenance));
+            return Ok(());
+        };
+        let start_pos = output.len();
+        self.copy_expression_to_vec(first, output);
+        for &arg in rest {
+            self.copy_expression_to_vec(arg, output);
+            let last = output.len() - start_pos;
+            output.push((
+                ExprNode::Call {
+                    fun,
+                    last,
+                    arity: 2,
+                },
+                provenance.clone(),
+            ));
+        }
+        Ok(())
+    }
+
     fn evaluate_numeric_expr(
         &self,
         expr_id: ExprId,
         captures: &HashMap<NameId, CapturedValue>,
-    ) -> Option<i32> {
-        match self.get(expr_id)? {
-            ExprNode::Number(n) => Some(*n),
+    ) -> Result<Numeric, RewriteError> {
+        let not_a_number = || {
+            RewriteError::TypeMismatch(format!(
+                "{} is not a number",
+                self.display_with_children(expr_id)
+            ))
+        };
+        match self.get(expr_id).ok_or_else(not_a_number)? {
+            ExprNode::Number(_) | ExprNode::Rational(_) | ExprNode::Float(_) => {
+                self.numeric_value(expr_id).ok_or_else(not_a_number)
+            }
             ExprNode::Variable(var_id) => {
                 if let Some(CapturedValue::Expression(captured_expr)) = captures.get(var_id) {
                     self.evaluate_numeric_expr(*captured_expr, captures)
                 } else {
-                    None
+                    Err(not_a_number())
                 }
             }
-            _ => None,
+            ExprNode::Call { fun, .. } => {
+                let op = self.function_compute_op(*fun).ok_or_else(not_a_number)?;
+                let args = self
+                    .children(expr_id)
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .rev()
+                    .map(|child| self.evaluate_numeric_expr(child, captures))
+                    .collect::<Result<Vec<_>, _>>()?;
+                compute_operation(op, &args)
+            }
+            ExprNode::Struct { .. } => Err(not_a_number()),
+        }
+    }
+}
+
+/// Applies a compute operation to evaluated operands.
+fn compute_operation(op: ComputeOp, args: &[Numeric]) -> Result<Numeric, RewriteError> {
+    match (op, args) {
+        (ComputeOp::Add, _) => Ok(args.iter().fold(Numeric::zero(), |acc, arg| acc.add(arg))),
+        (ComputeOp::Multiply, _) => Ok(args.iter().fold(Numeric::one(), |acc, arg| acc.mul(arg))),
+        (ComputeOp::Subtract, [a, b]) => Ok(a.sub(b)),
+        (ComputeOp::Divide, [a, b]) => a.div(b),
+        (ComputeOp::Power, [a, b]) => a.pow(b),
+        (ComputeOp::Negate, [a]) => Ok(a.neg()),
+        (ComputeOp::Modulo, [a, b]) => a.modulo(b),
+        (ComputeOp::IntegerDivide, [a, b]) => a.div_floor(b),
+        (ComputeOp::Abs, [a]) => Ok(a.abs()),
+        (ComputeOp::Min | ComputeOp::Max, [first, rest @ ..]) => {
+            let wanted = if op == ComputeOp::Min {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            };
+            Ok(rest.iter().fold(first.clone(), |best, arg| {
+                if arg.partial_cmp(&best) == Some(wanted) {
+                    arg.clone()
+                } else {
+                    best
+                }
+            }))
         }
+        (ComputeOp::Gcd, [a, b]) => a.gcd(b),
+        (ComputeOp::Lcm, [a, b]) => a.lcm(b),
+        (ComputeOp::Compare(compare), [a, b]) => Ok(Numeric::from_bool(a.compare(compare, b))),
+        (ComputeOp::If, [condition, then, otherwise]) => Ok(if condition.is_true() {
+            then.clone()
+        } else {
+            otherwise.clone()
+        }),
+        _ => Err(RewriteError::TypeMismatch(format!(
+            "{} cannot take {} operands",
+            op,
+            args.len()
+        ))),
     }
 }
@@ -1,3 +1,4 @@
+use crate::rules::RuleScope;
 use crate::{DisplayNode, EquivalenceGroupId, ExprId, Pool, RuleId};
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
@@ -29,13 +30,16 @@ impl Eq for SearchNode {}
 
 impl PartialOrd for SearchNode {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        other.cost.partial_cmp(&self.cost)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for SearchNode {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+        other
+            .cost
+            .partial_cmp(&self.cost)
+            .unwrap_or(Ordering::Equal)
     }
 }
 
@@ -80,7 +84,9 @@ impl SearchEngine {
         pool: &mut Pool,
         start_expr: ExprId,
         target_expr: Option<ExprId>,
+        scope: &RuleScope,
     ) -> Vec<SearchPath> {
+        let allowed = pool.rules_in_scope(scope);
         let mut queue = VecDeque::new();
         let mut visited = HashSet::new();
         let mut paths = Vec::new();
@@ -111,20 +117,23 @@ impl SearchEngine {
                 continue;
             }
 
-            if let Some(target) = target_expr {
-                if pool.expr_eq(current.expr_id, target) {
-                    paths.push(SearchPath {
-                        steps: current.path.clone(),
-                        cost: current.cost,
-                        length: current.depth,
-                    });
-                    continue;
-                }
+            if let Some(target) = target_expr
+                && pool.expr_eq(current.expr_id, target)
+            {
+                paths.push(SearchPath {
+                    steps: current.path.clone(),
+                    cost: current.cost,
+                    length: current.depth,
+                });
+                continue;
             }
 
             // First try existing transformations
             if let Some(outgoing) = pool.get_outgoing_transformations(current.expr_id) {
                 for &(next_expr, rule) in outgoing {
+                    if !in_scope(&allowed, rule) {
+                        continue;
+                    }
                     let next_group = pool.get_equivalence_group(next_expr).unwrap();
 
                     if visited.contains(&next_group) {
@@ -158,9 +167,9 @@ impl SearchEngine {
             }
             
             // Then apply rules to generate new expressions
-            let matches = pool.find_matches(current.expr_id);
+            let matches = pool.find_matches_among(current.expr_id, allowed.as_ref());
             for match_ in matches {
-                if let Some(new_expr) = pool.apply_rule(&match_) {
+                if let Ok(new_expr) = pool.apply_rule(&match_) {
                     pool.update_equivalence_groups(new_expr);
                     let new_group = match pool.get_equivalence_group(new_expr) {
                         Some(group) => group,
@@ -214,8 +223,10 @@ impl SearchEngine {
         pool: &mut Pool,
         start_expr: ExprId,
         target_expr: Option<ExprId>,
+        scope: &RuleScope,
         cost_fn: impl Fn(&Pool, RuleId, ExprId, ExprId) -> f64,
     ) -> Vec<SearchPath> {
+        let allowed = pool.rules_in_scope(scope);
         let mut heap = BinaryHeap::new();
         let mut distances: HashMap<EquivalenceGroupId, f64> = HashMap::new();
         let mut paths: HashMap<EquivalenceGroupId, Vec<(ExprId, RuleId, ExprId)>> = HashMap::new();
@@ -248,26 +259,29 @@ impl SearchEngine {
                 continue;
             }
 
-            if let Some(&best_dist) = distances.get(&current.group_id) {
-                if current.cost > best_dist {
-                    continue;
-                }
+            if let Some(&best_dist) = distances.get(&current.group_id)
+                && current.cost > best_dist
+            {
+                continue;
             }
 
-            if let Some(target) = target_expr {
-                if pool.expr_eq(current.expr_id, target) {
-                    result_paths.push(SearchPath {
-                        steps: current.path.clone(),
-                        cost: current.cost,
-                        length: current.depth,
-                    });
-                    continue;
-                }
+            if let Some(target) = target_expr
+                && pool.expr_eq(current.expr_id, target)
+            {
+                result_paths.push(SearchPath {
+                    steps: current.path.clone(),
+                    cost: current.cost,
+                    length: current.depth,
+                });
+                continue;
             }
 
             // First try existing transformations
             if let Some(outgoing) = pool.get_outgoing_transformations(current.expr_id) {
                 for &(next_expr, rule) in outgoing {
+                    if !in_scope(&allowed, rule) {
+                        continue;
+                    }
                     let next_group = pool.get_equivalence_group(next_expr).unwrap();
 
                     let current_chain: Vec<_> = current
@@ -283,10 +297,10 @@ impl SearchEngine {
                     let edge_cost = cost_fn(pool, rule, current.expr_id, next_expr);
                     let new_cost = current.cost + edge_cost;
 
-                    if let Some(&best_dist) = distances.get(&next_group) {
-                        if new_cost >= best_dist {
-                            continue;
-                        }
+                    if let Some(&best_dist) = distances.get(&next_group)
+                        && new_cost >= best_dist
+                    {
+                        continue;
                     }
 
                     let mut new_path = current.path.clone();
@@ -307,9 +321,9 @@ impl SearchEngine {
             }
             
             // Then apply rules to generate new expressions
-            let matches = pool.find_matches(current.expr_id);
+            let matches = pool.find_matches_among(current.expr_id, allowed.as_ref());
             for match_ in matches {
-                if let Some(new_expr) = pool.apply_rule(&match_) {
+                if let Ok(new_expr) = pool.apply_rule(&match_) {
                     pool.update_equivalence_groups(new_expr);
                     let new_group = match pool.get_equivalence_group(new_expr) {
                         Some(group) => group,
@@ -329,10 +343,10 @@ impl SearchEngine {
                     let edge_cost = cost_fn(pool, match_.rule_id, current.expr_id, new_expr);
                     let new_cost = current.cost + edge_cost;
 
-                    if let Some(&best_dist) = distances.get(&new_group) {
-                        if new_cost >= best_dist {
-                            continue;
-                        }
+                    if let Some(&best_dist) = distances.get(&new_group)
+                        && new_cost >= best_dist
+                    {
+                        continue;
                     }
 
                     let mut new_path = current.path.clone();
@@ -374,9 +388,11 @@ impl SearchEngine {
         pool: &mut Pool,
         start_expr: ExprId,
         target_expr: ExprId,
+        scope: &RuleScope,
         heuristic_fn: impl Fn(&Pool, ExprId, ExprId) -> f64,
         cost_fn: impl Fn(&Pool, RuleId, ExprId, ExprId) -> f64,
     ) -> Option<SearchPath> {
+        let allowed = pool.rules_in_scope(scope);
         let mut heap = BinaryHeap::new();
         let mut g_score: HashMap<EquivalenceGroupId, f64> = HashMap::new();
         let mut f_score: HashMap<EquivalenceGroupId, f64> = HashMap::new();
@@ -429,6 +445,9 @@ impl SearchEngine {
 
             if let Some(outgoing) = pool.get_outgoing_transformations(current.expr_id) {
                 for &(next_expr, rule) in outgoing {
+                    if !in_scope(&allowed, rule) {
+                        continue;
+                    }
                     let next_group = pool.get_equivalence_group(next_expr).unwrap();
 
                     let current_chain: Vec<_> = current
@@ -478,8 +497,10 @@ impl SearchEngine {
         &mut self,
         pool: &mut Pool,
         start_expr: ExprId,
+        scope: &RuleScope,
         num_walks: usize,
     ) -> Vec<SearchPath> {
+        let allowed = pool.rules_in_scope(scope);
         let mut paths = Vec::new();
 
         for _ in 0..num_walks {
@@ -499,6 +520,9 @@ impl SearchEngine {
                 // First collect existing transformations
                 if let Some(outgoing) = pool.get_outgoing_transformations(current_expr) {
                     for &(next_expr, rule) in outgoing {
+                        if !in_scope(&allowed, rule) {
+                            continue;
+                        }
                         let current_group = pool.get_equivalence_group(current_expr).unwrap();
                         let next_group = pool.get_equivalence_group(next_expr).unwrap();
 
@@ -520,9 +544,9 @@ impl SearchEngine {
                 }
                 
                 // Then apply rules to generate new moves
-                let matches = pool.find_matches(current_expr);
+                let matches = pool.find_matches_among(current_expr, allowed.as_ref());
                 for match_ in matches {
-                    if let Some(new_expr) = pool.apply_rule(&match_) {
+                    if let Ok(new_expr) = pool.apply_rule(&match_) {
                         pool.update_equivalence_groups(new_expr);
                         let current_group = pool.get_equivalence_group(current_expr).unwrap();
                         let new_group = match pool.get_equivalence_group(new_expr) {
@@ -579,8 +603,10 @@ impl SearchEngine {
         &mut self,
         pool: &mut Pool,
         start_expr: ExprId,
+        scope: &RuleScope,
         evaluation_fn: impl Fn(&Pool, ExprId, &[ExprId]) -> f64,
     ) -> Vec<SearchPath> {
+        let allowed = pool.rules_in_scope(scope);
         let mut current_beam = Vec::new();
         let mut all_paths = Vec::new();
 
@@ -604,6 +630,9 @@ impl SearchEngine {
             for current in &current_beam {
                 if let Some(outgoing) = pool.get_outgoing_transformations(current.expr_id) {
                     for &(next_expr, rule) in outgoing {
+                        if !in_scope(&allowed, rule) {
+                            continue;
+                        }
                         let next_group = pool.get_equivalence_group(next_expr).unwrap();
 
                         if explored_groups.contains(&next_group) {
@@ -675,23 +704,24 @@ impl SearchEngine {
         pool: &mut Pool,
         start_expr: ExprId,
         target_expr: Option<ExprId>,
+        scope: &RuleScope,
     ) -> Vec<SearchPath> {
         let mut all_paths = Vec::new();
 
-        let mut bfs_paths = self.bounded_bfs(pool, start_expr, target_expr);
+        let mut bfs_paths = self.bounded_bfs(pool, start_expr, target_expr, scope);
         all_paths.append(&mut bfs_paths);
 
         let unit_cost = |_pool: &Pool, _rule: RuleId, _from: ExprId, _to: ExprId| 1.0;
-        let mut dijkstra_paths = self.bounded_dijkstra(pool, start_expr, target_expr, unit_cost);
+        let mut dijkstra_paths = self.bounded_dijkstra(pool, start_expr, target_expr, scope, unit_cost);
         all_paths.append(&mut dijkstra_paths);
 
-        let mut random_paths = self.random_search(pool, start_expr, 20);
+        let mut random_paths = self.random_search(pool, start_expr, scope, 20);
         all_paths.append(&mut random_paths);
 
         let complexity_eval = |pool: &Pool, expr: ExprId, _beam: &[ExprId]| {
             pool.display_with_children(expr).len() as f64
         };
-        let mut beam_paths = self.beam_search(pool, start_expr, complexity_eval);
+        let mut beam_paths = self.beam_search(pool, start_expr, scope, complexity_eval);
         all_paths.append(&mut beam_paths);
 
         self.deduplicate_and_rank_paths(pool, all_paths)
@@ -748,6 +778,12 @@ pub mod heuristics {
     }
 }
 
+/// Whether `rule` is among the `allowed` rules of a scope, where `None`
+/// allows every rule.
+fn in_scope(allowed: &Option<HashSet<RuleId>>, rule: RuleId) -> bool {
+    allowed.as_ref().is_none_or(|allowed| allowed.contains(&rule))
+}
+
 fn levenshtein_distance(s1: &str, s2: &str) -> usize {
     let v1: Vec<char> = s1.chars().collect();
     let v2: Vec<char> = s2.chars().collect();
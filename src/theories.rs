@@ -0,0 +1,389 @@
+use crate::ast::*;
+use crate::children::Children;
+use crate::idx::*;
+use crate::operators::BUILTIN_OPERATORS;
+use crate::pool::Pool;
+use crate::rules::CapturedValue;
+use std::collections::HashMap;
+
+/// Calls with more operands than this are not split between several
+/// captures, which would take `2^n` attempts. Matches given up this way are
+/// reported as `MatchLimit::TooManyOperands`.
+pub const MAX_SPLIT_OPERANDS: usize = 16;
+
+/// Steps one match of a pattern against a node may take while trying the
+/// ways operands of declared theories line up. Three `?x` operands of an
+/// associative and commutative symbol split `n` operands in `3^n` ways, so
+/// the search stops here and reports `MatchLimit::TooManyMatches` with the
+/// matches found so far.
+pub const MAX_MATCH_ATTEMPTS: usize = 1 << 12;
+
+/// Equational properties of a function symbol, declared in rulesets as
+/// `assoc <>`, `comm f` or `ac + identity 0`.
+///
+/// A pattern call of a declared symbol matches modulo its properties:
+///
+/// - commutative: the operands match in any order, so `?x + 0` matches
+///   `0 + y`.
+/// - associative: nested calls are flattened into one operand list, and a
+///   `?x` operand may take several consecutive operands, captured as
+///   `CapturedValue::Operands`. `?x + 0` matches `y + 0 + z` with `x` bound
+///   to `y + z`.
+/// - identity: a `#a` operand with no number left to match stands for the
+///   identity, so with `ac * identity 1` the pattern `#a * ?x` matches `y`
+///   with `a = 1`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Theory {
+    pub associative: bool,
+    pub commutative: bool,
+    pub identity: Option<ExactNumber>,
+}
+
+impl Pool {
+    /// Declares the theory of `symbol`, which is a built-in operator such as
+    /// `+`, a declared operator or a function name. A later declaration of
+    /// the same symbol overrides an earlier one.
+    pub fn declare_theory(&mut self, symbol: &str, theory: Theory) -> FunctionId {
+        let function = match BUILTIN_OPERATORS
+            .iter()
+            .find(|(builtin, ..)| *builtin == symbol)
+        {
+            Some(&(_, function, ..)) => self.intern_function(function),
+            None => {
+                let name = self.intern_string(symbol.to_string());
+                self.intern_function(Function::Custom(name))
+            }
+        };
+        self.theories.push(TheoryDecl {
+            symbol: symbol.to_string(),
+            function,
+            associative: theory.associative,
+            commutative: theory.commutative,
+            identity: theory.identity,
+        });
+        function
+    }
+
+    /// The theory declared for `function`, if any.
+    pub fn theory(&self, function: FunctionId) -> Option<&TheoryDecl> {
+        self.theories
+            .iter()
+            .rev()
+            .find(|theory| theory.function == function)
+    }
+
+    /// The operands of `expr_id` as a call of the theory's symbol: its
+    /// arguments, with nested calls spliced in if the symbol is associative.
+    /// Any other expression is its own single operand.
+    pub fn theory_operands(&self, expr_id: ExprId, theory: &TheoryDecl) -> Vec<ExprId> {
+        let mut operands = Vec::new();
+        self.collect_operands(expr_id, theory, true, &mut operands);
+        operands
+    }
+
+    fn collect_operands(
+        &self,
+        expr_id: ExprId,
+        theory: &TheoryDecl,
+        top: bool,
+        operands: &mut Vec<ExprId>,
+    ) {
+        match self[expr_id] {
+            ExprNode::Call { fun, .. } if fun == theory.function && (top || theory.associative) => {
+                let children: Vec<_> = self.children(expr_id).collect();
+                for child in children.into_iter().rev() {
+                    self.collect_operands(child, theory, false, operands);
+                }
+            }
+            _ => operands.push(expr_id),
+        }
+    }
+
+    fn pattern_operands(&self, pattern_id: PatternId, theory: &TheoryDecl) -> Vec<PatternId> {
+        let mut operands = Vec::new();
+        let mut stack = vec![(pattern_id, true)];
+        while let Some((pattern_id, top)) = stack.pop() {
+            match self[pattern_id] {
+                Pattern::Call { fun, .. }
+                    if fun == theory.function && (top || theory.associative) =>
+                {
+                    stack.extend(self.children(pattern_id).map(|child| (child, false)));
+                }
+                _ => operands.push(pattern_id),
+            }
+        }
+        operands
+    }
+
+    fn is_identity(&self, expr_id: ExprId, theory: &TheoryDecl) -> bool {
+        theory
+            .identity
+            .is_some_and(|identity| self[expr_id] == ExprNode::from(identity))
+    }
+
+    /// The operands a capture stands for under the theory, without identity
+    /// elements. `None` if it is not an expression.
+    pub(crate) fn captured_operands(
+        &self,
+        value: &CapturedValue,
+        theory: &TheoryDecl,
+    ) -> Option<Vec<ExprId>> {
+        let operands = match value {
+            CapturedValue::Expression(expr_id) if theory.associative => {
+                self.theory_operands(*expr_id, theory)
+            }
+            CapturedValue::Expression(expr_id) => vec![*expr_id],
+            CapturedValue::Operands { fun, args } if *fun == theory.function => args.clone(),
+            _ => return None,
+        };
+        Some(
+            operands
+                .into_iter()
+                .filter(|&operand| !self.is_identity(operand, theory))
+                .collect(),
+        )
+    }
+
+    /// Whether two operand lists are equal under the theory, in any order if
+    /// it is commutative.
+    pub(crate) fn operands_eq(&self, theory: &TheoryDecl, a: &[ExprId], b: &[ExprId]) -> bool {
+        if theory.commutative {
+            a.len() == b.len() && self.remove_operands(a, b.to_vec()).is_some()
+        } else {
+            a.len() == b.len() && a.iter().zip(b).all(|(&x, &y)| self.expr_eq(x, y))
+        }
+    }
+
+    /// Removes one operand equal to each of `wanted` from `remaining`.
+    fn remove_operands(
+        &self,
+        wanted: &[ExprId],
+        mut remaining: Vec<ExprId>,
+    ) -> Option<Vec<ExprId>> {
+        for &operand in wanted {
+            let index = remaining
+                .iter()
+                .position(|&other| self.expr_eq(operand, other))?;
+            remaining.remove(index);
+        }
+        Some(remaining)
+    }
+
+    /// Every way of matching a call pattern of the theory's symbol against
+    /// `node_id`, as extensions of `captures`.
+    pub(crate) fn match_theory_call(
+        &self,
+        pattern_id: PatternId,
+        node_id: ExprId,
+        theory: &TheoryDecl,
+        captures: HashMap<NameId, CapturedValue>,
+    ) -> Vec<HashMap<NameId, CapturedValue>> {
+        let is_call = matches!(self[node_id], ExprNode::Call { fun, .. } if fun == theory.function);
+        if !is_call && theory.identity.is_none() {
+            return Vec::new();
+        }
+        let mut pattern_operands = self.pattern_operands(pattern_id, theory);
+        let operands = self.theory_operands(node_id, theory);
+
+        let mut found = Vec::new();
+        if theory.commutative {
+            // Operands that match a single term prune the search the most.
+            pattern_operands.sort_by_key(|&operand| {
+                matches!(self[operand], Pattern::Wildcard(_) | Pattern::AnyNumber(_))
+            });
+            self.match_unordered(theory, &pattern_operands, operands, captures, &mut found);
+        } else {
+            self.match_ordered(theory, &pattern_operands, &operands, captures, &mut found);
+        }
+        found
+    }
+
+    /// Binds `name` to `operands`, which are consecutive operands of the
+    /// theory's symbol.
+    fn bind_operands(
+        &self,
+        theory: &TheoryDecl,
+        name: NameId,
+        operands: &[ExprId],
+        mut captures: HashMap<NameId, CapturedValue>,
+    ) -> HashMap<NameId, CapturedValue> {
+        let value = match operands {
+            [operand] => CapturedValue::Expression(*operand),
+            _ => CapturedValue::Operands {
+                fun: theory.function,
+                args: operands.to_vec(),
+            },
+        };
+        captures.insert(name, value);
+        captures
+    }
+
+    /// The capture name of a `?x`, `#a` or `?x:kind` operand that is already
+    /// bound.
+    pub(crate) fn bound_capture<'a>(
+        &self,
+        pattern_id: PatternId,
+        captures: &'a HashMap<NameId, CapturedValue>,
+    ) -> Option<&'a CapturedValue> {
+        match self[pattern_id] {
+            Pattern::Wildcard(name) | Pattern::AnyNumber(name) | Pattern::Typed { name, .. } => {
+                captures.get(&name)
+            }
+            _ => None,
+        }
+    }
+
+    /// Counts one step of the current match against `MAX_MATCH_ATTEMPTS`,
+    /// returning false once the budget is used up.
+    fn spend_match_attempt(&self) -> bool {
+        let attempts = self.match_attempts.get() + 1;
+        self.match_attempts.set(attempts);
+        attempts <= MAX_MATCH_ATTEMPTS
+    }
+
+    /// Whether the last `pattern_matches_all` ran out of attempts before
+    /// trying every way to match.
+    pub(crate) fn match_attempts_exhausted(&self) -> bool {
+        self.match_attempts.get() > MAX_MATCH_ATTEMPTS
+    }
+
+    fn match_unordered(
+        &self,
+        theory: &TheoryDecl,
+        pattern_operands: &[PatternId],
+        remaining: Vec<ExprId>,
+        captures: HashMap<NameId, CapturedValue>,
+        found: &mut Vec<HashMap<NameId, CapturedValue>>,
+    ) {
+        if !self.spend_match_attempt() {
+            return;
+        }
+        let Some((&first, rest)) = pattern_operands.split_first() else {
+            if remaining.is_empty() {
+                found.push(captures);
+            }
+            return;
+        };
+
+        if let Some(bound) = self.bound_capture(first, &captures) {
+            if let Some(wanted) = self.captured_operands(bound, theory)
+                && let Some(remaining) = self.remove_operands(&wanted, remaining)
+            {
+                self.match_unordered(theory, rest, remaining, captures, found);
+            }
+            return;
+        }
+
+        match self[first] {
+            Pattern::AnyNumber(name) => {
+                let numbers: Vec<_> = (0..remaining.len())
+                    .filter(|&index| self.numeric_value(remaining[index]).is_some())
+                    .collect();
+                if numbers.is_empty() && theory.identity.is_some() {
+                    let captures = self.bind_operands(theory, name, &[], captures.clone());
+                    self.match_unordered(theory, rest, remaining.clone(), captures, found);
+                }
+                for index in numbers {
+                    let mut others = remaining.clone();
+                    let operand = others.remove(index);
+                    let captures = self.bind_operands(theory, name, &[operand], captures.clone());
+                    self.match_unordered(theory, rest, others, captures, found);
+                }
+            }
+            Pattern::Wildcard(name) if theory.associative => {
+                // The last operand takes everything that is left, the others
+                // try every non-empty subset.
+                if remaining.is_empty() {
+                    return;
+                }
+                if remaining.len() > MAX_SPLIT_OPERANDS {
+                    self.split_limit_hit.set(true);
+                    return;
+                }
+                let all = (1usize << remaining.len()) - 1;
+                let subsets = if rest.is_empty() {
+                    all..all + 1
+                } else {
+                    1..all + 1
+                };
+                for subset in subsets {
+                    let (taken, others): (Vec<_>, Vec<_>) = remaining
+                        .iter()
+                        .enumerate()
+                        .partition(|(index, _)| subset & (1 << index) != 0);
+                    let taken: Vec<_> = taken.into_iter().map(|(_, &operand)| operand).collect();
+                    let others = others.into_iter().map(|(_, &operand)| operand).collect();
+                    let captures = self.bind_operands(theory, name, &taken, captures.clone());
+                    self.match_unordered(theory, rest, others, captures, found);
+                }
+            }
+            _ => {
+                for index in 0..remaining.len() {
+                    for captures in self.match_pattern(first, remaining[index], captures.clone()) {
+                        let mut others = remaining.clone();
+                        others.remove(index);
+                        self.match_unordered(theory, rest, others, captures, found);
+                    }
+                }
+            }
+        }
+    }
+
+    fn match_ordered(
+        &self,
+        theory: &TheoryDecl,
+        pattern_operands: &[PatternId],
+        remaining: &[ExprId],
+        captures: HashMap<NameId, CapturedValue>,
+        found: &mut Vec<HashMap<NameId, CapturedValue>>,
+    ) {
+        if !self.spend_match_attempt() {
+            return;
+        }
+        let Some((&first, rest)) = pattern_operands.split_first() else {
+            if remaining.is_empty() {
+                found.push(captures);
+            }
+            return;
+        };
+
+        if let Some(bound) = self.bound_capture(first, &captures) {
+            if let Some(wanted) = self.captured_operands(bound, theory)
+                && wanted.len() <= remaining.len()
+                && self.operands_eq(theory, &wanted, &remaining[..wanted.len()])
+            {
+                self.match_ordered(theory, rest, &remaining[wanted.len()..], captures, found);
+            }
+            return;
+        }
+
+        match self[first] {
+            Pattern::AnyNumber(name) => match remaining.split_first() {
+                Some((&operand, others)) if self.numeric_value(operand).is_some() => {
+                    let captures = self.bind_operands(theory, name, &[operand], captures);
+                    self.match_ordered(theory, rest, others, captures, found);
+                }
+                _ if theory.identity.is_some() => {
+                    let captures = self.bind_operands(theory, name, &[], captures);
+                    self.match_ordered(theory, rest, remaining, captures, found);
+                }
+                _ => {}
+            },
+            Pattern::Wildcard(name) if theory.associative => {
+                let first_end = if rest.is_empty() { remaining.len() } else { 1 };
+                for end in first_end.max(1)..=remaining.len() {
+                    let captures =
+                        self.bind_operands(theory, name, &remaining[..end], captures.clone());
+                    self.match_ordered(theory, rest, &remaining[end..], captures, found);
+                }
+            }
+            _ => {
+                if let Some((&operand, others)) = remaining.split_first() {
+                    for captures in self.match_pattern(first, operand, captures) {
+                        self.match_ordered(theory, rest, others, captures, found);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -134,7 +134,9 @@ fn render_expression_text_recursive(
     let is_highlighted = expr_id == highlighted_id;
 
     let content = match expr {
-        ExprNode::Number(n) => rsx! { "{n}" },
+        ExprNode::Number(n) => rsx! { "{pool.display_number(*n)}" },
+        ExprNode::Rational(r) => rsx! { "{pool.display_rational(*r)}" },
+        ExprNode::Float(f) => rsx! { "{pool.display_float(*f)}" },
         ExprNode::Variable(name_id) => rsx! { "{pool.display_name(*name_id)}" },
         ExprNode::Call { fun, .. } => {
             let children: Vec<_> = pool.children(expr_id).collect();
@@ -259,7 +261,17 @@ fn ExpressionDetails(pool: Signal<Pool>, expr_id: ExprId) -> Element {
             match expr {
                 ExprNode::Number(n) => rsx! {
                     div { class: styles::TEXT_SMALL,
-                        "Value: {n}"
+                        "Value: {pool_ref.display_number(*n)}"
+                    }
+                },
+                ExprNode::Rational(r) => rsx! {
+                    div { class: styles::TEXT_SMALL,
+                        "Value: {pool_ref.display_rational(*r)}"
+                    }
+                },
+                ExprNode::Float(f) => rsx! {
+                    div { class: styles::TEXT_SMALL,
+                        "Value: {pool_ref.display_float(*f)}"
                     }
                 },
                 ExprNode::Variable(name_id) => rsx! {
@@ -343,7 +355,13 @@ fn PatternDetails(pool: Signal<Pool>, pattern: Pattern) -> Element {
         div { class: styles::SPACE_Y_1,
             match pattern {
                 Pattern::Number(n) => rsx! {
-                    div { class: styles::TEXT_MONO, "Number: {n}" }
+                    div { class: styles::TEXT_MONO, "Number: {pool_ref.display_number(n)}" }
+                },
+                Pattern::Rational(r) => rsx! {
+                    div { class: styles::TEXT_MONO, "Rational: {pool_ref.display_rational(r)}" }
+                },
+                Pattern::Float(f) => rsx! {
+                    div { class: styles::TEXT_MONO, "Float: {pool_ref.display_float(f)}" }
                 },
                 Pattern::Variable(name_id) => rsx! {
                     div { class: styles::TEXT_MONO, "Variable: {pool_ref.display_name(name_id)}" }
@@ -354,6 +372,12 @@ fn PatternDetails(pool: Signal<Pool>, pattern: Pattern) -> Element {
                 Pattern::Wildcard(name_id) => rsx! {
                     div { class: styles::TEXT_MONO, "Wildcard: ?{pool_ref.display_name(name_id)}" }
                 },
+                Pattern::Typed { name, kind } => rsx! {
+                    div { class: styles::TEXT_MONO, "Typed: ?{pool_ref.display_name(name)}:{kind}" }
+                },
+                Pattern::Sequence(name_id) => rsx! {
+                    div { class: styles::TEXT_MONO, "Sequence: ?{pool_ref.display_name(name_id)}..." }
+                },
                 Pattern::Call { fun, arity, .. } => rsx! {
                     div { class: styles::TEXT_MONO,
                         "Call: {pool_ref.display_function(fun)}({arity} args)"
@@ -374,6 +398,18 @@ fn PatternDetails(pool: Signal<Pool>, pattern: Pattern) -> Element {
                         "VarStruct: {pool_ref.display_name(var)}({arity} fields)"
                     }
                 },
+                Pattern::Not { .. } => rsx! {
+                    div { class: styles::TEXT_MONO, "Not" }
+                },
+                Pattern::Where { arity, .. } => rsx! {
+                    div { class: styles::TEXT_MONO, "Where: {arity - 1} constraints" }
+                },
+                Pattern::Distinct { var, .. } => rsx! {
+                    div { class: styles::TEXT_MONO, "Distinct: ?{pool_ref.display_name(var)} !=" }
+                },
+                Pattern::Context { var, .. } => rsx! {
+                    div { class: styles::TEXT_MONO, "Context: {pool_ref.display_name(var)}[..]" }
+                },
             }
         }
     }
@@ -417,11 +453,23 @@ fn ActionDetails(pool: Signal<Pool>, action: Action) -> Element {
         div { class: styles::SPACE_Y_1,
             match action {
                 Action::Number(n) => rsx! {
-                    div { class: styles::TEXT_MONO, "Number: {n}" }
+                    div { class: styles::TEXT_MONO, "Number: {pool_ref.display_number(n)}" }
+                },
+                Action::Rational(r) => rsx! {
+                    div { class: styles::TEXT_MONO, "Rational: {pool_ref.display_rational(r)}" }
+                },
+                Action::Float(f) => rsx! {
+                    div { class: styles::TEXT_MONO, "Float: {pool_ref.display_float(f)}" }
                 },
                 Action::Variable(name_id) => rsx! {
                     div { class: styles::TEXT_MONO, "Variable: {pool_ref.display_name(name_id)}" }
                 },
+                Action::Fresh(name_id) => rsx! {
+                    div { class: styles::TEXT_MONO, "Fresh: {pool_ref.display_name(name_id)}" }
+                },
+                Action::Splice(name_id) => rsx! {
+                    div { class: styles::TEXT_MONO, "Splice: {pool_ref.display_name(name_id)}..." }
+                },
                 Action::Call { fun, arity, .. } => rsx! {
                     div { class: styles::TEXT_MONO,
                         "Call: {pool_ref.display_function(fun)}({arity} args)"
@@ -447,6 +495,9 @@ fn ActionDetails(pool: Signal<Pool>, action: Action) -> Element {
                         "VarStruct: {pool_ref.display_name(var)}({arity} fields)"
                     }
                 },
+                Action::Plug { var, .. } => rsx! {
+                    div { class: styles::TEXT_MONO, "Plug: {pool_ref.display_name(var)}[..]" }
+                },
             }
         }
     }
@@ -457,6 +508,11 @@ pub fn RuleDisplay(pool: Signal<Pool>, rule_id: RuleId, highlight: Option<bool>)
     let pool_ref = pool.read();
     let rule = &pool_ref[rule_id];
     let is_highlighted = highlight.unwrap_or(false);
+    let arrow = if pool_ref.reverse_rule(rule_id).is_some() {
+        "⇔"
+    } else {
+        "⇒"
+    };
 
     let container_class = if is_highlighted {
         format!("{} bg-orange-100 border-orange-300", styles::CARD)
@@ -482,12 +538,18 @@ pub fn RuleDisplay(pool: Signal<Pool>, rule_id: RuleId, highlight: Option<bool>)
                         PatternDisplay { pool: pool, pattern_id: rule.pattern, highlight: false }
                     }
                     div { class: "flex-none px-4 flex items-center",
-                        span { class: "text-2xl text-gray-400", "⇒" }
+                        span { class: "text-2xl text-gray-400", "{arrow}" }
                     }
                     div { class: "flex-1",
                         ActionDisplay { pool: pool, action_id: rule.action, highlight: false }
                     }
                 }
+
+                if let Some(guard) = rule.guard {
+                    div { class: "text-sm text-gray-600 font-mono",
+                        "when {pool_ref.display_with_children(guard)}"
+                    }
+                }
             }
         }
     }
@@ -554,100 +616,73 @@ fn get_basic_arithmetic_ruleset() -> String {
 
   double            : ?x + ?x            => 2 * x
   incr_coeff        : #a * ?x + ?x       => [a+1] * x
-  add_assoc      : (?x + ?y) + ?z => x + (y + z)
+  add_assoc      : (?x + ?y) + ?z <=> ?x + (?y + ?z)
   add_comm       : ?x + ?y        => y + x
-  add_zero_left  : 0 + ?x         => x
-  add_zero_right : ?x + 0         => x
-  mul_assoc      : (?x * ?y) * ?z => x * (y * z)
+  add_zero_left  : 0 + ?x         <=> ?x
+  add_zero_right : ?x + 0         <=> ?x
+  mul_assoc      : (?x * ?y) * ?z <=> ?x * (?y * ?z)
   mul_comm       : ?x * ?y        => y * x
-  mul_one_left   : 1 * ?x         => x
-  mul_one_right  : ?x * 1         => x
+  mul_one_left   : 1 * ?x         <=> ?x
+  mul_one_right  : ?x * 1         <=> ?x
   mul_zero_left  : 0 * ?x         => 0
   mul_zero_right : ?x * 0         => 0
-  left_distrib   : ?x * (?y + ?z) => x * y + x * z
-  right_distrib  : (?x + ?y) * ?z => x * z + y * z
+  left_distrib   : ?x * (?y + ?z) <=> ?x * ?y + ?x * ?z
+  right_distrib  : (?x + ?y) * ?z <=> ?x * ?z + ?y * ?z
 
-  b_add_assoc      : ?x + (?y + ?z) => (x + y) + z
-  b_add_zero_left  : ?x =>0 + x
-  b_add_zero_right : ?x => x + 0
-  b_mul_assoc      : ?x * (?y * ?z) => (x * y) * z
-  b_mul_one_left   : ?x => 1 * x
-  b_mul_one_right  : ?x => x * 1
-  b_mul_zero_left  : 0  => 0 * new
-  b_mul_zero_right : 0  => new * 0
-  b_left_distrib   : ?x * ?y + ?x * ?z  => x * (y + z)
-  b_right_distrib  :  ?x * ?z + ?y * ?z => (x + y) * z
+  b_mul_zero_left  : 0  => 0 * fresh(n)
+  b_mul_zero_right : 0  => fresh(n) * 0
 
 
 }"#
     .to_string()
 }
 
+const MONOID_RULESET: &str = r#"monoid {
+  left_identity  : 0 + ?x         <=> ?x
+  right_identity : ?x + 0         <=> ?x
+  associativity  : (?x + ?y) + ?z <=> ?x + (?y + ?z)
+}"#;
+
 fn get_monoid_ruleset() -> String {
-    r#"monoid {
-  left_identity  : 0 + ?x         => x
-  right_identity : ?x + 0         => x
-  associativity  : (?x + ?y) + ?z => x + (y + z)
-
- b_left_identity  : ?x => 0 + x
- b_right_identity : ?x => x + 0
- b_associativity  : ?x + (?y + ?z) => (x + y ) + z
-}"#
-    .to_string()
+    MONOID_RULESET.to_string()
 }
 
 fn get_group_ruleset() -> String {
-    r#"group {
-  left_identity      : 0 + ?x         => x
-  right_identity     : ?x + 0         => x
+    format!(
+        r#"{MONOID_RULESET}
+
+group extends monoid {{
   left_inverse       : -?x + ?x       => 0
   right_inverse      : ?x + -?x       => 0
-  associativity      : (?x + ?y) + ?z => x + (y + z)
-  inverse_inverse    : -(-(?x))       => x
-  inverse_identity   : -(0)           => 0
-
-  b_left_identity    : ?x             => 0 + x
-  b_right_identity   : ?x             => x + 0
-  b_left_inverse     : 0              => -x + x
-  b_right_inverse    : 0              => x + -x
-  b_associativity    : ?x + (?y + ?z) => (x + y) + z
-  b_inverse_inverse  : ?x             => -(-(x))
-  b_inverse_identity : 0              => -(0)
-
-
+  inverse_inverse    : -(-(?x))       <=> ?x
+  inverse_identity   : -(0)           <=> 0
 
-}"#
-    .to_string()
+  b_left_inverse     : 0              => -fresh(x) + fresh(x)
+  b_right_inverse    : 0              => fresh(x) + -fresh(x)
+}}"#
+    )
 }
 
 fn get_semiring_ruleset() -> String {
-    r#"semiring {
-  add_assoc      : (?x + ?y) + ?z => x + (y + z)
+    format!(
+        r#"{MONOID_RULESET}
+
+semiring {{
+  use monoid;
+
   add_comm       : ?x + ?y        => y + x
-  add_zero_left  : 0 + ?x         => x
-  add_zero_right : ?x + 0         => x
-  mul_assoc      : (?x * ?y) * ?z => x * (y * z)
-  mul_one_left   : 1 * ?x         => x
-  mul_one_right  : ?x * 1         => x
+  mul_assoc      : (?x * ?y) * ?z <=> ?x * (?y * ?z)
+  mul_one_left   : 1 * ?x         <=> ?x
+  mul_one_right  : ?x * 1         <=> ?x
   mul_zero_left  : 0 * ?x         => 0
   mul_zero_right : ?x * 0         => 0
-  left_distrib   : ?x * (?y + ?z) => x * y + x * z
-  right_distrib  : (?x + ?y) * ?z => x * z + y * z
+  left_distrib   : ?x * (?y + ?z) <=> ?x * ?y + ?x * ?z
+  right_distrib  : (?x + ?y) * ?z <=> ?x * ?z + ?y * ?z
 
-  b_add_assoc      : ?x + (?y + ?z) => (x + y) + z
-  b_add_zero_left  : ?x =>0 + x
-  b_add_zero_right : ?x => x + 0
-  b_mul_assoc      : ?x * (?y * ?z) => (x * y) * z
-  b_mul_one_left   : ?x => 1 * x
-  b_mul_one_right  : ?x => x * 1
-  b_mul_zero_left  : 0  => 0 * new
-  b_mul_zero_right : 0  => new * 0
-  b_left_distrib   : ?x * ?y + ?x * ?z  => x * (y + z)
-  b_right_distrib  :  ?x * ?z + ?y * ?z => (x + y) * z
-
-
-}"#
-    .to_string()
+  b_mul_zero_left  : 0  => 0 * fresh(n)
+  b_mul_zero_right : 0  => fresh(n) * 0
+}}"#
+    )
 }
 
 #[component]
@@ -727,6 +762,8 @@ pub fn ParsedRulesetsList(
 pub fn get_expr_type_name(expr: &ExprNode) -> &'static str {
     match expr {
         ExprNode::Number(_) => "Number",
+        ExprNode::Rational(_) => "Rational",
+        ExprNode::Float(_) => "Float",
         ExprNode::Variable(_) => "Variable",
         ExprNode::Call { .. } => "Call",
         ExprNode::Struct { .. } => "Struct",
@@ -736,24 +773,37 @@ pub fn get_expr_type_name(expr: &ExprNode) -> &'static str {
 fn get_pattern_type_name(pattern: &Pattern) -> &'static str {
     match pattern {
         Pattern::Number(_) => "Number",
+        Pattern::Rational(_) => "Rational",
+        Pattern::Float(_) => "Float",
         Pattern::Variable(_) => "Variable",
         Pattern::AnyNumber(_) => "AnyNumber",
         Pattern::Wildcard(_) => "Wildcard",
+        Pattern::Typed { .. } => "Typed",
+        Pattern::Sequence(_) => "Sequence",
         Pattern::Call { .. } => "Call",
         Pattern::Struct { .. } => "Struct",
         Pattern::VarCallName { .. } => "VarCall",
         Pattern::VarStructName { .. } => "VarStruct",
+        Pattern::Not { .. } => "Not",
+        Pattern::Where { .. } => "Where",
+        Pattern::Distinct { .. } => "Distinct",
+        Pattern::Context { .. } => "Context",
     }
 }
 
 fn get_action_type_name(action: &Action) -> &'static str {
     match action {
         Action::Number(_) => "Number",
+        Action::Rational(_) => "Rational",
+        Action::Float(_) => "Float",
         Action::Variable(_) => "Variable",
+        Action::Fresh(_) => "Fresh",
+        Action::Splice(_) => "Splice",
         Action::Call { .. } => "Call",
         Action::Struct { .. } => "Struct",
         Action::Compute { .. } => "Compute",
         Action::VarCallName { .. } => "VarCall",
         Action::VarStructName { .. } => "VarStruct",
+        Action::Plug { .. } => "Plug",
     }
 }
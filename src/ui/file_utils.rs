@@ -18,7 +18,7 @@ pub fn download_text_file(filename: &str, content: &str) {
         serde_json::to_string(content).unwrap_or_default(),
         serde_json::to_string(filename).unwrap_or_default()
     );
-    
+
     eval(&js_code);
 }
 
@@ -26,10 +26,10 @@ pub fn download_text_file(filename: &str, content: &str) {
 pub async fn read_file_from_event(event: &FormEvent) -> Option<(String, String)> {
     if let Some(file_engine) = event.files() {
         let files = file_engine.files();
-        if let Some(file_name) = files.first() {
-            if let Some(contents) = file_engine.read_file_to_string(file_name).await {
-                return Some((file_name.clone(), contents));
-            }
+        if let Some(file_name) = files.first()
+            && let Some(contents) = file_engine.read_file_to_string(file_name).await
+        {
+            return Some((file_name.clone(), contents));
         }
     }
     None
@@ -2,7 +2,7 @@ use super::super::{
     display_components::CompactExpressionCard,
     primitives::TransformationGraph,
 };
-use crate::{ActionId, Children, DisplayNode, ExprId, PatternId, Pool, RuleId, rules::Match, search::{SearchEngine, SearchConfig, SearchPath}};
+use crate::{ActionId, Children, DisplayNode, ExprId, PatternId, Pool, Provenance, RuleId, RulesetId, rules::{Match, MatchLimit, RuleScope}, search::{SearchEngine, SearchConfig, SearchPath}};
 use dioxus::prelude::*;
 
 #[component]
@@ -18,17 +18,25 @@ pub fn ExplorerPage(pool: Signal<Pool>) -> Element {
 
     let mut highlighted_subexpr = use_signal(|| None::<ExprId>);
     let mut current_matches = use_signal(Vec::<Match>::new);
+    let mut match_limits = use_signal(Vec::<MatchLimit>::new);
     let mut rules_panel_collapsed = use_signal(|| false);
     let mut hovered_rule_index = use_signal(|| None::<usize>);
     let _show_search_panel = use_signal(|| false);
     let mut last_applied_rule = use_signal(|| None::<(ExprId, ExprId, RuleId, Match)>);
+    let mut apply_error = use_signal(|| None::<String>);
+    let mut inactive_rulesets = use_signal(Vec::<RulesetId>::new);
 
     use_effect(move || {
         if let Some(expr_id) = current_expr.read().as_ref() {
-            let matches = pool.read().find_matches(*expr_id);
+            let pool_ref = pool.read();
+            let scope = active_rule_scope(&pool_ref, &inactive_rulesets.read());
+            let (matches, limits) = pool_ref.find_matches_with_limits(*expr_id, &scope);
             current_matches.set(matches);
+            match_limits.set(limits);
+            apply_error.set(None);
         } else {
             current_matches.set(Vec::new());
+            match_limits.set(Vec::new());
         }
     });
 
@@ -67,7 +75,9 @@ pub fn ExplorerPage(pool: Signal<Pool>) -> Element {
                                     spawn(async move {
                                         if let Some((_filename, contents)) = super::super::file_utils::read_file_from_event(&evt).await {
                                             match serde_json::from_str::<crate::Pool>(&contents) {
-                                                Ok(imported_pool) => {
+                                                Ok(mut imported_pool) => {
+                                                    imported_pool.update_rule_index();
+                                                    imported_pool.rebuild_hash_cons();
                                                     pool.set(imported_pool);
                                                     // Reset current expression to the latest one
                                                     let pool_ref = pool.read();
@@ -127,6 +137,33 @@ pub fn ExplorerPage(pool: Signal<Pool>) -> Element {
                             "Reset Pool"
                         }
 
+                        button {
+                            class: "px-3 py-2 rounded transition-colors text-sm bg-gray-100 text-gray-700 hover:bg-gray-200",
+                            title: "Keeps parsed expressions, the current one and the transformation graph, and drops everything else",
+                            onclick: move |_| {
+                                let mut roots = parsed_roots(&pool.read());
+                                roots.extend(*current_expr.read());
+                                if let Some((from, to, ..)) = last_applied_rule.read().as_ref() {
+                                    roots.extend([*from, *to]);
+                                }
+                                let remap = pool.write().collect_garbage(&roots);
+                                let new_expr = current_expr.read().and_then(|id| remap.expr(id));
+                                let new_highlight = highlighted_subexpr.read().and_then(|id| remap.expr(id));
+                                let new_last_applied = last_applied_rule.read().as_ref().and_then(|(from, to, rule_id, m)| {
+                                    Some((remap.expr(*from)?, remap.expr(*to)?, *rule_id, remap.remap_match(m)?))
+                                });
+                                let new_matches: Vec<_> = current_matches.read().iter().filter_map(|m| remap.remap_match(m)).collect();
+                                let new_limits: Vec<_> = match_limits.read().iter().filter_map(|&limit| remap.remap_match_limit(limit)).collect();
+                                current_expr.set(new_expr);
+                                highlighted_subexpr.set(new_highlight);
+                                last_applied_rule.set(new_last_applied);
+                                current_matches.set(new_matches);
+                                match_limits.set(new_limits);
+                                hovered_rule_index.set(None);
+                            },
+                            "Collect Garbage"
+                        }
+
                         button {
                             class: format!("px-3 py-2 rounded transition-colors text-sm {}",
                                 if *rules_panel_collapsed.read() {
@@ -190,9 +227,31 @@ pub fn ExplorerPage(pool: Signal<Pool>) -> Element {
 
                     if !*rules_panel_collapsed.read() {
                         div { class: "w-80 flex-shrink-0 bg-white shadow-lg border-l border-gray-200 overflow-y-auto",
+                            if let Some(message) = apply_error.read().as_ref() {
+                                div { class: format!("{} m-4 mb-0 text-sm", super::super::styles::ERROR_BOX),
+                                    "{message}"
+                                }
+                            }
+                            for (idx, limit) in match_limits.read().iter().enumerate() {
+                                div {
+                                    key: "{idx}",
+                                    class: format!("{} m-4 mb-0 text-sm", super::super::styles::WARNING_BOX),
+                                    {pool.read().describe_match_limit(limit)}
+                                }
+                            }
                             RulesSidebar {
                                 pool: pool,
                                 matches: current_matches.read().clone(),
+                                inactive_rulesets: inactive_rulesets.read().clone(),
+                                on_toggle_ruleset: move |ruleset_id| {
+                                    let mut inactive = inactive_rulesets.write();
+                                    match inactive.iter().position(|&id| id == ruleset_id) {
+                                        Some(index) => {
+                                            inactive.remove(index);
+                                        }
+                                        None => inactive.push(ruleset_id),
+                                    }
+                                },
                                 hovered_rule_index: hovered_rule_index,
                                 on_hover_rule: move |index| {
                                     hovered_rule_index.set(index);
@@ -210,10 +269,20 @@ pub fn ExplorerPage(pool: Signal<Pool>) -> Element {
                                     let match_clone = match_.clone();
                                     
                                     let mut p = pool.write();
-                                    if let Some(new_expr) = p.apply_rule(&match_) {
-                                        // Track the last applied rule with the match info
-                                        last_applied_rule.set(Some((from_expr, new_expr, rule_id, match_clone)));
-                                        current_expr.set(Some(new_expr));
+                                    match p.apply_rule(&match_) {
+                                        Ok(new_expr) => {
+                                            // Track the last applied rule with the match info
+                                            last_applied_rule.set(Some((from_expr, new_expr, rule_id, match_clone)));
+                                            current_expr.set(Some(new_expr));
+                                        }
+                                        Err(error) => {
+                                            let rule_name = p.display_name(p[rule_id].name);
+                                            apply_error.set(Some(format!(
+                                                "Cannot apply {}: {}",
+                                                rule_name,
+                                                p.describe_rewrite_error(&error)
+                                            )));
+                                        }
                                     }
                                 },
                             }
@@ -308,19 +377,69 @@ fn ExpressionQuickSelector(
     }
 }
 
+/// The parsed expressions, which the selectors offer to go back to and which
+/// garbage collection must keep.
+fn parsed_roots(pool: &Pool) -> Vec<ExprId> {
+    pool.get_all_roots()
+        .filter(|&root| matches!(pool.get_provenance(root), Some(Provenance::Parsed(_))))
+        .collect()
+}
+
+/// Every rule while all rulesets are active, otherwise only the rules of the
+/// active ones.
+fn active_rule_scope(pool: &Pool, inactive_rulesets: &[RulesetId]) -> RuleScope {
+    if inactive_rulesets.is_empty() {
+        return RuleScope::All;
+    }
+    RuleScope::Rulesets(
+        (0..pool.rulesets.len())
+            .map(RulesetId)
+            .filter(|ruleset_id| !inactive_rulesets.contains(ruleset_id))
+            .collect(),
+    )
+}
+
 #[component]
 fn RulesSidebar(
     pool: Signal<Pool>,
     matches: Vec<Match>,
+    inactive_rulesets: Vec<RulesetId>,
+    on_toggle_ruleset: EventHandler<RulesetId>,
     hovered_rule_index: Signal<Option<usize>>,
     on_hover_rule: EventHandler<Option<usize>>,
     on_apply_rule: EventHandler<Match>,
 ) -> Element {
     let mut search_query = use_signal(String::new);
+    let ruleset_names: Vec<_> = {
+        let pool_ref = pool.read();
+        (0..pool_ref.rulesets.len())
+            .map(|id| (RulesetId(id), pool_ref.display_name(pool_ref.rulesets[id].name)))
+            .collect()
+    };
     
     rsx! {
         div { class: "p-4",
             h2 { class: "text-lg font-semibold mb-4", "Applicable Rules" }
+
+            if ruleset_names.len() > 1 {
+                div { class: "mb-4",
+                    div { class: "text-sm text-gray-600 mb-1", "Active rulesets:" }
+                    div { class: "flex flex-wrap gap-2",
+                        for (ruleset_id, name) in ruleset_names {
+                            label {
+                                key: "{ruleset_id.0}",
+                                class: "flex items-center gap-1 text-sm",
+                                input {
+                                    r#type: "checkbox",
+                                    checked: !inactive_rulesets.contains(&ruleset_id),
+                                    onchange: move |_| on_toggle_ruleset.call(ruleset_id),
+                                }
+                                "{name}"
+                            }
+                        }
+                    }
+                }
+            }
             
             if !matches.is_empty() {
                 div { class: "mb-4",
@@ -427,6 +546,14 @@ fn RuleMatchItem(
                     "Pattern: {pool_ref.display_with_children(PatternId(rule.pattern.0))}"
                     br {}
                     "Action: {pool_ref.display_with_children(ActionId(rule.action.0))}"
+                    if let Some(guard) = rule.guard {
+                        br {}
+                        "Guard: {pool_ref.display_with_children(guard)}"
+                    }
+                    if let Some(equation) = pool_ref.display_equation(match_.rule_id) {
+                        br {}
+                        "Equation: {equation}"
+                    }
                 }
 
                 if !match_.captures.is_empty() {
@@ -438,17 +565,7 @@ fn RuleMatchItem(
                                     span { class: "text-purple-600 font-mono", "?{pool_ref[*name_id]}" }
                                     span { class: "text-gray-400", "→" }
                                     span { class: "text-gray-700 font-mono text-xs",
-                                        match captured_value {
-                                            crate::rules::CapturedValue::Expression(expr_id) => {
-                                                pool_ref.display_with_children(*expr_id).to_string()
-                                            }
-                                            crate::rules::CapturedValue::Function(fun_id) => {
-                                                format!("fn:{}", pool_ref[*fun_id])
-                                            }
-                                            crate::rules::CapturedValue::StructName(name_id) => {
-                                                format!("struct:{}", pool_ref[*name_id])
-                                            }
-                                        }
+                                        {pool_ref.display_captured_value(captured_value)}
                                     }
                                 }
                             }
@@ -468,6 +585,7 @@ fn RuleMatchItem(
 fn SearchPanel(
     pool: Signal<Pool>,
     current_expr: ExprId,
+    scope: RuleScope,
     on_select_expr: EventHandler<ExprId>,
 ) -> Element {
     let mut source_expr = use_signal(|| current_expr);
@@ -524,7 +642,7 @@ fn SearchPanel(
                         }
                     }
                     if let Some(error) = target_expr_error.read().as_ref() {
-                        div { class: "text-red-500 text-xs mt-1", "{error}" }
+                        div { class: "text-red-500 text-xs mt-1 whitespace-pre-wrap", "{error}" }
                     }
                 }
             }
@@ -604,20 +722,23 @@ fn SearchPanel(
                         drop(pool_write);
                         
                         let mut pool_write = pool.write();
-                        let mut config = SearchConfig::default();
-                        config.max_depth = depth;
+                        let config = SearchConfig {
+                            max_depth: depth,
+                            ..SearchConfig::default()
+                        };
                         
                         let mut engine = SearchEngine::new(config);
                         let paths = match strategy.as_str() {
-                            "bfs" => engine.bounded_bfs(&mut pool_write, source, target),
+                            "bfs" => engine.bounded_bfs(&mut pool_write, source, target, &scope),
                             "dijkstra" => engine.bounded_dijkstra(
                                 &mut pool_write,
                                 source, 
                                 target,
+                                &scope,
                                 |_, _, _, _| 1.0
                             ),
                             "beam" => {
-                                engine.beam_search(&mut pool_write, source, |pool, expr, _path| {
+                                engine.beam_search(&mut pool_write, source, &scope, |pool, expr, _path| {
                                     if let Some(t) = target {
                                         if pool.expr_eq(expr, t) { 1000.0 } else { 1.0 }
                                     } else {
@@ -625,7 +746,7 @@ fn SearchPanel(
                                     }
                                 })
                             },
-                            "random" => engine.random_search(&mut pool_write, source, 10),
+                            "random" => engine.random_search(&mut pool_write, source, &scope, 10),
                             _ => vec![],
                         };
                         drop(pool_write);
@@ -20,6 +20,7 @@ pub fn HowToPage() -> Element {
                             ol { class: "list-decimal list-inside mt-2 space-y-1",
                                 li { "Go to the Input page to enter expressions and rulesets" }
                                 li { "Navigate to Explorer to visualize and transform expressions" }
+                                li { "With several rulesets loaded, untick a ruleset in the Explorer sidebar to leave its rules out of matching and search" }
                             }
                         }
                     }
@@ -44,12 +45,26 @@ pub fn HowToPage() -> Element {
                             code: "algebra {{\n  distribute: ?a * (?b + ?c) => a * b + a * c\n  commute_add: ?x + ?y => y + x\n  identity: ?x + 0 => x\n}}"
                         }
 
+                        p { class: "mt-2", "A file may contain several rulesets. Use // for line comments and /* */ for block comments. A ruleset can include the rules of another with use name; inside its body or with name extends other {{ ... }}." }
+                        p { class: "mt-2", "Write name: lhs <=> rhs for a rule that works both ways. It adds name: lhs => rhs and b_name: rhs => lhs, so both sides must use the same ?x and #x variables." }
+                        p { class: "mt-2", "A rule may end with a guard, e.g. div_self: ?x / ?x => 1 when x != 0. Guards compare captured values with == != < <= > >=, can test is_number(x) or is_variable(x), and combine conditions with &&, || and !. [a > 1] is the same as a > 1." }
+                        p { class: "mt-2", "Declare your own operators with infixl 6 <+>, infixr 8 ^>, infix 4 ~= or prefix 9 ~, at the top of a file or inside a ruleset. The digit is the precedence: + and - are 6, * and / are 7 and ^ is 8. Once declared, the operator can be used in rules and expressions, e.g. ?a <+> 0 => a." }
+                        p { class: "mt-2", "Declare ac + identity 0, assoc <+> or comm f to match calls modulo associativity and commutativity. With ac + identity 0, ?x + 0 also matches 0 + y, ?x + 0 matches y + 0 + z with x = y + z, and #a * ?x + #b * ?x matches x + 2 * x with a = 1." }
+
                         div { class: "bg-yellow-50 border-l-4 border-yellow-400 p-4 mt-4",
                             p { class: "font-semibold", "Pattern Types:" }
                             ul { class: "list-disc list-inside mt-2 space-y-1",
                                 li { "x, y, a, b - Match exact variables (literal variable names)" }
                                 li { "?x, ?y, ?a, ?b - Wildcards (match any expression)" }
                                 li { "#x, #y, #a, #b - AnyNumber (match any numeric value)" }
+                                li { "?x:var, ?x:call, ?x:struct - Match only a variable, a function call or a struct" }
+                                li { "?x:ground - Match a term without variables; ?x:nonnum matches anything but a number" }
+                                li { "not(p) - Match any term p does not match, e.g. ?x * not(1)" }
+                                li { "p where ?y != 1, ?x != ?y - Match p only if each capture differs from the pattern after its !=" }
+                                li { "C[p] - Match p anywhere inside the term and capture the rest as C; the action C[a] puts a in its place, e.g. C[?x - ?x] => C[0]" }
+                                li { "?xs... - Sequence (any number of consecutive arguments of a call or struct, spliced back with xs..., e.g. sum(?x, ?xs...) => sum(xs..., x))" }
+                                li { "3//4 - Exact rational literal (compute blocks divide exactly)" }
+                                li { "0.5, 1e-3 - Float literal (any float operand makes a compute block use floats)" }
                                 li { "Patterns on the left side match expressions" }
                                 li { "Actions on the right side define transformations" }
                             }
@@ -68,6 +83,10 @@ pub fn HowToPage() -> Element {
                                 li { "Literal values - Direct replacement (e.g., 0, x + y)" }
                                 li { "Variable substitution - Use captured patterns (x, a, b without prefixes)" }
                                 li { "[expr] - Compute operations for mathematical evaluation" }
+                                li { "Inside [...]: + - * / ^, a % b, div(a, b), abs, min, max, gcd, lcm, comparisons (1 or 0) and if(c, a, b), which only evaluates the branch it picks" }
+                                li { "Captures bound to numeric subtrees are evaluated, so ?x matching 2 * 3 gives [x + 1] = 7" }
+                                li { "If a compute block fails, e.g. by dividing by zero, the rule is not applied and the Explorer says why" }
+                                li { "fresh(n) - A new variable (n_1, n_2, ...) in every application, e.g. 0 => fresh(n) * 0" }
                             }
                         }
                     }
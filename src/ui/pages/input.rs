@@ -3,7 +3,10 @@ use super::super::{
     file_utils,
     navigation::Page,
     primitives::{ErrorDisplay, UIError},
-    state::{InputStateProvider, use_expression_input, use_parsing_state, use_ruleset_input},
+    state::{
+        InputState, InputStateProvider, use_expression_input, use_parsing_state,
+        use_ruleset_input,
+    },
     styles,
 };
 use crate::{Pool, parser};
@@ -184,25 +187,9 @@ fn InputPageContent(pool: Signal<Pool>, on_navigate: EventHandler<Page>) -> Elem
                                                         
                                                         // Auto-parse the uploaded ruleset
                                                         let mut p = pool.write();
-                                                        match parser::parse_ruleset(&contents, &mut p) {
-                                                            Ok(ruleset_id) => {
-                                                                state.ruleset_error = None;
-                                                                state.ruleset_result = Some(ruleset_id);
-                                                                if !state.parsed_rulesets.contains(&ruleset_id) {
-                                                                    state.parsed_rulesets.push(ruleset_id);
-                                                                }
-                                                                if state.selected_ruleset.is_none() {
-                                                                    state.selected_ruleset = Some(ruleset_id);
-                                                                }
-                                                            }
-                                                            Err(e) => {
-                                                                state.ruleset_error = Some(UIError::ParseError {
-                                                                    message: format!("Ruleset: {}", e),
-                                                                    position: None
-                                                                });
-                                                                state.ruleset_result = None;
-                                                            }
-                                                        }
+                                                        // A file may hold several rulesets; register all of them
+                                                        let parsed = parser::parse_rulesets_with_diagnostics(&contents, &mut p);
+                                                        register_rulesets(&mut state, &parsed);
                                                     }
                                                 });
                                             }
@@ -238,30 +225,10 @@ fn InputPageContent(pool: Signal<Pool>, on_navigate: EventHandler<Page>) -> Elem
                                 onclick: move |_| {
                                     let mut p = pool.write();
                                     let current_text = input_state.read().ruleset_text.clone();
-                                    match parser::parse_ruleset(&current_text, &mut p) {
-                                        Ok(ruleset_id) => {
-                                            let mut state = input_state.write();
-                                            state.ruleset_error = None;
-                                            state.ruleset_result = Some(ruleset_id);
-                                            // Add to parsed rulesets list if not already present
-                                            if !state.parsed_rulesets.contains(&ruleset_id) {
-                                                state.parsed_rulesets.push(ruleset_id);
-                                            }
-                                            // Auto-select if it's the first parsed ruleset
-                                            if state.selected_ruleset.is_none() {
-                                                state.selected_ruleset = Some(ruleset_id);
-                                            }
-                                        }
-                                        Err(e) => {
-                                            let ui_error = UIError::ParseError {
-                                                message: format!("Ruleset: {}", e),
-                                                position: None
-                                            };
-                                            let mut state = input_state.write();
-                                            state.ruleset_error = Some(ui_error);
-                                            state.ruleset_result = None;
-                                        }
-                                    }
+                                    // Valid rules are kept even if others fail, so the
+                                    // partial ruleset is usable while the errors are fixed
+                                    let parsed = parser::parse_rulesets_with_diagnostics(&current_text, &mut p);
+                                    register_rulesets(&mut input_state.write(), &parsed);
                                 },
                                 "Parse Ruleset"
                             }
@@ -349,7 +316,7 @@ fn InputPageContent(pool: Signal<Pool>, on_navigate: EventHandler<Page>) -> Elem
                                                                 Err(e) => {
                                                                     state.expr_error = Some(UIError::ParseError {
                                                                         message: e.to_string(),
-                                                                        position: None
+                                                                        position: Some(e.span().start)
                                                                     });
                                                                     state.expr_result = None;
                                                                 }
@@ -408,7 +375,7 @@ fn InputPageContent(pool: Signal<Pool>, on_navigate: EventHandler<Page>) -> Elem
                                         Err(e) => {
                                             let ui_error = UIError::ParseError {
                                                 message: e.to_string(),
-                                                position: None
+                                                position: Some(e.span().start)
                                             };
                                             let mut state = input_state.write();
                                             state.expr_error = Some(ui_error);
@@ -456,7 +423,9 @@ fn InputPageContent(pool: Signal<Pool>, on_navigate: EventHandler<Page>) -> Elem
                                 spawn(async move {
                                     if let Some((_filename, contents)) = file_utils::read_file_from_event(&evt).await {
                                         match serde_json::from_str::<crate::Pool>(&contents) {
-                                            Ok(imported_pool) => {
+                                            Ok(mut imported_pool) => {
+                                                imported_pool.update_rule_index();
+                                                imported_pool.rebuild_hash_cons();
                                                 pool.set(imported_pool);
                                                 log::info!("Pool imported successfully");
                                                 // Navigate to explorer page after successful import
@@ -489,6 +458,17 @@ fn InputPageContent(pool: Signal<Pool>, on_navigate: EventHandler<Page>) -> Elem
                             },
                             "💾 Export Pool"
                         }
+
+                        // Export the pool's rulesets as ruleset source
+                        button {
+                            class: format!("{} text-sm", styles::BTN_SECONDARY),
+                            disabled: pool.read().rulesets.is_empty(),
+                            onclick: move |_| {
+                                let source = pool.read().rulesets_to_source();
+                                file_utils::download_text_file("rules.txt", &source);
+                            },
+                            "📜 Export Rules"
+                        }
                     }
                 }
                 div { class: styles::GRID_3,
@@ -508,4 +488,29 @@ fn InputPageContent(pool: Signal<Pool>, on_navigate: EventHandler<Page>) -> Elem
             }
         }
     }
-}
\ No newline at end of file
+}
+/// Records the rulesets parsed from one text and reports the diagnostics of
+/// every ruleset in it.
+fn register_rulesets(state: &mut InputState, parsed: &[parser::RulesetParse]) {
+    for ruleset_id in parsed.iter().filter_map(|result| result.ruleset) {
+        // Add to parsed rulesets list if not already present
+        if !state.parsed_rulesets.contains(&ruleset_id) {
+            state.parsed_rulesets.push(ruleset_id);
+        }
+        // Auto-select if it's the first parsed ruleset
+        if state.selected_ruleset.is_none() {
+            state.selected_ruleset = Some(ruleset_id);
+        }
+    }
+    state.ruleset_result = parsed.iter().find_map(|result| result.ruleset);
+
+    let errors: Vec<_> = parsed.iter().flat_map(|result| &result.errors).collect();
+    state.ruleset_error = errors.first().map(|first| UIError::ParseError {
+        message: errors
+            .iter()
+            .map(|e| format!("Ruleset: {}", e))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        position: Some(first.span().start),
+    });
+}
@@ -433,12 +433,12 @@ pub fn Graph<T: Clone + std::hash::Hash + Eq + std::fmt::Display + 'static>(
                                     if let Ok(edge_idx) = edge_idx_str.parse::<usize>() {
                                         // Check if mouse is within the label bounding box
                                         if mouse_x >= top_left.x && mouse_x <= bottom_right.x &&
-                                           mouse_y >= top_left.y && mouse_y <= bottom_right.y {
-                                            if let Some(edge) = edges.get(edge_idx) {
-                                                handler.call(Some((edge_idx, edge.from.clone(), edge.to.clone())));
-                                                found_edge = true;
-                                                break;
-                                            }
+                                           mouse_y >= top_left.y && mouse_y <= bottom_right.y
+                                            && let Some(edge) = edges.get(edge_idx)
+                                        {
+                                            handler.call(Some((edge_idx, edge.from.clone(), edge.to.clone())));
+                                            found_edge = true;
+                                            break;
                                         }
                                     }
                                 }
@@ -640,39 +640,29 @@ pub fn TransformationGraph(
                         ]));
                         
                         // Captures section (if any)
-                        if let Some(ref match_) = match_with_captures {
-                            if !match_.captures.is_empty() {
-                                let mut capture_records = vec![];
-                                
-                                // Create capture records with each capture as a horizontal array
-                                for (name_id, captured_value) in &match_.captures {
-                                    let var_name = format!("?{}", pool_ref[*name_id]);
-                                    let value_str = match captured_value {
-                                        crate::rules::CapturedValue::Expression(expr_id) => {
-                                            pool_ref.display_with_children(*expr_id).to_string()
-                                        }
-                                        crate::rules::CapturedValue::Function(fun_id) => {
-                                            format!("fn:{}", pool_ref[*fun_id])
-                                        }
-                                        crate::rules::CapturedValue::StructName(name_id_val) => {
-                                            format!("struct:{}", pool_ref[*name_id_val])
-                                        }
-                                    };
-                                    
-                                    // Each capture is a horizontal array: [var, arrow, value]
-                                    capture_records.push(RecordDef::Array(vec![
-                                        RecordDef::new_text(&var_name),
-                                        RecordDef::new_text("→"),
-                                        RecordDef::new_text(&value_str),
-                                    ]));
-                                }
+                        if let Some(ref match_) = match_with_captures
+                            && !match_.captures.is_empty()
+                        {
+                            let mut capture_records = vec![];
+                            
+                            // Create capture records with each capture as a horizontal array
+                            for (name_id, captured_value) in &match_.captures {
+                                let var_name = format!("?{}", pool_ref[*name_id]);
+                                let value_str = pool_ref.display_captured_value(captured_value);
                                 
-                                // Captures section with header and nested capture records
-                                main_fields.push(RecordDef::Array(vec![
-                                    RecordDef::new_text("Captures:"),
-                                    RecordDef::Array(capture_records),
+                                // Each capture is a horizontal array: [var, arrow, value]
+                                capture_records.push(RecordDef::Array(vec![
+                                    RecordDef::new_text(&var_name),
+                                    RecordDef::new_text("→"),
+                                    RecordDef::new_text(&value_str),
                                 ]));
                             }
+                            
+                            // Captures section with header and nested capture records
+                            main_fields.push(RecordDef::Array(vec![
+                                RecordDef::new_text("Captures:"),
+                                RecordDef::Array(capture_records),
+                            ]));
                         }
                         
                         let shape = Some(ShapeKind::Record(RecordDef::Array(main_fields)));
@@ -973,7 +963,7 @@ pub fn ErrorDisplay(
                                 ErrorSeverity::Critical => "Critical Error",
                             }
                         }
-                        div { class: "text-sm", "{error}" }
+                        div { class: "text-sm whitespace-pre-wrap", "{error}" }
 
                         if show_details {
                             details { class: "mt-2",
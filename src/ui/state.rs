@@ -1,7 +1,10 @@
 use super::{display_components::ViewMode, navigation::Page, primitives::UIError};
-use crate::{ExprId, Pool, RuleId, RulesetId, rules::Match};
+use crate::{
+    ExprId, Pool, RuleId, RulesetId,
+    rules::{Match, MatchLimit, RuleScope},
+};
 use dioxus::prelude::*;
-use serde::{Serialize, Deserialize};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -47,9 +50,15 @@ impl Default for ExpressionState {
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct RulesState {
     pub current_matches: Vec<Match>,
+    /// Rules whose matches were cut short, see `Pool::find_matches_with_limits`.
+    #[serde(default)]
+    pub match_limits: Vec<MatchLimit>,
     pub applied_rules_history: Vec<(ExprId, RuleId, ExprId)>,
     pub hovered_rule_index: Option<usize>,
     pub rules_panel_collapsed: bool,
+    /// The rules `current_matches` are found with.
+    #[serde(default)]
+    pub rule_scope: RuleScope,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -178,9 +187,11 @@ pub fn set_current_expression(
     expression_state.current_expr = expr_id;
 
     if let Some(expr_id) = expr_id {
-        rules_state.current_matches = pool.find_matches(expr_id);
+        (rules_state.current_matches, rules_state.match_limits) =
+            pool.find_matches_with_limits(expr_id, &rules_state.rule_scope);
     } else {
         rules_state.current_matches.clear();
+        rules_state.match_limits.clear();
     }
 }
 
@@ -212,16 +223,16 @@ pub fn apply_rule_at_index(
     if let (Some(match_info), Some(current_expr)) = (
         rules_state.current_matches.get(match_index).cloned(),
         expression_state.current_expr,
-    ) {
-        if let Some(result_expr) = pool.apply_rule(&match_info) {
-            rules_state
-                .applied_rules_history
-                .push((current_expr, match_info.rule_id, result_expr));
+    ) && let Ok(result_expr) = pool.apply_rule(&match_info)
+    {
+        rules_state
+            .applied_rules_history
+            .push((current_expr, match_info.rule_id, result_expr));
 
-            expression_state.current_expr = Some(result_expr);
+        expression_state.current_expr = Some(result_expr);
 
-            rules_state.current_matches = pool.find_matches(result_expr);
-        }
+        (rules_state.current_matches, rules_state.match_limits) =
+            pool.find_matches_with_limits(result_expr, &rules_state.rule_scope);
     }
 }
 
@@ -345,13 +345,13 @@ impl RenderBackend for DioxusSVGWriter {
         
         // Extract edge index from properties if available
         let mut edge_id_opt = None;
-        if let Some(ref prop_str) = properties {
-            if let Some(edge_idx_start) = prop_str.find("data-edge-index='") {
-                let edge_idx_start = edge_idx_start + 17; // length of "data-edge-index='"
-                if let Some(edge_idx_end) = prop_str[edge_idx_start..].find('\'') {
-                    let edge_id = prop_str[edge_idx_start..edge_idx_start + edge_idx_end].to_string();
-                    edge_id_opt = Some(edge_id);
-                }
+        if let Some(ref prop_str) = properties
+            && let Some(edge_idx_start) = prop_str.find("data-edge-index='")
+        {
+            let edge_idx_start = edge_idx_start + 17; // length of "data-edge-index='"
+            if let Some(edge_idx_end) = prop_str[edge_idx_start..].find('\'') {
+                let edge_id = prop_str[edge_idx_start..edge_idx_start + edge_idx_end].to_string();
+                edge_id_opt = Some(edge_id);
             }
         }
 
@@ -20,6 +20,7 @@ mod algebraic_transformations {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -49,6 +50,7 @@ mod algebraic_transformations {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -78,6 +80,7 @@ mod algebraic_transformations {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -107,6 +110,7 @@ mod algebraic_transformations {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -141,6 +145,7 @@ mod function_transformations {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -170,6 +175,7 @@ mod function_transformations {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -199,6 +205,7 @@ mod function_transformations {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -233,8 +240,9 @@ mod complex_pattern_scenarios {
             name: rule1_name,
             pattern: pattern1,
             action: action1,
+            guard: None,
         };
-        let rule1_id = pool.add_rule(rule1);
+        let _rule1_id = pool.add_rule(rule1);
 
         let pattern2 = parse_test_pattern_into("?x * 1", &mut pool);
         let action2 = parse_test_action_into("x", &mut pool);
@@ -243,8 +251,9 @@ mod complex_pattern_scenarios {
             name: rule2_name,
             pattern: pattern2,
             action: action2,
+            guard: None,
         };
-        let rule2_id = pool.add_rule(rule2);
+        let _rule2_id = pool.add_rule(rule2);
 
         let mut current_expr = expr;
         let mut iterations = 0;
@@ -261,7 +270,7 @@ mod complex_pattern_scenarios {
                 break;
             }
 
-            if let Some(new_expr) = pool.apply_rule(&matches[0]) {
+            if let Ok(new_expr) = pool.apply_rule(&matches[0]) {
                 current_expr = new_expr;
             } else {
                 break;
@@ -283,6 +292,7 @@ mod complex_pattern_scenarios {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -312,6 +322,7 @@ mod complex_pattern_scenarios {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -347,8 +358,9 @@ mod optimization_transformations {
             name: rule1_name,
             pattern,
             action,
+            guard: None,
         };
-        let rule1_id = pool.add_rule(rule1);
+        let _rule1_id = pool.add_rule(rule1);
 
         let pattern2 = parse_test_pattern_into("4 + 1", &mut pool);
         let action2 = parse_test_action_into("5", &mut pool);
@@ -357,8 +369,9 @@ mod optimization_transformations {
             name: rule2_name,
             pattern: pattern2,
             action: action2,
+            guard: None,
         };
-        let rule2_id = pool.add_rule(rule2);
+        let _rule2_id = pool.add_rule(rule2);
 
         let pattern3 = parse_test_pattern_into("5 * 5", &mut pool);
         let action3 = parse_test_action_into("25", &mut pool);
@@ -367,6 +380,7 @@ mod optimization_transformations {
             name: rule3_name,
             pattern: pattern3,
             action: action3,
+            guard: None,
         };
         let _rule3_id = pool.add_rule(rule3);
 
@@ -385,7 +399,7 @@ mod optimization_transformations {
                 break;
             }
 
-            if let Some(new_expr) = pool.apply_rule(&matches[0]) {
+            if let Ok(new_expr) = pool.apply_rule(&matches[0]) {
                 current_expr = new_expr;
             } else {
                 break;
@@ -407,6 +421,7 @@ mod optimization_transformations {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -436,8 +451,9 @@ mod optimization_transformations {
             name: rule1_name,
             pattern: pattern1,
             action: action1,
+            guard: None,
         };
-        let rule1_id = pool.add_rule(rule1);
+        let _rule1_id = pool.add_rule(rule1);
 
         let pattern2 = parse_test_pattern_into("?x * 1", &mut pool);
         let action2 = parse_test_action_into("x", &mut pool);
@@ -446,8 +462,9 @@ mod optimization_transformations {
             name: rule2_name,
             pattern: pattern2,
             action: action2,
+            guard: None,
         };
-        let rule2_id = pool.add_rule(rule2);
+        let _rule2_id = pool.add_rule(rule2);
 
         let mut current_expr = expr;
         loop {
@@ -456,7 +473,7 @@ mod optimization_transformations {
                 break;
             }
 
-            if let Some(new_expr) = pool.apply_rule(&matches[0]) {
+            if let Ok(new_expr) = pool.apply_rule(&matches[0]) {
                 current_expr = new_expr;
             } else {
                 break;
@@ -482,6 +499,7 @@ mod rule_interaction_tests {
             name: rule1_name,
             pattern: pattern1,
             action: action1,
+            guard: None,
         };
         let rule1_id = pool.add_rule(rule1);
 
@@ -492,6 +510,7 @@ mod rule_interaction_tests {
             name: rule2_name,
             pattern: pattern2,
             action: action2,
+            guard: None,
         };
         let rule2_id = pool.add_rule(rule2);
 
@@ -502,7 +521,7 @@ mod rule_interaction_tests {
         assert!(matching_rules.contains(&rule1_id));
         assert!(matching_rules.contains(&rule2_id));
 
-        if let Some(result) = pool.apply_rule(&matches[0]) {
+        if let Ok(result) = pool.apply_rule(&matches[0]) {
             let result_display = pool.display_with_children(result);
             assert!(result_display == "0" || result_display == "(0 * x)");
         }
@@ -519,6 +538,7 @@ mod rule_interaction_tests {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -534,7 +554,7 @@ mod rule_interaction_tests {
                 break;
             }
 
-            if let Some(new_expr) = pool.apply_rule(applicable_matches[0]) {
+            if let Ok(new_expr) = pool.apply_rule(applicable_matches[0]) {
                 current_expr = new_expr;
                 application_count += 1;
             } else {
@@ -557,6 +577,7 @@ mod rule_interaction_tests {
             name: rule1_name,
             pattern: pattern1,
             action: action1,
+            guard: None,
         };
         let rule1_id = pool.add_rule(rule1);
 
@@ -567,6 +588,7 @@ mod rule_interaction_tests {
             name: rule2_name,
             pattern: pattern2,
             action: action2,
+            guard: None,
         };
         let rule2_id = pool.add_rule(rule2);
 
@@ -579,3 +601,176 @@ mod rule_interaction_tests {
         assert!(matching_rules.contains(&rule2_id));
     }
 }
+
+#[cfg(test)]
+mod rule_index {
+    use super::*;
+    use expression_explorer::parser::{parse_expression, parse_ruleset};
+    use expression_explorer::rule_index::RuleIndex;
+
+    const RULES: &str = "ac * identity 1
+r {
+  add_zero: ?x + 0 => x
+  mul_one: ?x * 1 => x
+  combine: #a * ?x + #b * ?x => [a + b] * x
+  fold: #a + #b => [a + b]
+  any_call: ?f(?x) => x
+  name: x => y
+  one: 1 => 2
+  point: Point{?xs...} => Point{}
+  any: ?e => e
+}";
+
+    fn rule_names(pool: &Pool, rule_ids: Vec<RuleId>) -> Vec<String> {
+        rule_ids
+            .into_iter()
+            .map(|rule_id| pool.display_name(pool[rule_id].name))
+            .collect()
+    }
+
+    #[test]
+    fn test_only_rules_with_a_fitting_head_are_tried() {
+        let mut pool = new_test_pool();
+        parse_ruleset(RULES, &mut pool).unwrap();
+
+        let sum = parse_expression("a + 0", &mut pool).unwrap();
+        assert_eq!(
+            rule_names(&pool, pool.candidate_rules(sum)),
+            ["add_zero", "mul_one", "combine", "fold", "any_call", "any"]
+        );
+        let one = parse_expression("1", &mut pool).unwrap();
+        assert_eq!(
+            rule_names(&pool, pool.candidate_rules(one)),
+            ["mul_one", "one", "any"]
+        );
+        let x = parse_expression("x", &mut pool).unwrap();
+        assert_eq!(
+            rule_names(&pool, pool.candidate_rules(x)),
+            ["mul_one", "name", "any"]
+        );
+    }
+
+    #[test]
+    fn test_indexed_matches_equal_unindexed_matches() {
+        let mut pool = new_test_pool();
+        parse_ruleset(RULES, &mut pool).unwrap();
+        let expr = parse_expression(
+            "f(x + 2 * x, Point{1, y}, 1 * (3 + 4), g(0 + z))",
+            &mut pool,
+        )
+        .unwrap();
+
+        let indexed = pool.find_matches(expr);
+        let mut unindexed_pool = pool.clone();
+        unindexed_pool.rule_index = RuleIndex::default();
+        assert_eq!(unindexed_pool.candidate_rules(expr).len(), pool.rules.len());
+        assert_eq!(unindexed_pool.find_matches(expr), indexed);
+        assert!(indexed.len() > 10);
+    }
+
+    #[test]
+    fn test_index_catches_up_after_deserializing() {
+        let mut pool = new_test_pool();
+        parse_ruleset(RULES, &mut pool).unwrap();
+        let expr = parse_expression("a + 0", &mut pool).unwrap();
+        let expected = pool.find_matches(expr);
+
+        let json = serde_json::to_string(&pool).unwrap();
+        let mut restored: Pool = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.find_matches(expr), expected);
+        restored.update_rule_index();
+        assert_eq!(restored.candidate_rules(expr), pool.candidate_rules(expr));
+        assert_eq!(restored.find_matches(expr), expected);
+    }
+}
+
+#[cfg(test)]
+mod rule_scopes {
+    use super::*;
+    use expression_explorer::parser::{parse_expression, parse_rulesets};
+    use expression_explorer::search::{SearchConfig, SearchEngine};
+
+    const RULESETS: &str = "base {
+  add_zero: ?x + 0 => x
+}
+algebra {
+  use base;
+  commute: ?x + ?y => y + x
+}
+other {
+  to_zero: ?x + ?x => 0
+}";
+
+    fn matched_rules(pool: &Pool, matches: &[Match]) -> Vec<String> {
+        let mut names: Vec<_> = matches
+            .iter()
+            .map(|m| pool.display_name(pool[m.rule_id].name))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    #[test]
+    fn test_matches_are_limited_to_the_scope() {
+        let mut pool = new_test_pool();
+        let rulesets = parse_rulesets(RULESETS, &mut pool).unwrap();
+        let expr = parse_expression("0 + 0", &mut pool).unwrap();
+
+        let all = pool.find_matches(expr);
+        assert_eq!(pool.find_matches_in(expr, &RuleScope::All), all);
+        assert_eq!(
+            matched_rules(&pool, &all),
+            ["add_zero", "commute", "to_zero"]
+        );
+
+        let algebra = pool.find_matches_in(expr, &RuleScope::Rulesets(vec![rulesets[1]]));
+        assert_eq!(matched_rules(&pool, &algebra), ["add_zero", "commute"]);
+
+        let both = RuleScope::Rulesets(vec![rulesets[0], rulesets[2]]);
+        assert_eq!(
+            matched_rules(&pool, &pool.find_matches_in(expr, &both)),
+            ["add_zero", "to_zero"]
+        );
+
+        let to_zero = pool.ruleset_rule_ids(rulesets[2]);
+        let rules = pool.find_matches_in(expr, &RuleScope::Rules(to_zero));
+        assert_eq!(matched_rules(&pool, &rules), ["to_zero"]);
+        assert!(
+            pool.find_matches_in(expr, &RuleScope::Rules(Vec::new()))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_search_only_uses_rules_in_scope() {
+        let mut pool = new_test_pool();
+        let rulesets = parse_rulesets(RULESETS, &mut pool).unwrap();
+        let start = parse_expression("x + y", &mut pool).unwrap();
+        let target = parse_expression("y + x", &mut pool).unwrap();
+        pool.update_equivalence_groups(start);
+        let config = SearchConfig {
+            max_depth: 3,
+            max_nodes_explored: 50,
+            ..SearchConfig::default()
+        };
+
+        let other = RuleScope::Rulesets(vec![rulesets[2]]);
+        let paths =
+            SearchEngine::new(config.clone()).bounded_bfs(&mut pool, start, Some(target), &other);
+        assert!(paths.is_empty());
+
+        let algebra = RuleScope::Rulesets(vec![rulesets[1]]);
+        let paths =
+            SearchEngine::new(config.clone()).bounded_bfs(&mut pool, start, Some(target), &algebra);
+        assert!(!paths.is_empty());
+        let allowed = pool.rules_in_scope(&algebra).unwrap();
+        for path in &paths {
+            assert!(path.steps.iter().all(|(_, rule, _)| allowed.contains(rule)));
+        }
+
+        // The transformation found above is not reused outside its scope.
+        let paths = SearchEngine::new(config).bounded_bfs(&mut pool, start, Some(target), &other);
+        assert!(paths.is_empty());
+    }
+}
@@ -1,7 +1,10 @@
+#![allow(dead_code)]
+
 use expression_explorer::children::Children;
 use expression_explorer::parser::actions::parse_action;
 use expression_explorer::parser::patterns::parse_pattern;
 use expression_explorer::parser::*;
+use expression_explorer::rules::{Match, RewriteError};
 use expression_explorer::*;
 
 pub fn new_test_pool() -> Pool {
@@ -14,7 +17,6 @@ pub fn parse_test_expr(input: &str) -> (Pool, ExprId) {
     (pool, expr_id)
 }
 
-
 pub fn assert_expr_display(pool: &Pool, expr: ExprId, expected: &str) {
     let actual = pool.display_with_children(expr);
     assert_eq!(
@@ -32,7 +34,8 @@ pub fn make_var(pool: &mut Pool, name: &str) -> ExprId {
 }
 
 pub fn make_num(pool: &mut Pool, value: i32) -> ExprId {
-    let expr_id = pool.add_expr(ExprNode::Number(value));
+    let number_id = pool.intern_number(value.into());
+    let expr_id = pool.add_expr(ExprNode::Number(number_id));
     pool.mark_expr_end(expr_id);
     expr_id
 }
@@ -45,6 +48,35 @@ pub fn parse_test_action_into(input: &str, pool: &mut Pool) -> ActionId {
     parse_action(input, pool).expect("Failed to parse test action")
 }
 
+/// Parses `input`, registers a single `pattern => action` rule and applies it
+/// at the root.
+pub fn apply_test_rule(
+    input: &str,
+    pattern: &str,
+    action: &str,
+) -> (Pool, Result<ExprId, RewriteError>) {
+    let (mut pool, expr) = parse_test_expr(input);
+    let pattern = parse_test_pattern_into(pattern, &mut pool);
+    let action = parse_test_action_into(action, &mut pool);
+    let rule_name = pool.intern_string("test_rule".to_string());
+    let rule_id = pool.add_rule(Rule {
+        name: rule_name,
+        pattern,
+        action,
+        guard: None,
+    });
+
+    let mut captures = std::collections::HashMap::new();
+    assert!(pool.pattern_matches(pattern, expr, &mut captures));
+    let result = pool.apply_rule(&Match {
+        root: expr,
+        offset: expr,
+        rule_id,
+        captures,
+    });
+    (pool, result)
+}
+
 pub fn get_children_vec(pool: &Pool, expr: ExprId) -> Vec<ExprId> {
     pool.children(expr).collect()
 }
@@ -75,7 +107,6 @@ pub fn parse_test_ruleset(input: &str) -> (Pool, RulesetId) {
     (pool, ruleset_id)
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -20,6 +20,7 @@ mod end_to_end_workflows {
             name: rule1_name,
             pattern: pattern1,
             action: action1,
+            guard: None,
         };
         let _rule1_id = pool.add_rule(rule1);
 
@@ -30,6 +31,7 @@ mod end_to_end_workflows {
             name: rule2_name,
             pattern: pattern2,
             action: action2,
+            guard: None,
         };
         let _rule2_id = pool.add_rule(rule2);
 
@@ -39,7 +41,7 @@ mod end_to_end_workflows {
 
         let first_match = &matches[0];
         let result1 = pool.apply_rule(first_match);
-        assert!(result1.is_some());
+        assert!(result1.is_ok());
 
         let new_expr = result1.unwrap();
         let display = pool.display_with_children(new_expr);
@@ -62,6 +64,7 @@ mod end_to_end_workflows {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -79,7 +82,7 @@ mod end_to_end_workflows {
             let applicable_match = matches.iter().find(|m| m.rule_id == rule_id);
 
             if let Some(match_obj) = applicable_match {
-                if let Some(new_expr) = pool.apply_rule(match_obj) {
+                if let Ok(new_expr) = pool.apply_rule(match_obj) {
                     expr = new_expr;
                 } else {
                     break;
@@ -105,6 +108,7 @@ mod end_to_end_workflows {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -114,7 +118,7 @@ mod end_to_end_workflows {
 
         assert!(double_matches.len() >= 4);
 
-        if let Some(result) = pool.apply_rule(double_matches[0]) {
+        if let Ok(result) = pool.apply_rule(double_matches[0]) {
             let result_display = pool.display_with_children(result);
             assert!(result_display.contains("2 *"));
         }
@@ -146,7 +150,7 @@ mod ruleset_integration {
         let matches = pool.find_matches(expr);
         assert!(!matches.is_empty());
 
-        if let Some(result) = pool.apply_rule(&matches[0]) {
+        if let Ok(result) = pool.apply_rule(&matches[0]) {
             let result_display = pool.display_with_children(result);
             assert_ne!(result_display, pool.display_with_children(expr));
         }
@@ -163,6 +167,7 @@ mod ruleset_integration {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -193,6 +198,7 @@ mod performance_and_edge_cases {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let _rule_id = pool.add_rule(rule);
 
@@ -211,6 +217,7 @@ mod performance_and_edge_cases {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -238,6 +245,7 @@ mod performance_and_edge_cases {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let _rule_id = pool.add_rule(rule);
 
@@ -2,7 +2,11 @@ mod common;
 
 use common::*;
 use expression_explorer::parser::patterns::parse_pattern;
-use expression_explorer::parser::{parse_expression, parse_ruleset};
+use expression_explorer::{DisplayNode, ExprNode, RulesetId};
+use expression_explorer::parser::{
+    ParseError, parse_expression, parse_ruleset, parse_ruleset_with_diagnostics, parse_rulesets,
+    parse_rulesets_with_diagnostics,
+};
 
 #[cfg(test)]
 mod expression_parser_tests {
@@ -22,6 +26,48 @@ mod expression_parser_tests {
         assert_eq!(count_nodes(&pool, expr), 1);
     }
 
+    #[test]
+    fn test_numbers_beyond_i32() {
+        let (mut pool, expr) = parse_test_expr("123456789012345678901234567890 + 1");
+        assert_expr_display(&pool, expr, "(123456789012345678901234567890 + 1)");
+
+        let again = parse_expression("123456789012345678901234567890", &mut pool).unwrap();
+        let children = get_children_vec(&pool, expr);
+        assert_eq!(pool[children[1]], pool[again]);
+    }
+
+    #[test]
+    fn test_rational_literals_are_normalized() {
+        let (mut pool, expr) = parse_test_expr("6//8 + 4//2");
+        assert_expr_display(&pool, expr, "(3//4 + 2)");
+
+        let children = get_children_vec(&pool, expr);
+        assert!(matches!(pool[children[0]], ExprNode::Number(_)));
+        let again = parse_expression("3//4", &mut pool).unwrap();
+        assert_eq!(pool[children[1]], pool[again]);
+    }
+
+    #[test]
+    fn test_rational_zero_denominator_rejected() {
+        let mut pool = new_test_pool();
+        assert!(parse_expression("1//0", &mut pool).is_err());
+    }
+
+    #[test]
+    fn test_float_literals() {
+        let (pool, expr) = parse_test_expr("0.5 + 1e-3 * 2.5E3");
+        assert_expr_display(&pool, expr, "(0.5 + (0.001 * 2500.0))");
+    }
+
+    #[test]
+    fn test_float_is_distinct_from_exact_value() {
+        let mut pool = new_test_pool();
+        let float = parse_expression("0.5", &mut pool).unwrap();
+        let rational = parse_expression("1//2", &mut pool).unwrap();
+        assert!(matches!(pool[float], ExprNode::Float(_)));
+        assert!(!pool.expr_eq(float, rational));
+    }
+
     #[test]
     fn test_simple_addition() {
         let (pool, expr) = parse_test_expr("(x + y)");
@@ -298,6 +344,469 @@ mod ruleset_parser_tests {
         let ruleset_data = &pool[ruleset];
         assert_eq!(ruleset_data.rules_end - ruleset_data.rules_start, 2);
     }
+
+    #[test]
+    fn test_rule_and_action_locations_are_absolute() {
+        let text = "rules {\n  first: ?x + 0 => x\n  second: ?x * 2 => x + [2 * 3]\n}";
+        let (pool, ruleset) = parse_test_ruleset(text);
+
+        let second = pool[ruleset].rules_start + 1;
+        let rule_location = pool.rule_locations[second].clone();
+        assert_eq!(
+            &text[rule_location.start..rule_location.end],
+            "second: ?x * 2 => x + [2 * 3]"
+        );
+
+        let action_location = pool.get_action_location(pool.rules[second].action).unwrap();
+        assert_eq!(
+            text[action_location.start..action_location.end].trim_end(),
+            "x + [2 * 3]"
+        );
+    }
+
+    #[test]
+    fn test_rule_bodies_with_braces_and_line_breaks() {
+        let text = r#"structs {
+  unwrap: Box{?x} => x
+  wrap: ?x => Box{x}
+  sum: Pair{?a, ?b} => [a +
+    b]
+}"#;
+        let (pool, ruleset) = parse_test_ruleset(text);
+        assert_eq!(pool[ruleset].rules_end - pool[ruleset].rules_start, 3);
+    }
+
+    #[test]
+    fn test_comments_are_ignored() {
+        let text = r#"// Basic identities
+algebra {
+  /* additive
+     identity */
+  add_zero: ?x + 0 => x // keeps x
+  mul_one: ?x * 1 => x
+}"#;
+        let (pool, ruleset) = parse_test_ruleset(text);
+        assert_eq!(pool[ruleset].rules_end - pool[ruleset].rules_start, 2);
+    }
+
+    #[test]
+    fn test_line_comment_does_not_swallow_rationals() {
+        let (pool, expr) = parse_test_expr("3//4 + x // note");
+        assert_expr_display(&pool, expr, "(3//4 + x)");
+
+        let mut pool = new_test_pool();
+        assert!(parse_expression("x//2", &mut pool).is_err());
+    }
+
+    #[test]
+    fn test_multiple_rulesets_in_one_file() {
+        let mut pool = new_test_pool();
+        let text = r#"
+// first file section
+algebra {
+  add_zero: ?x + 0 => x
+}
+
+/* second section */
+calculus {
+  const: d(#c) => 0
+  linear: d(?x) => 1
+}
+"#;
+        let rulesets = parse_rulesets(text, &mut pool).unwrap();
+
+        let names: Vec<_> = rulesets
+            .iter()
+            .map(|&id| pool.display_name(pool[id].name))
+            .collect();
+        assert_eq!(names, ["algebra", "calculus"]);
+        assert_eq!(pool.get_ruleset_rule_count(rulesets[1]), 2);
+    }
+
+    #[test]
+    fn test_multiple_rulesets_strict_failure_registers_none() {
+        let mut pool = new_test_pool();
+        let text = "good {\n  a: ?x => x\n}\nbad {\n  b: ?x * => x\n}";
+        let err = parse_rulesets(text, &mut pool).unwrap_err();
+        assert_eq!(err.rule_name(), Some("b"));
+        assert_eq!(pool.rulesets.len(), 0);
+    }
+
+    #[test]
+    fn test_strict_failure_rolls_back_rules() {
+        let mut pool = new_test_pool();
+        parse_rulesets("base {\n  k: k => 0\n}", &mut pool).unwrap();
+        let rules_before = pool.rules.len();
+        let patterns_before = pool.patterns.len();
+        let actions_before = pool.actions.len();
+
+        let text =
+            "good {\n  g: a => b\n  one: 1 * ?x <=> ?x\n}\nbad {\n  b1: x => y\n  b2: ?p => ?q\n}";
+        assert!(parse_rulesets(text, &mut pool).is_err());
+        assert_eq!(pool.rulesets.len(), 1);
+        assert_eq!(pool.rules.len(), rules_before);
+        assert_eq!(pool.patterns.len(), patterns_before);
+        assert_eq!(pool.actions.len(), actions_before);
+        assert_eq!(pool.rule_reverses.len(), 0);
+
+        for input in ["a", "x"] {
+            let expr = parse_expression(input, &mut pool).unwrap();
+            assert!(pool.find_matches(expr).is_empty(), "{input} still matches");
+        }
+        let k = parse_expression("k", &mut pool).unwrap();
+        assert_eq!(pool.find_matches(k).len(), 1);
+
+        // Rules added afterwards get indexed under their own ids.
+        parse_rulesets("later {\n  l: a => c\n}", &mut pool).unwrap();
+        let a = parse_expression("a", &mut pool).unwrap();
+        let matches = pool.find_matches(a);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].rule_id.0, rules_before);
+    }
+
+    #[test]
+    fn test_multiple_rulesets_with_diagnostics() {
+        let mut pool = new_test_pool();
+        let text = "good {\n  a: ?x => x\n}\nbad {\n  b: ?x * => x\n  c: ?x => x\n}";
+        let parsed = parse_rulesets_with_diagnostics(text, &mut pool);
+
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed[0].is_ok());
+        let partial = parsed[1].ruleset.unwrap();
+        assert_eq!(pool.get_ruleset_rule_count(partial), 1);
+        assert_eq!(parsed[1].errors[0].span().line, 5);
+    }
+
+    fn rule_names(pool: &expression_explorer::Pool, ruleset: RulesetId) -> Vec<String> {
+        pool.get_ruleset_rules(ruleset)
+            .map(|rule| pool.display_name(rule.name))
+            .collect()
+    }
+
+    #[test]
+    fn test_use_and_extends_import_rules_transitively() {
+        let mut pool = new_test_pool();
+        let text = r#"
+monoid {
+  identity: ?x + 0 => x
+}
+group extends monoid {
+  inverse: ?x + -?x => 0
+}
+abelian {
+  use group;
+  commute: ?x + ?y => y + x
+}
+"#;
+        let rulesets = parse_rulesets(text, &mut pool).unwrap();
+
+        assert_eq!(rule_names(&pool, rulesets[1]), ["identity", "inverse"]);
+        assert_eq!(
+            rule_names(&pool, rulesets[2]),
+            ["identity", "inverse", "commute"]
+        );
+        assert_eq!(pool.get_ruleset_rule_count(rulesets[2]), 3);
+    }
+
+    #[test]
+    fn test_imports_may_refer_forward_and_to_earlier_input() {
+        let mut pool = new_test_pool();
+        let base = parse_ruleset("base {\n  a: ?x => x\n}", &mut pool).unwrap();
+        let text = "first extends second, base {\n}\nsecond {\n  b: ?y => y\n}";
+        let rulesets = parse_rulesets(text, &mut pool).unwrap();
+
+        assert_eq!(pool[rulesets[0]].imports, [rulesets[1], base]);
+        assert_eq!(rule_names(&pool, rulesets[0]), ["b", "a"]);
+    }
+
+    #[test]
+    fn test_repeated_import_is_kept_once() {
+        let mut pool = new_test_pool();
+        let text = "t {\n  a: ?x => x\n}\nu extends t {\n  use t;\n}";
+        let rulesets = parse_rulesets(text, &mut pool).unwrap();
+
+        assert_eq!(pool[rulesets[1]].imports, [rulesets[0]]);
+        assert_eq!(pool.ruleset_to_source(rulesets[1]), "u {\n  use t;\n}\n");
+    }
+
+    #[test]
+    fn test_unknown_import_is_reported() {
+        let mut pool = new_test_pool();
+        let text = "rules {\n  use missing;\n  a: ?x => x\n}";
+
+        let err = parse_ruleset(text, &mut pool).unwrap_err();
+        assert!(matches!(err, ParseError::Import { .. }));
+        assert_eq!(err.span().line, 2);
+        assert_eq!(pool.rulesets.len(), 0);
+
+        let parsed = parse_ruleset_with_diagnostics(text, &mut pool);
+        let ruleset = parsed.ruleset.unwrap();
+        assert_eq!(rule_names(&pool, ruleset), ["a"]);
+        assert_eq!(parsed.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_circular_imports_are_rejected() {
+        let mut pool = new_test_pool();
+        let text = "a {\n  use b;\n  x: ?x => x\n}\nb {\n  use a;\n  y: ?y => y\n}";
+
+        let err = parse_rulesets(text, &mut pool).unwrap_err();
+        assert_eq!(err.message(), "circular import b -> a -> b");
+        assert_eq!(pool.rulesets.len(), 0);
+
+        let parsed = parse_rulesets_with_diagnostics(text, &mut pool);
+        assert!(parsed[0].is_ok());
+        assert_eq!(parsed[1].errors.len(), 1);
+        let b = parsed[1].ruleset.unwrap();
+        assert_eq!(rule_names(&pool, b), ["y"]);
+    }
+
+    #[test]
+    fn test_self_import_is_a_cycle() {
+        let mut pool = new_test_pool();
+        let err = parse_ruleset("loop {\n  use loop;\n}", &mut pool).unwrap_err();
+        assert_eq!(err.message(), "circular import loop -> loop");
+    }
+
+    #[test]
+    fn test_equation_registers_both_directions() {
+        let mut pool = new_test_pool();
+        let text = "laws {\n  add_assoc: (?x + ?y) + ?z <=> ?x + (?y + ?z)\n  add_comm: ?x + ?y => y + x\n}";
+        let ruleset = parse_ruleset(text, &mut pool).unwrap();
+        assert_eq!(rule_names(&pool, ruleset), ["add_assoc", "b_add_assoc", "add_comm"]);
+
+        let ids = pool.ruleset_rule_ids(ruleset);
+        assert_eq!(pool.reverse_rule(ids[0]), Some(ids[1]));
+        assert_eq!(pool.reverse_rule(ids[1]), Some(ids[0]));
+        assert_eq!(pool.reverse_rule(ids[2]), None);
+        assert_eq!(pool.rule_equation(ids[1]), Some((ids[0], ids[1])));
+        assert_eq!(
+            pool.display_equation(ids[1]).unwrap(),
+            "add_assoc: ((?x + ?y) + ?z) <=> (?x + (?y + ?z))"
+        );
+    }
+
+    #[test]
+    fn test_equation_reverse_rule_rewrites_back() {
+        let mut pool = new_test_pool();
+        let ruleset = parse_ruleset("laws {\n  one: 1 * ?x <=> ?x\n}", &mut pool).unwrap();
+        let reverse = pool.ruleset_rule_ids(ruleset)[1];
+
+        let expr = parse_expression("a + b", &mut pool).unwrap();
+        let m = pool
+            .find_matches(expr)
+            .into_iter()
+            .find(|m| m.rule_id == reverse && m.offset == expr)
+            .expect("reverse rule matches the root");
+        let result = pool.apply_rule(&m).unwrap();
+        assert_expr_display(&pool, result, "(1 * (a + b))");
+    }
+
+    #[test]
+    fn test_equation_with_unbound_variable_is_rejected() {
+        let mut pool = new_test_pool();
+        let text = "laws {\n  zero: ?x * 0 <=> 0\n  one: 1 * ?x <=> ?x\n}";
+
+        let err = parse_ruleset(text, &mut pool).unwrap_err();
+        assert_eq!(err.rule_name(), Some("zero"));
+        assert_eq!(
+            err.message(),
+            "in rule 'zero': ?x is not bound by the right-hand side"
+        );
+        assert_eq!((err.span().line, err.span().column), (2, 9));
+        assert!(pool.rulesets.is_empty());
+
+        let parsed = parse_ruleset_with_diagnostics(text, &mut pool);
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(rule_names(&pool, parsed.ruleset.unwrap()), ["one", "b_one"]);
+    }
+
+    #[test]
+    fn test_rule_guards_are_parsed_and_displayed() {
+        let mut pool = new_test_pool();
+        let text = r#"guarded {
+  div_self: ?x / ?x => 1 when x != 0
+  split: #a * ?x => [a - 1] * x + x when [a > 1] && !is_variable(x)
+  plain: ?x + 0 => x
+  when_var: ?when + 0 => when
+}"#;
+        let ruleset = parse_ruleset(text, &mut pool).unwrap();
+        let ids = pool.ruleset_rule_ids(ruleset);
+        let shown: Vec<_> = ids.iter().map(|&id| pool.display_with_children(id)).collect();
+        assert_eq!(shown[0], "div_self: (?x / ?x) => 1 when x != 0");
+        assert_eq!(
+            shown[1],
+            "split: (#a * ?x) => (([a - 1] * x) + x) when (a > 1 && !is_variable(x))"
+        );
+        assert!(pool[ids[2]].guard.is_none());
+        assert!(pool[ids[3]].guard.is_none());
+    }
+
+    #[test]
+    fn test_guard_on_equation_applies_to_both_directions() {
+        let mut pool = new_test_pool();
+        let text = "laws {\n  swap: ?x * ?y <=> ?y * ?x when x != y\n}";
+        let ruleset = parse_ruleset(text, &mut pool).unwrap();
+        let ids = pool.ruleset_rule_ids(ruleset);
+        assert!(pool[ids[0]].guard.is_some());
+        assert_eq!(pool[ids[0]].guard, pool[ids[1]].guard);
+    }
+
+    #[test]
+    fn test_guard_errors() {
+        let mut pool = new_test_pool();
+        let err = parse_ruleset("r {\n  bad: ?x / ?x => 1 when y != 0\n}", &mut pool).unwrap_err();
+        assert_eq!(err.rule_name(), Some("bad"));
+        assert_eq!(err.message(), "in rule 'bad': y is not bound by the pattern");
+        assert_eq!((err.span().line, err.span().column), (2, 26));
+
+        let text = "r {\n  broken: ?x / ?x => 1 when x !=\n  ok: ?x + 0 => x\n}";
+        let parsed = parse_ruleset_with_diagnostics(text, &mut pool);
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].rule_name(), Some("broken"));
+        assert_eq!(rule_names(&pool, parsed.ruleset.unwrap()), ["ok"]);
+    }
+
+    #[test]
+    fn test_recovery_reports_every_broken_rule() {
+        let mut pool = new_test_pool();
+        let text = r#"mixed {
+  first: ?x + 0 => x
+  broken_pattern: ?x * => x
+  second: ?x * 1 => x
+  broken_action: ?x - ?x => x +
+  third: ?x ^ 1 => x
+}"#;
+        let parsed = parse_ruleset_with_diagnostics(text, &mut pool);
+
+        let ruleset = parsed.ruleset.expect("partial ruleset");
+        let rule_names: Vec<_> = pool
+            .get_ruleset_rules(ruleset)
+            .map(|rule| pool.display_name(rule.name))
+            .collect();
+        assert_eq!(rule_names, ["first", "second", "third"]);
+
+        let failed: Vec<_> = parsed.errors.iter().map(|e| e.rule_name()).collect();
+        assert_eq!(failed, [Some("broken_pattern"), Some("broken_action")]);
+        assert_eq!(parsed.errors[0].span().line, 3);
+        assert_eq!(parsed.errors[1].span().line, 5);
+        assert!(!parsed.is_ok());
+    }
+
+    #[test]
+    fn test_recovery_skips_malformed_lines() {
+        let mut pool = new_test_pool();
+        let text = "rules {\n  good: ?x + 0 => x\n  no colon here\n}";
+        let parsed = parse_ruleset_with_diagnostics(text, &mut pool);
+
+        let ruleset = parsed.ruleset.expect("partial ruleset");
+        assert_eq!(pool[ruleset].rules_end - pool[ruleset].rules_start, 1);
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].span().line, 3);
+        assert_eq!(parsed.errors[0].expected(), ["rule"]);
+    }
+
+    #[test]
+    fn test_recovery_without_header_has_no_ruleset() {
+        let mut pool = new_test_pool();
+        let parsed = parse_ruleset_with_diagnostics("rules  good: ?x => x }", &mut pool);
+        assert_eq!(parsed.ruleset, None);
+        assert_eq!(parsed.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_strict_parse_registers_nothing_on_error() {
+        let mut pool = new_test_pool();
+        let text = "rules {\n  good: ?x + 0 => x\n  bad: ?x * => x\n}";
+        assert!(parse_ruleset(text, &mut pool).is_err());
+        assert_eq!(pool.rulesets.len(), 0);
+    }
+}
+
+#[cfg(test)]
+mod operator_declaration_tests {
+    use super::*;
+    use expression_explorer::Fixity;
+
+    #[test]
+    fn test_declared_operators_parse_and_display() {
+        let mut pool = new_test_pool();
+        let text = "infixl 6 <+>\nprefix 9 ~\nops {\n  zero: ?a <+> 0 => a\n  twice: ~ ~?a => a\n}";
+        let ruleset = parse_ruleset(text, &mut pool).unwrap();
+        let shown: Vec<_> = pool
+            .ruleset_rule_ids(ruleset)
+            .iter()
+            .map(|&id| pool.display_with_children(id))
+            .collect();
+        assert_eq!(shown, ["zero: (?a <+> 0) => a", "twice: (~(~?a)) => a"]);
+
+        let expr = parse_expression("a <+> b <+> c * d", &mut pool).unwrap();
+        assert_expr_display(&pool, expr, "((a <+> b) <+> (c * d))");
+        let expr = parse_expression("~x ^ 2 + ~f(y)", &mut pool).unwrap();
+        assert_expr_display(&pool, expr, "(((~x) ^ 2) + (~f(y)))");
+    }
+
+    #[test]
+    fn test_precedence_and_associativity() {
+        let mut pool = new_test_pool();
+        pool.declare_operator("^>", Fixity::InfixRight, 8);
+        pool.declare_operator("<|", Fixity::InfixLeft, 1);
+        pool.declare_operator("==>", Fixity::Infix, 4);
+
+        let expr = parse_expression("a ^> b ^> c", &mut pool).unwrap();
+        assert_expr_display(&pool, expr, "(a ^> (b ^> c))");
+        let expr = parse_expression("a + b <| c * d", &mut pool).unwrap();
+        assert_expr_display(&pool, expr, "((a + b) <| (c * d))");
+        let expr = parse_expression("a + b ==> c", &mut pool).unwrap();
+        assert_expr_display(&pool, expr, "((a + b) ==> c)");
+        let expr = parse_expression("x*-y - 2^3^z", &mut pool).unwrap();
+        assert_expr_display(&pool, expr, "((x * (-y)) - (2 ^ (3 ^ z)))");
+
+        let err = parse_expression("a ==> b ==> c", &mut pool).unwrap_err();
+        assert_eq!(err.message(), "==> is non-associative and needs parentheses here");
+        assert!(parse_expression("(a ==> b) ==> c", &mut pool).is_ok());
+    }
+
+    #[test]
+    fn test_undeclared_operators_are_rejected() {
+        let mut pool = new_test_pool();
+        let err = parse_expression("a <+> b", &mut pool).unwrap_err();
+        assert_eq!(err.message(), "<+> is not declared as an infix operator");
+        assert_eq!(err.span().column, 3);
+
+        pool.declare_operator("~", Fixity::InfixLeft, 5);
+        let err = parse_expression("~a", &mut pool).unwrap_err();
+        assert_eq!(err.message(), "~ is not declared as a prefix operator");
+    }
+
+    #[test]
+    fn test_operators_inside_ruleset_and_strict_failure() {
+        let mut pool = new_test_pool();
+        let text = "ops {\n  infixr 5 <>\n  assoc: (?a <> ?b) <> ?c => a <> b <> c\n}";
+        let ruleset = parse_ruleset(text, &mut pool).unwrap();
+        let rule = pool.ruleset_rule_ids(ruleset)[0];
+        assert_eq!(
+            pool.display_with_children(rule),
+            "assoc: ((?a <> ?b) <> ?c) => (a <> (b <> c))"
+        );
+
+        let mut pool = new_test_pool();
+        let text = "infixl 6 <+>\nbad {\n  broken: ?a <+> => a\n}";
+        assert!(parse_ruleset(text, &mut pool).is_err());
+        assert!(pool.operators.is_empty());
+    }
+
+    #[test]
+    fn test_rules_rewrite_declared_operators() {
+        let mut pool = new_test_pool();
+        let text = "infixl 6 <+>\nops {\n  zero: ?a <+> 0 => a\n}";
+        parse_ruleset(text, &mut pool).unwrap();
+        let expr = parse_expression("(x * y) <+> 0", &mut pool).unwrap();
+        let matches = pool.find_matches(expr);
+        assert_eq!(matches.len(), 1);
+        let result = pool.apply_rule(&matches[0]).unwrap();
+        assert_expr_display(&pool, result, "(x * y)");
+    }
 }
 
 #[cfg(test)]
@@ -331,4 +840,48 @@ mod error_handling_tests {
         let result = parse_ruleset("invalid ruleset", &mut pool);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_error_span_and_expected_tokens() {
+        let mut pool = new_test_pool();
+        let err = parse_expression("f(x +)", &mut pool).unwrap_err();
+
+        assert!(matches!(err, ParseError::Syntax { .. }));
+        assert_eq!(err.span().line, 1);
+        assert_eq!(err.span().column, 6);
+        assert_eq!(err.span().start, 5);
+        assert!(!err.expected().is_empty());
+        assert_eq!(err.snippet(), "1 | f(x +)\n  |      ^");
+    }
+
+    #[test]
+    fn test_invalid_literal_span() {
+        let mut pool = new_test_pool();
+        let err = parse_expression("x + 1//0", &mut pool).unwrap_err();
+
+        assert!(matches!(err, ParseError::InvalidLiteral { .. }));
+        assert_eq!((err.span().start, err.span().end), (4, 8));
+        assert!(err.snippet().ends_with("    ^^^^"));
+    }
+
+    #[test]
+    fn test_ruleset_error_names_rule_with_absolute_location() {
+        let mut pool = new_test_pool();
+        let text = "rules {\n  good: ?x + 0 => x\n  bad: ?x * => x\n}";
+        let err = parse_ruleset(text, &mut pool).unwrap_err();
+
+        assert_eq!(err.rule_name(), Some("bad"));
+        assert_eq!(err.span().line, 3);
+        assert!(err.snippet().starts_with("3 |   bad: ?x * => x"));
+        assert!(err.to_string().contains("in rule 'bad'"));
+    }
+
+    #[test]
+    fn test_odd_input_does_not_panic() {
+        for input in ["", "   ", "(", ")", "f(", "//", "x y"] {
+            let mut pool = new_test_pool();
+            assert!(parse_expression(input, &mut pool).is_err(), "{input:?}");
+            assert!(parse_pattern(input, &mut pool).is_err(), "{input:?}");
+        }
+    }
 }
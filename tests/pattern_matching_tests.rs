@@ -1,10 +1,41 @@
 mod common;
 
 use common::*;
+use expression_explorer::parser::parse_ruleset;
 use expression_explorer::rules;
 use expression_explorer::*;
 use std::collections::HashMap;
 
+/// Whether `pattern` matches `input` at its root.
+fn matches(pattern: &str, input: &str) -> bool {
+    let (mut pool, expr) = parse_test_expr(input);
+    let pattern = parse_test_pattern_into(pattern, &mut pool);
+    pool.pattern_matches(pattern, expr, &mut HashMap::new())
+}
+
+/// The matches in `expr` as sorted `rule: subexpression` lines.
+fn applied_rules(pool: &Pool, expr: ExprId) -> Vec<String> {
+    let mut applied: Vec<_> = pool
+        .find_matches(expr)
+        .iter()
+        .map(|m| {
+            let name = pool.display_name(pool[m.rule_id].name);
+            format!("{name}: {}", pool.display_with_children(m.offset))
+        })
+        .collect();
+    applied.sort();
+    applied
+}
+
+/// The source of `ruleset`, checked to load back into a fresh pool unchanged.
+fn reloaded_source(pool: &Pool, ruleset: RulesetId) -> String {
+    let source = pool.ruleset_to_source(ruleset);
+    let mut reloaded = new_test_pool();
+    let reloaded_id = parse_ruleset(&source, &mut reloaded).unwrap();
+    assert_eq!(reloaded.ruleset_to_source(reloaded_id), source);
+    source
+}
+
 #[cfg(test)]
 mod simple_pattern_matching {
     use super::*;
@@ -266,6 +297,18 @@ mod function_pattern_matching {
         assert!(has_function_capture);
         assert!(has_expression_capture);
     }
+
+    #[test]
+    fn test_captured_function_shows_its_name() {
+        let (mut pool, expr) = parse_test_expr("sin(x)");
+        let pattern = parse_test_pattern_into("?f(?x)", &mut pool);
+
+        let mut captures = HashMap::new();
+        assert!(pool.pattern_matches(pattern, expr, &mut captures));
+
+        let f = pool.intern_string("f".to_string());
+        assert_eq!(pool.display_captured_value(&captures[&f]), "fn:sin");
+    }
 }
 
 #[cfg(test)]
@@ -336,3 +379,459 @@ mod complex_pattern_scenarios {
         }
     }
 }
+
+#[cfg(test)]
+mod theories {
+    use super::*;
+    use expression_explorer::parser::{
+        parse_expression, parse_ruleset, parse_ruleset_with_diagnostics,
+    };
+    use expression_explorer::rules::{MAX_MATCHES_PER_RULE, MatchLimit, RuleScope};
+    use expression_explorer::theories::{MAX_SPLIT_OPERANDS, Theory};
+    use std::time::{Duration, Instant};
+
+    const AC_PLUS: Theory = Theory {
+        associative: true,
+        commutative: true,
+        identity: None,
+    };
+
+    fn solutions(pool: &Pool, pattern: PatternId, expr: ExprId) -> Vec<Vec<String>> {
+        let mut names: Vec<_> = pool
+            .patterns
+            .iter()
+            .filter_map(|node| match node {
+                Pattern::Wildcard(name) | Pattern::AnyNumber(name) => Some(*name),
+                _ => None,
+            })
+            .collect();
+        names.sort_by_key(|name| pool.display_name(*name));
+        names.dedup();
+        let mut found: Vec<Vec<String>> = pool
+            .pattern_matches_all(pattern, expr, &HashMap::new())
+            .iter()
+            .map(|captures| {
+                names
+                    .iter()
+                    .filter_map(|name| captures.get(name))
+                    .map(|value| pool.display_captured_value(value))
+                    .collect()
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn test_without_theories_matching_is_syntactic() {
+        let (mut pool, expr) = parse_test_expr("0 + y");
+        let pattern = parse_test_pattern_into("?x + 0", &mut pool);
+        assert!(solutions(&pool, pattern, expr).is_empty());
+    }
+
+    #[test]
+    fn test_commutative_operands_match_in_any_order() {
+        let (mut pool, expr) = parse_test_expr("0 + y");
+        pool.declare_theory(
+            "+",
+            Theory {
+                commutative: true,
+                ..Theory::default()
+            },
+        );
+        let pattern = parse_test_pattern_into("?x + 0", &mut pool);
+        assert_eq!(solutions(&pool, pattern, expr), vec![vec!["y"]]);
+    }
+
+    #[test]
+    fn test_associative_wildcard_takes_several_operands() {
+        let (mut pool, expr) = parse_test_expr("y + 0 + z");
+        pool.declare_theory("+", AC_PLUS);
+        let pattern = parse_test_pattern_into("?x + 0", &mut pool);
+        assert_eq!(solutions(&pool, pattern, expr), vec![vec!["y + z"]]);
+
+        let action = parse_test_action_into("x", &mut pool);
+        let rule_name = pool.intern_string("zero".to_string());
+        let rule_id = pool.add_rule(Rule {
+            name: rule_name,
+            pattern,
+            action,
+            guard: None,
+        });
+        let mut captures = HashMap::new();
+        assert!(pool.pattern_matches(pattern, expr, &mut captures));
+        let result = pool
+            .apply_rule(&rules::Match {
+                root: expr,
+                offset: expr,
+                rule_id,
+                captures,
+            })
+            .unwrap();
+        assert_expr_display(&pool, result, "(y + z)");
+    }
+
+    #[test]
+    fn test_every_split_is_a_separate_solution() {
+        let (mut pool, expr) = parse_test_expr("a + b + c");
+        pool.declare_theory("+", AC_PLUS);
+        let pattern = parse_test_pattern_into("?x + ?y", &mut pool);
+        let found = solutions(&pool, pattern, expr);
+        assert_eq!(found.len(), 6);
+        assert!(found.contains(&vec!["a + c".to_string(), "b".to_string()]));
+
+        pool.declare_theory(
+            "+",
+            Theory {
+                associative: true,
+                ..Theory::default()
+            },
+        );
+        assert_eq!(
+            solutions(&pool, pattern, expr),
+            vec![vec!["a", "b + c"], vec!["a + b", "c"]]
+        );
+    }
+
+    #[test]
+    fn test_non_linear_pattern_compares_modulo_theory() {
+        let (mut pool, expr) = parse_test_expr("f(a + b, b + a)");
+        pool.declare_theory("+", AC_PLUS);
+        let pattern = parse_test_pattern_into("f(?x, ?x)", &mut pool);
+        assert!(pool.pattern_matches(pattern, expr, &mut HashMap::new()));
+
+        let expr = parse_expression("b + a + b", &mut pool).unwrap();
+        let pattern = parse_test_pattern_into("?x + ?x", &mut pool);
+        assert!(solutions(&pool, pattern, expr).is_empty());
+    }
+
+    #[test]
+    fn test_identity_stands_for_a_missing_number() {
+        let mut pool = new_test_pool();
+        let text = "ac + identity 0
+ac * identity 1
+algebra {
+  combine: #a * ?x + #b * ?x => [a + b] * x
+}";
+        parse_ruleset(text, &mut pool).unwrap();
+        let expr = parse_expression("x + 2 * x", &mut pool).unwrap();
+
+        let matches = pool.find_matches(expr);
+        assert!(!matches.is_empty());
+        for found in &matches {
+            let result = pool.apply_rule(found).unwrap();
+            assert_expr_display(&pool, result, "(3 * x)");
+        }
+    }
+
+    #[test]
+    fn test_guards_filter_each_solution() {
+        let mut pool = new_test_pool();
+        let text = "ac +
+s {
+  pick: ?x + #a => x when a > 1
+}";
+        parse_ruleset(text, &mut pool).unwrap();
+        let expr = parse_expression("1 + y + 5", &mut pool).unwrap();
+
+        let matches = pool.find_matches(expr);
+        let results: Vec<_> = matches
+            .iter()
+            .filter(|found| found.offset == expr)
+            .map(|found| {
+                let result = pool.apply_rule(found).unwrap();
+                pool.display_with_children(result)
+            })
+            .collect();
+        assert_eq!(results, vec!["(1 + y)"]);
+    }
+
+    fn sum_of_variables(count: usize) -> String {
+        (0..count)
+            .map(|index| format!("v{index}"))
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+
+    #[test]
+    fn test_matches_per_rule_are_capped() {
+        let mut pool = new_test_pool();
+        parse_ruleset("ac +\ns {\n  sw: ?x + ?y => y + x\n}", &mut pool).unwrap();
+        let expr = parse_expression(&sum_of_variables(14), &mut pool).unwrap();
+
+        let start = Instant::now();
+        let (matches, limits) = pool.find_matches_with_limits(expr, &RuleScope::All);
+        assert!(
+            start.elapsed() < Duration::from_secs(2),
+            "took {:?}",
+            start.elapsed()
+        );
+
+        let at_root = matches.iter().filter(|found| found.offset == expr).count();
+        assert_eq!(at_root, MAX_MATCHES_PER_RULE);
+        let rule_id = matches[0].rule_id;
+        assert!(limits.contains(&MatchLimit::TooManyMatches {
+            offset: expr,
+            rule_id
+        }));
+        assert!(
+            !limits
+                .iter()
+                .any(|limit| matches!(limit, MatchLimit::TooManyOperands { .. }))
+        );
+    }
+
+    #[test]
+    fn test_match_attempts_are_bounded() {
+        let mut pool = new_test_pool();
+        parse_ruleset("ac +\ns {\n  first: ?a + ?b + ?c => a\n}", &mut pool).unwrap();
+        let expr = parse_expression(&sum_of_variables(14), &mut pool).unwrap();
+
+        let start = Instant::now();
+        let (matches, limits) = pool.find_matches_with_limits(expr, &RuleScope::All);
+        assert!(
+            start.elapsed() < Duration::from_secs(2),
+            "took {:?}",
+            start.elapsed()
+        );
+
+        let at_root = matches.iter().filter(|found| found.offset == expr).count();
+        assert!(at_root > 0 && at_root <= MAX_MATCHES_PER_RULE);
+        assert!(limits.contains(&MatchLimit::TooManyMatches {
+            offset: expr,
+            rule_id: RuleId(0)
+        }));
+    }
+
+    #[test]
+    fn test_split_limit_is_reported() {
+        let mut pool = new_test_pool();
+        parse_ruleset("ac +\ns {\n  drop_w: ?x + ?y + w => x + y\n}", &mut pool).unwrap();
+        let input = format!("{} + w", sum_of_variables(MAX_SPLIT_OPERANDS + 1));
+        let expr = parse_expression(&input, &mut pool).unwrap();
+
+        let (matches, limits) = pool.find_matches_with_limits(expr, &RuleScope::All);
+        assert!(matches.is_empty());
+        assert_eq!(
+            limits,
+            vec![MatchLimit::TooManyOperands {
+                offset: expr,
+                rule_id: RuleId(0)
+            }]
+        );
+        assert!(
+            pool.describe_match_limit(&limits[0])
+                .starts_with("drop_w may match")
+        );
+    }
+
+    #[test]
+    fn test_theory_declarations_parse_and_export() {
+        let mut pool = new_test_pool();
+        let text = "infixl 6 <+>
+ac <+> identity 0
+s {
+  comm f
+  assoc g
+  ac * identity 1//2
+  zero: ?x <+> 0 => x
+}";
+        let ruleset = parse_ruleset(text, &mut pool).unwrap();
+        let theories: Vec<_> = pool
+            .theories
+            .iter()
+            .map(|theory| {
+                (
+                    theory.symbol.as_str(),
+                    theory.associative,
+                    theory.commutative,
+                )
+            })
+            .collect();
+        assert_eq!(
+            theories,
+            vec![
+                ("<+>", true, true),
+                ("f", false, true),
+                ("g", true, false),
+                ("*", true, true)
+            ]
+        );
+
+        let source = pool.ruleset_to_source(ruleset);
+        assert!(
+            source.starts_with(
+                "infixl 6 <+>\nac <+> identity 0\ncomm f\nassoc g\nac * identity 1//2\n"
+            )
+        );
+        let mut reloaded = new_test_pool();
+        let reloaded_id = parse_ruleset(&source, &mut reloaded).unwrap();
+        assert_eq!(reloaded.ruleset_to_source(reloaded_id), source);
+    }
+
+    #[test]
+    fn test_broken_theory_declaration_is_reported() {
+        let mut pool = new_test_pool();
+        assert!(parse_ruleset("ac + identity 1//0\ns {\n}", &mut pool).is_err());
+        assert!(pool.theories.is_empty());
+
+        let result = parse_ruleset_with_diagnostics("ac + identity 1//0\ns {\n}", &mut pool);
+        assert!(result.ruleset.is_some());
+        assert_eq!(result.errors.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod typed_captures {
+    use super::*;
+    use expression_explorer::display::DisplayNode;
+    use expression_explorer::parser::patterns::parse_pattern;
+    use expression_explorer::parser::parse_expression;
+
+    #[test]
+    fn test_each_kind_only_matches_its_nodes() {
+        let cases = [
+            ("?x:var", "x", true),
+            ("?x:var", "2", false),
+            ("?x:var", "f(x)", false),
+            ("?x:call", "f(x)", true),
+            ("?x:call", "a + b", true),
+            ("?x:call", "P{ 1 }", false),
+            ("?x:struct", "P{ 1, x }", true),
+            ("?x:struct", "f(1)", false),
+            ("?x:ground", "2 * f(3, P{ 4 })", true),
+            ("?x:ground", "2 * f(3, P{ y })", false),
+            ("?x:ground", "1//2", true),
+            ("?x:nonnum", "y", true),
+            ("?x:nonnum", "-1", true),
+            ("?x:nonnum", "1.5", false),
+            ("?x:nonnum", "1//2", false),
+        ];
+        for (pattern, input, expected) in cases {
+            assert_eq!(matches(pattern, input), expected, "{pattern} on {input}");
+        }
+    }
+
+    #[test]
+    fn test_typed_captures_nest_and_repeat() {
+        assert!(matches("?a:var * ?b:ground", "x * (1 + 2)"));
+        assert!(!matches("?a:var * ?b:ground", "(1 + 2) * x"));
+        assert!(matches("?x:var + ?x", "y + y"));
+        assert!(!matches("?x:call + ?x", "y + y"));
+        assert!(matches("g(?x:nonnum, ?x:nonnum)", "g(h(1), h(1))"));
+    }
+
+    #[test]
+    fn test_rules_with_typed_captures() {
+        let mut pool = new_test_pool();
+        let ruleset = parse_ruleset(
+            "s {
+  fold: ?a:ground * 0 => 0
+  order: #n * ?x:var => x * n
+}",
+            &mut pool,
+        )
+        .unwrap();
+        let expr = parse_expression("f(2, 3) * 0 + x * 0 + 2 * y + 2 * g(y)", &mut pool).unwrap();
+        assert_eq!(
+            applied_rules(&pool, expr),
+            ["fold: (f(2, 3) * 0)", "order: (2 * y)"]
+        );
+
+        let source = reloaded_source(&pool, ruleset);
+        assert!(source.contains("?a:ground * 0 => 0"));
+        assert!(source.contains("#n * ?x:var => x * n"));
+    }
+
+    #[test]
+    fn test_unknown_kind_is_rejected() {
+        let mut pool = new_test_pool();
+        assert!(parse_pattern("?x:number", &mut pool).is_err());
+        let pattern = parse_pattern("f(?x:call)", &mut pool).unwrap();
+        assert_eq!(pool.display_with_children(pattern), "f(?x:call)");
+    }
+}
+
+#[cfg(test)]
+mod constraints {
+    use super::*;
+    use expression_explorer::display::DisplayNode;
+    use expression_explorer::parser::patterns::parse_pattern;
+    use expression_explorer::parser::parse_expression;
+
+    #[test]
+    fn test_capture_must_not_match_constraint() {
+        assert!(matches("?x * ?y where ?y != 1", "a * b"));
+        assert!(!matches("?x * ?y where ?y != 1", "a * 1"));
+        assert!(matches("?x * ?y where ?y != 1", "1 * a"));
+        assert!(matches("?x + ?y where ?x != ?y", "a + b"));
+        assert!(!matches("?x + ?y where ?x != ?y", "f(a) + f(a)"));
+        assert!(!matches("?x where ?x != f(?z)", "f(2)"));
+        assert!(matches("?x where ?x != f(?z), ?x != 0", "g(2)"));
+        assert!(!matches("?x where ?x != f(?z), ?x != 0", "0"));
+    }
+
+    #[test]
+    fn test_not_rejects_what_its_pattern_matches() {
+        assert!(matches("?x * not(1)", "a * b"));
+        assert!(!matches("?x * not(1)", "a * 1"));
+        assert!(matches("f(not(#n))", "f(x)"));
+        assert!(!matches("f(not(#n))", "f(2)"));
+        assert!(!matches("not(?a * ?a)", "(x + 1) * (x + 1)"));
+        assert!(matches("not(?a * ?a)", "x * y"));
+        assert!(matches("not(not(?x:var))", "x"));
+    }
+
+    #[test]
+    fn test_captures_inside_not_stay_local() {
+        let (mut pool, expr) = parse_test_expr("g(a, b)");
+        let pattern = parse_test_pattern_into("g(?x, not(f(?z)))", &mut pool);
+        let mut captures = HashMap::new();
+        assert!(pool.pattern_matches(pattern, expr, &mut captures));
+        let names: Vec<_> = captures
+            .keys()
+            .map(|&name| pool.display_name(name))
+            .collect();
+        assert_eq!(names, ["x"]);
+    }
+
+    #[test]
+    fn test_constraints_restrict_rules() {
+        let mut pool = new_test_pool();
+        let ruleset = parse_ruleset(
+            "s {
+  comm: ?x + ?y where ?x != ?y => y + x
+  unit: ?x * not(1) => x * 1
+}",
+            &mut pool,
+        )
+        .unwrap();
+        let expr = parse_expression("(a + a) * 1 + (a + b) * c", &mut pool).unwrap();
+        assert_eq!(
+            applied_rules(&pool, expr),
+            [
+                "comm: (((a + a) * 1) + ((a + b) * c))",
+                "comm: (a + b)",
+                "unit: ((a + b) * c)"
+            ]
+        );
+
+        let source = reloaded_source(&pool, ruleset);
+        assert!(source.contains("comm: ?x + ?y where ?x != ?y => y + x"));
+        assert!(source.contains("unit: ?x * not(1) => x * 1"));
+    }
+
+    #[test]
+    fn test_constraints_need_bound_captures() {
+        let mut pool = new_test_pool();
+        assert!(parse_pattern("?x where ?y != 1", &mut pool).is_err());
+        assert!(parse_ruleset("s { r: f(not(?z)) => 1 when z > 0 }", &mut pool).is_err());
+        assert!(parse_ruleset("s { e: not(0) <=> 1 }", &mut pool).is_err());
+
+        let pattern = parse_pattern("f(?x, not(g(?x))) where ?x != 0", &mut pool).unwrap();
+        assert_eq!(
+            pool.display_with_children(pattern),
+            "f(?x, not(g(?x))) where ?x != 0"
+        );
+    }
+}
@@ -2,7 +2,10 @@ mod common;
 
 use common::*;
 use expression_explorer::children::Children;
+use expression_explorer::parser::*;
+use expression_explorer::rules::*;
 use expression_explorer::*;
+use std::collections::HashMap;
 
 #[cfg(test)]
 mod basic_pool_operations {
@@ -244,3 +247,468 @@ mod memory_efficiency_tests {
         assert_eq!(x_count, 1);
     }
 }
+
+#[cfg(test)]
+mod hash_consing_tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_subtrees_share_canonical_id() {
+        let (pool, expr) = parse_test_expr("(x + y) * (x + y)");
+
+        let children = get_children_vec(&pool, expr);
+        assert_ne!(children[0], children[1]);
+        assert_eq!(pool.canonical(children[0]), pool.canonical(children[1]));
+        assert_eq!(pool.expr_hash(children[0]), pool.expr_hash(children[1]));
+        assert!(pool.expr_eq(children[0], children[1]));
+    }
+
+    #[test]
+    fn test_different_subtrees_are_distinct() {
+        let (pool, expr) = parse_test_expr("(x + y) * (y + x)");
+
+        let children = get_children_vec(&pool, expr);
+        assert_ne!(pool.canonical(children[0]), pool.canonical(children[1]));
+        assert!(!pool.expr_eq(children[0], children[1]));
+    }
+
+    #[test]
+    fn test_equal_expressions_across_roots() {
+        let mut pool = new_test_pool();
+        let first = parse_expression("f(a + b, c)", &mut pool).unwrap();
+        let second = parse_expression("f(a + b, c)", &mut pool).unwrap();
+        let third = parse_expression("f(a + b, d)", &mut pool).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(pool.canonical(first), pool.canonical(second));
+        assert_ne!(pool.canonical(first), pool.canonical(third));
+        assert_eq!(pool.find_interned_root(second), Some(first));
+    }
+
+    #[test]
+    fn test_rewrite_copies_unchanged_subtrees_under_their_canonical_id() {
+        let mut pool = new_test_pool();
+        parse_ruleset("s {\n  zero: ?x + 0 => x\n}", &mut pool).unwrap();
+        let expr = parse_expression("f(a * b, x + 0)", &mut pool).unwrap();
+        let found = pool.find_matches(expr).remove(0);
+        let result = pool.apply_rule(&found).unwrap();
+
+        // Children are listed last first, so `a * b` is the second.
+        let product = get_children_vec(&pool, expr)[1];
+        let copied = get_children_vec(&pool, result)[1];
+        assert_ne!(product, copied);
+        assert_eq!(pool.canonical(product), pool.canonical(copied));
+        assert_expr_display(&pool, result, "f((a * b), x)");
+    }
+
+    #[test]
+    fn test_rewrite_to_existing_root_reuses_it() {
+        let mut pool = new_test_pool();
+        let expr = parse_expression("x + y", &mut pool).unwrap();
+        let pattern = parse_test_pattern_into("?a + ?b", &mut pool);
+        let action = parse_test_action_into("b + a", &mut pool);
+        let rule_name = pool.intern_string("comm".to_string());
+        let rule_id = pool.add_rule(Rule {
+            name: rule_name,
+            pattern,
+            action,
+            guard: None,
+        });
+
+        let mut captures = HashMap::new();
+        assert!(pool.pattern_matches(pattern, expr, &mut captures));
+        let swapped = pool
+            .apply_rule(&Match {
+                root: expr,
+                offset: expr,
+                rule_id,
+                captures,
+            })
+            .unwrap();
+        assert_expr_display(&pool, swapped, "(y + x)");
+
+        let size_after_first = pool.exprs.len();
+
+        let mut captures = HashMap::new();
+        assert!(pool.pattern_matches(pattern, swapped, &mut captures));
+        let back = pool
+            .apply_rule(&Match {
+                root: swapped,
+                offset: swapped,
+                rule_id,
+                captures,
+            })
+            .unwrap();
+
+        assert_eq!(back, expr);
+        assert_eq!(pool.exprs.len(), size_after_first);
+        assert_eq!(
+            pool.get_equivalence_group(back),
+            pool.get_equivalence_group(expr)
+        );
+    }
+
+    #[test]
+    fn test_equivalence_groups_use_canonical_ids() {
+        let mut pool = new_test_pool();
+        let first = parse_expression("a * (b + c)", &mut pool).unwrap();
+        let second = parse_expression("a * (b + c)", &mut pool).unwrap();
+        let third = parse_expression("a * (c + b)", &mut pool).unwrap();
+
+        pool.update_equivalence_groups(first);
+        pool.update_equivalence_groups(second);
+        pool.update_equivalence_groups(third);
+
+        assert_eq!(
+            pool.get_equivalence_group(first),
+            pool.get_equivalence_group(second)
+        );
+        assert_ne!(
+            pool.get_equivalence_group(first),
+            pool.get_equivalence_group(third)
+        );
+    }
+
+    #[test]
+    fn test_rebuild_hash_cons_after_raw_push() {
+        let (mut pool, expr) = parse_test_expr("(x + y)");
+
+        let expr_slice: Vec<ExprNode> = pool.get_full_slice(expr).to_vec();
+        for node in expr_slice {
+            pool.exprs.push(node);
+        }
+        let copy = ExprId(pool.exprs.len() - 1);
+        pool.mark_expr_end(copy);
+
+        pool.rebuild_hash_cons();
+
+        assert_eq!(pool.canonical(copy), pool.canonical(expr));
+        assert_eq!(pool.expr_hashes.len(), pool.exprs.len());
+    }
+
+    #[test]
+    fn test_repeated_rewrites_grow_the_pool_boundedly() {
+        let mut pool = new_test_pool();
+        parse_ruleset("s {\n  comm: ?a + ?b => b + a\n}", &mut pool).unwrap();
+        let mut current = parse_expression("(a + b) * (c * d * e * g)", &mut pool).unwrap();
+
+        let mut sizes = Vec::new();
+        for _ in 0..20 {
+            let before = pool.exprs.len();
+            let found = pool.find_matches(current).remove(0);
+            current = pool.apply_rule(&found).unwrap();
+            assert!(pool.exprs.len() - before <= pool.get_full_slice(current).len());
+            sizes.push(pool.exprs.len());
+        }
+
+        // Swapping back and forth only ever stores the two roots.
+        assert!(sizes[1..].iter().all(|&size| size == sizes[0]));
+        assert_expr_display(&pool, current, "((a + b) * (((c * d) * e) * g))");
+    }
+
+    #[test]
+    fn test_hash_cons_tables_are_rebuilt_after_import() {
+        let (pool, expr) = parse_test_expr("(a + b) * (a + b)");
+        let json = serde_json::to_string(&pool).unwrap();
+        for table in [
+            "expr_hashes",
+            "canonical_exprs",
+            "hash_cons",
+            "interned_roots",
+        ] {
+            assert!(
+                !json.contains(&format!("\"{table}\"")),
+                "{table} is exported"
+            );
+        }
+
+        let mut restored: Pool = serde_json::from_str(&json).unwrap();
+        restored.rebuild_hash_cons();
+        let children = get_children_vec(&restored, expr);
+        assert_eq!(
+            restored.canonical(children[0]),
+            restored.canonical(children[1])
+        );
+        assert_eq!(restored.find_interned_root(expr), Some(expr));
+
+        // Adding to a pool that was not rebuilt catches the tables up first.
+        let mut restored: Pool = serde_json::from_str(&json).unwrap();
+        let again = parse_expression("(a + b) * (a + b)", &mut restored).unwrap();
+        assert_eq!(restored.expr_hashes.len(), restored.exprs.len());
+        assert_eq!(restored.canonical(again), restored.canonical(expr));
+        assert_eq!(restored.find_interned_root(again), Some(expr));
+    }
+
+    #[test]
+    fn test_pools_without_literal_tables_deserialize() {
+        let mut pool = new_test_pool();
+        parse_ruleset("s {\n  comm: ?a + ?b => b + a\n}", &mut pool).unwrap();
+        let expr = parse_expression("a + b", &mut pool).unwrap();
+        let found = pool.find_matches(expr).remove(0);
+        let swapped = pool.apply_rule(&found).unwrap();
+
+        let mut json = serde_json::to_value(&pool).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        for field in ["numbers", "rationals", "floats", "canonical_groups"] {
+            fields.remove(field);
+        }
+        let mut restored: Pool = serde_json::from_value(json).unwrap();
+        restored.rebuild_hash_cons();
+
+        let again = parse_expression("b + a", &mut restored).unwrap();
+        restored.update_equivalence_groups(again);
+        assert_eq!(
+            restored.get_equivalence_group(again),
+            restored.get_equivalence_group(swapped)
+        );
+    }
+}
+
+#[cfg(test)]
+mod number_table_tests {
+    use super::*;
+
+    #[test]
+    fn test_numbers_are_interned() {
+        let mut pool = new_test_pool();
+        let big = "98765432109876543210".parse().unwrap();
+        let first = pool.intern_number(big);
+        let second = pool.intern_number("98765432109876543210".parse().unwrap());
+        let small = pool.intern_number(7.into());
+
+        assert_eq!(first, second);
+        assert_ne!(first, small);
+        assert_eq!(pool.display_number(first), "98765432109876543210");
+    }
+
+    #[test]
+    fn test_special_floats_survive_json_round_trip() {
+        let mut pool = new_test_pool();
+        let nan = pool.intern_float(f64::NAN);
+        let negative_zero = pool.intern_float(-0.0);
+
+        let json = serde_json::to_string(&pool).unwrap();
+        let mut restored: Pool = serde_json::from_str(&json).unwrap();
+
+        assert!(restored.float_value(nan).is_nan());
+        assert!(restored.float_value(negative_zero).is_sign_negative());
+        assert_eq!(restored.intern_float(-0.0), negative_zero);
+        assert_ne!(restored.intern_float(0.0), negative_zero);
+    }
+
+    #[test]
+    fn test_numbers_survive_json_round_trip() {
+        let (pool, expr) = parse_test_expr("98765432109876543210 * 3");
+        let json = serde_json::to_string(&pool).unwrap();
+        let mut restored: Pool = serde_json::from_str(&json).unwrap();
+
+        assert_expr_display(&restored, expr, "(98765432109876543210 * 3)");
+        let again = parse_expression("98765432109876543210", &mut restored).unwrap();
+        let children = get_children_vec(&restored, expr);
+        assert_eq!(restored[children[1]], restored[again]);
+    }
+}
+
+#[cfg(test)]
+mod garbage_collection_tests {
+    use super::*;
+
+    fn add_swap_rule(pool: &mut Pool) -> RuleId {
+        let pattern = parse_test_pattern_into("?a + ?b", pool);
+        let action = parse_test_action_into("b + a", pool);
+        let rule_name = pool.intern_string("comm".to_string());
+        pool.add_rule(Rule {
+            name: rule_name,
+            pattern,
+            action,
+            guard: None,
+        })
+    }
+
+    fn apply_at_root(pool: &mut Pool, rule_id: RuleId, expr: ExprId) -> ExprId {
+        let pattern = pool[rule_id].pattern;
+        let mut captures = HashMap::new();
+        assert!(pool.pattern_matches(pattern, expr, &mut captures));
+        pool.apply_rule(&Match {
+            root: expr,
+            offset: expr,
+            rule_id,
+            captures,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_unreachable_roots_are_collected() {
+        let mut pool = new_test_pool();
+        let garbage = parse_expression("f(a, b) * c", &mut pool).unwrap();
+        let live = parse_expression("g(x + 1, y)", &mut pool).unwrap();
+
+        let remap = pool.collect_garbage(&[live]);
+
+        assert_eq!(remap.expr(garbage), None);
+        let live = remap.expr(live).unwrap();
+        assert_eq!(pool.exprs.len(), 5);
+        assert_eq!(pool.locations.len(), pool.exprs.len());
+        assert_eq!(pool.expr_hashes.len(), pool.exprs.len());
+        assert_eq!(pool.get_all_roots().collect::<Vec<_>>(), vec![live]);
+        assert_expr_display(&pool, live, "g((x + 1), y)");
+    }
+
+    #[test]
+    fn test_subexpression_root_keeps_whole_tree() {
+        let (mut pool, expr) = parse_test_expr("(a + b) * c");
+        let subexpr = get_children_vec(&pool, expr)[1];
+        assert_expr_display(&pool, subexpr, "(a + b)");
+
+        let remap = pool.collect_garbage(&[subexpr]);
+
+        let expr = remap.expr(expr).unwrap();
+        assert_expr_display(&pool, expr, "((a + b) * c)");
+        assert_expr_display(&pool, remap.expr(subexpr).unwrap(), "(a + b)");
+    }
+
+    #[test]
+    fn test_transformation_history_survives() {
+        let mut pool = new_test_pool();
+        let rule_id = add_swap_rule(&mut pool);
+        let _garbage = parse_expression("p + q", &mut pool).unwrap();
+        let start = parse_expression("x + y", &mut pool).unwrap();
+        let swapped = apply_at_root(&mut pool, rule_id, start);
+
+        let remap = pool.collect_garbage(&[]);
+
+        let start = remap.expr(start).unwrap();
+        let swapped = remap.expr(swapped).unwrap();
+        assert_expr_display(&pool, start, "(x + y)");
+        assert_expr_display(&pool, swapped, "(y + x)");
+        assert_eq!(
+            pool.get_outgoing_transformations(start),
+            Some(&vec![(swapped, rule_id)])
+        );
+        assert_eq!(
+            pool.get_incoming_transformations(swapped),
+            Some(&vec![(start, rule_id)])
+        );
+        let start_group = pool.get_equivalence_group(start).unwrap();
+        let swapped_group = pool.get_equivalence_group(swapped).unwrap();
+        assert_eq!(pool.equivalence_groups.len(), 2);
+        assert_eq!(
+            pool.equivalence_outgoing.get(&start_group),
+            Some(&vec![(swapped_group, rule_id)])
+        );
+
+        // The compacted rule still applies and reuses the live root.
+        let back = apply_at_root(&mut pool, rule_id, swapped);
+        assert_eq!(back, start);
+    }
+
+    #[test]
+    fn test_rules_survive_compaction() {
+        let mut pool = new_test_pool();
+        let _garbage = parse_expression("a * b * c", &mut pool).unwrap();
+        let rule_id = add_swap_rule(&mut pool);
+        let patterns_before = pool.patterns.len();
+
+        let remap = pool.collect_garbage(&[]);
+
+        assert!(pool.exprs.is_empty());
+        assert!(pool.expr_ends.is_empty());
+        assert_eq!(pool.patterns.len(), patterns_before);
+        assert_eq!(remap.pattern(pool[rule_id].pattern), Some(pool[rule_id].pattern));
+
+        let expr = parse_expression("m + n", &mut pool).unwrap();
+        let swapped = apply_at_root(&mut pool, rule_id, expr);
+        assert_expr_display(&pool, swapped, "(n + m)");
+    }
+
+    #[test]
+    fn test_guard_operands_survive_compaction() {
+        let mut pool = new_test_pool();
+        let _unused = parse_test_action_into("[1 + 2]", &mut pool);
+        let ruleset =
+            parse_ruleset("r {\n  big: #a * ?x => x when [a - 1] > 1\n}", &mut pool).unwrap();
+        let rule_id = pool.ruleset_rule_ids(ruleset)[0];
+
+        pool.collect_garbage(&[]);
+
+        let guard = pool[rule_id].guard.unwrap();
+        assert_eq!(pool.display_with_children(guard), "[a - 1] > 1");
+        let expr = parse_expression("3 * y", &mut pool).unwrap();
+        assert_eq!(pool.find_matches(expr).len(), 1);
+        let expr = parse_expression("2 * y", &mut pool).unwrap();
+        assert!(pool.find_matches(expr).is_empty());
+    }
+
+    #[test]
+    fn test_hash_cons_rebuilt_after_collection() {
+        let mut pool = new_test_pool();
+        let _garbage = parse_expression("u * v", &mut pool).unwrap();
+        let live = parse_expression("(x + y) * (x + y)", &mut pool).unwrap();
+
+        let remap = pool.collect_garbage(&[live]);
+        let live = remap.expr(live).unwrap();
+
+        let children = get_children_vec(&pool, live);
+        assert_eq!(pool.canonical(children[0]), pool.canonical(children[1]));
+        let again = parse_expression("(x + y) * (x + y)", &mut pool).unwrap();
+        assert_eq!(pool.find_interned_root(again), Some(live));
+    }
+}
+
+#[cfg(test)]
+mod ruleset_import_tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_resolution_survives_manual_cycles() {
+        let mut pool = Pool::new();
+        let rulesets = parse_rulesets(
+            "a {\n  ra: ?x => x\n}\nb extends a {\n  rb: ?y => y\n}",
+            &mut pool,
+        )
+        .unwrap();
+        let (a, b) = (rulesets[0], rulesets[1]);
+
+        // Close the cycle behind the parser's back
+        pool.rulesets[a.0].imports.push(b);
+
+        assert_eq!(pool.get_ruleset_rule_count(a), 2);
+        assert_eq!(pool.get_ruleset_rule_count(b), 2);
+        assert_eq!(pool.ruleset_import_path(a, b), Some(vec![a, b]));
+        assert_eq!(pool.ruleset_import_path(b, a), Some(vec![b, a]));
+    }
+
+    #[test]
+    fn test_import_path_and_name_lookup() {
+        let mut pool = Pool::new();
+        let rulesets = parse_rulesets(
+            "base {\n}\nmid extends base {\n}\ntop { use mid; }\nbase {\n}",
+            &mut pool,
+        )
+        .unwrap();
+
+        // Imports resolve to the latest ruleset of that name
+        assert_eq!(pool.find_ruleset_by_name("base"), Some(rulesets[3]));
+        assert_eq!(pool[rulesets[1]].imports, [rulesets[3]]);
+        assert_eq!(
+            pool.ruleset_import_path(rulesets[2], rulesets[3]),
+            Some(vec![rulesets[2], rulesets[1], rulesets[3]])
+        );
+        assert_eq!(pool.ruleset_import_path(rulesets[3], rulesets[2]), None);
+        assert_eq!(pool.find_ruleset_by_name("missing"), None);
+    }
+
+    #[test]
+    fn test_rulesets_without_imports_deserialize() {
+        let mut pool = Pool::new();
+        let ruleset = parse_ruleset("plain {\n  r: ?x => x\n}", &mut pool).unwrap();
+
+        let mut json: serde_json::Value = serde_json::to_value(&pool).unwrap();
+        json["rulesets"][0].as_object_mut().unwrap().remove("imports");
+        let restored: Pool = serde_json::from_value(json).unwrap();
+
+        assert!(restored[ruleset].imports.is_empty());
+        assert_eq!(restored.get_ruleset_rule_count(ruleset), 1);
+    }
+}
@@ -0,0 +1,411 @@
+mod common;
+
+use common::*;
+use expression_explorer::parser::actions::parse_action;
+use expression_explorer::parser::patterns::parse_pattern;
+use expression_explorer::parser::*;
+use expression_explorer::*;
+
+/// Pretty-prints `input` and checks both the text and that it parses back to
+/// the same expression.
+fn assert_expr_round_trip(pool: &mut Pool, input: &str, expected: &str) {
+    let expr = parse_expression(input, pool).unwrap();
+    let printed = pool.pretty(expr);
+    assert_eq!(printed, expected, "pretty form of {}", input);
+    let reparsed = parse_expression(&printed, pool).unwrap();
+    assert!(
+        pool.expr_eq(expr, reparsed),
+        "{} re-parsed differently",
+        printed
+    );
+}
+
+fn declare_test_operators(pool: &mut Pool) {
+    pool.declare_operator("<+>", Fixity::InfixLeft, 6);
+    pool.declare_operator("<>", Fixity::InfixRight, 6);
+    pool.declare_operator("==", Fixity::Infix, 6);
+    pool.declare_operator("~", Fixity::Prefix, 5);
+    pool.declare_operator("!", Fixity::Prefix, 9);
+}
+
+#[cfg(test)]
+mod expression_printing {
+    use super::*;
+
+    #[test]
+    fn test_minimal_parentheses_for_builtins() {
+        let mut pool = new_test_pool();
+        let cases = [
+            ("(a + b) + c", "a + b + c"),
+            ("a + (b + c)", "a + (b + c)"),
+            ("a - (b - c)", "a - (b - c)"),
+            ("(a * b) + (c / d)", "a * b + c / d"),
+            ("(a + b) * c", "(a + b) * c"),
+            ("a ^ (b ^ c)", "a ^ b ^ c"),
+            ("(a ^ b) ^ c", "(a ^ b) ^ c"),
+            ("-(x ^ 2)", "-(x ^ 2)"),
+            ("(-x) ^ 2", "-x ^ 2"),
+            ("x*-y", "x * -y"),
+            ("--x", "--x"),
+            ("-(a + b)", "-(a + b)"),
+        ];
+        for (input, expected) in cases {
+            assert_expr_round_trip(&mut pool, input, expected);
+        }
+    }
+
+    #[test]
+    fn test_calls_structs_and_literals() {
+        let mut pool = new_test_pool();
+        assert_expr_round_trip(&mut pool, "f((a + b), S{c, (d)})", "f(a + b, S{c, d})");
+        assert_expr_round_trip(&mut pool, "sin(x) * 3//4 + 0.5", "sin(x) * 3//4 + 0.5");
+    }
+
+    #[test]
+    fn test_declared_operators() {
+        let mut pool = new_test_pool();
+        declare_test_operators(&mut pool);
+        let cases = [
+            ("(a <+> b) <+> c", "a <+> b <+> c"),
+            ("a <> (b <> c)", "a <> b <> c"),
+            ("(a <> b) <> c", "(a <> b) <> c"),
+            ("(a == b) == c", "(a == b) == c"),
+            ("a + b == c", "a + b == c"),
+            ("(a <> b) + c", "(a <> b) + c"),
+            ("a <> (b + c)", "a <> b + c"),
+            ("(~a) + b", "(~a) + b"),
+            ("~(a + b)", "~a + b"),
+            ("a * (~b)", "a * ~b"),
+            ("(a * (~b)) + c", "a * (~b) + c"),
+            ("~(-a)", "~ -a"),
+            ("~(~a)", "~ ~a"),
+            ("!x ^ 2", "!x ^ 2"),
+            ("-(!x)", "-(!x)"),
+        ];
+        for (input, expected) in cases {
+            assert_expr_round_trip(&mut pool, input, expected);
+        }
+    }
+
+    #[test]
+    fn test_every_small_tree_round_trips() {
+        let mut pool = new_test_pool();
+        declare_test_operators(&mut pool);
+        let binary = ["+", "-", "*", "/", "^", "<+>", "<>", "=="];
+        let unary = ["-", "~", "!"];
+
+        // Fully parenthesized sources, so each parses to the intended tree.
+        let mut trees = vec!["a".to_string(), "b".to_string()];
+        for _ in 0..2 {
+            let mut next = trees.clone();
+            for op in unary {
+                next.extend(trees.iter().map(|t| format!("({}{})", op, t)));
+            }
+            for op in binary {
+                for left in &trees {
+                    next.extend(
+                        trees
+                            .iter()
+                            .map(|right| format!("({} {} {})", left, op, right)),
+                    );
+                }
+            }
+            trees = next;
+        }
+
+        for tree in &trees {
+            let expr = parse_expression(tree, &mut pool).unwrap();
+            let printed = pool.pretty(expr);
+            let reparsed = parse_expression(&printed, &mut pool)
+                .unwrap_or_else(|e| panic!("{} printed as {}: {}", tree, printed, e));
+            assert!(
+                pool.expr_eq(expr, reparsed),
+                "{} printed as {}",
+                tree,
+                printed
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_functions_print_their_names() {
+        let mut pool = new_test_pool();
+        let name = pool.intern_string("foo".to_string());
+        let foo = pool.intern_function(Function::Custom(name));
+        make_var(&mut pool, "x");
+        let call = pool.add_expr(ExprNode::Call {
+            fun: foo,
+            last: 1,
+            arity: 1,
+        });
+        assert_eq!(pool.pretty(call), "foo(x)");
+    }
+
+    #[test]
+    fn test_negative_literals_print_as_negation() {
+        let mut pool = new_test_pool();
+        make_var(&mut pool, "x");
+        let power = pool.intern_function(Function::Power);
+        make_num(&mut pool, -3);
+        let expr = pool.add_expr(ExprNode::Call {
+            fun: power,
+            last: 2,
+            arity: 2,
+        });
+        assert_eq!(pool.pretty(expr), "x ^ -3");
+    }
+
+    #[test]
+    fn test_non_finite_floats_print_as_divisions() {
+        let mut pool = new_test_pool();
+        let cases = [
+            (f64::INFINITY, "1.0 / 0.0"),
+            (f64::NEG_INFINITY, "-(1.0 / 0.0)"),
+            (f64::NAN, "0.0 / 0.0"),
+        ];
+        for (value, expected) in cases {
+            let float = pool.intern_float(value);
+            let expr = pool.add_expr(ExprNode::Float(float));
+            assert_eq!(pool.pretty(expr), expected);
+            assert_eq!(pool.display_with_children(expr), format!("({expected})"));
+
+            let reparsed = parse_expression(expected, &mut pool).unwrap();
+            assert!(!matches!(pool[reparsed], ExprNode::Variable(_)));
+        }
+
+        make_var(&mut pool, "x");
+        let infinity = pool.intern_float(f64::INFINITY);
+        pool.add_expr(ExprNode::Float(infinity));
+        let power = pool.intern_function(Function::Power);
+        let expr = pool.add_expr(ExprNode::Call {
+            fun: power,
+            last: 2,
+            arity: 2,
+        });
+        assert_eq!(pool.pretty(expr), "x ^ (1.0 / 0.0)");
+    }
+
+    #[test]
+    fn test_unspellable_literals_print_the_same_after_reparsing() {
+        let mut pool = new_test_pool();
+        let negate = pool.intern_function(Function::Negate);
+        let divide = pool.intern_function(Function::Divide);
+
+        let three = make_num(&mut pool, -3);
+        let mut literals = vec![(three, negate)];
+        for (value, fun) in [
+            (-0.0, negate),
+            (f64::INFINITY, divide),
+            (f64::NEG_INFINITY, negate),
+            (f64::NAN, divide),
+        ] {
+            let float = pool.intern_float(value);
+            literals.push((pool.add_expr(ExprNode::Float(float)), fun));
+        }
+
+        for (expr, fun) in literals {
+            let printed = pool.pretty(expr);
+            let reparsed = parse_expression(&printed, &mut pool).unwrap();
+            assert!(
+                matches!(pool[reparsed], ExprNode::Call { fun: f, .. } if f == fun),
+                "{} re-parsed as {}",
+                printed,
+                pool.display_with_children(reparsed)
+            );
+            assert_eq!(pool.pretty(reparsed), printed);
+        }
+    }
+
+    #[test]
+    fn test_deep_trees_break_over_lines() {
+        let mut pool = new_test_pool();
+        declare_test_operators(&mut pool);
+        let depth = 30;
+        let input = format!("{}~ -x{}", "f(".repeat(depth), ")".repeat(depth));
+        let expr = parse_expression(&input, &mut pool).unwrap();
+        assert_eq!(pool.pretty(expr), input);
+
+        let printed = pool.pretty_width(expr, 80);
+        assert!(printed.contains("~ -x"));
+        let reparsed = parse_expression(&printed, &mut pool).unwrap();
+        assert!(pool.expr_eq(expr, reparsed));
+    }
+
+    #[test]
+    fn test_wide_terms_break_over_lines() {
+        let mut pool = new_test_pool();
+        let input = "foo(alpha + beta * gamma, bar(delta, epsilon), zeta) + omega";
+        let expr = parse_expression(input, &mut pool).unwrap();
+        assert_eq!(pool.pretty_width(expr, 200), input);
+
+        let printed = pool.pretty_width(expr, 30);
+        assert_eq!(
+            printed,
+            "foo(\n    alpha + beta * gamma,\n    bar(delta, epsilon),\n    zeta\n)\n    + omega"
+        );
+        assert!(printed.lines().all(|line| line.len() <= 30));
+        let reparsed = parse_expression(&printed, &mut pool).unwrap();
+        assert!(pool.expr_eq(expr, reparsed));
+    }
+}
+
+#[cfg(test)]
+mod pattern_and_action_printing {
+    use super::*;
+
+    #[test]
+    fn test_patterns_round_trip() {
+        let mut pool = new_test_pool();
+        declare_test_operators(&mut pool);
+        let cases = [
+            ("(?x + 0) * #a", "(?x + 0) * #a"),
+            ("?f((#a + ?x), S{?y})", "?f(#a + ?x, S{?y})"),
+            ("?s{(?x <+> ?y)}", "?s{?x <+> ?y}"),
+            ("~(?x ^ 2)", "~?x ^ 2"),
+        ];
+        for (input, expected) in cases {
+            let pattern = parse_pattern(input, &mut pool).unwrap();
+            let printed = pool.pretty(pattern);
+            assert_eq!(printed, expected);
+            let reparsed = parse_pattern(&printed, &mut pool).unwrap();
+            assert_eq!(
+                pool.display_with_children(reparsed),
+                pool.display_with_children(pattern)
+            );
+        }
+    }
+
+    #[test]
+    fn test_actions_round_trip() {
+        let mut pool = new_test_pool();
+        let cases = [
+            ("[a + (b * c)] * x", "[a + b * c] * x"),
+            ("[(a + b) * c]", "[(a + b) * c]"),
+            ("[-(a ^ 2)] + fresh(n)", "[-(a ^ 2)] + fresh(n)"),
+            ("?f(x, [a - 1])", "?f(x, [a - 1])"),
+            ("g()", "g()"),
+            ("[(a % b) < c]", "[a % b < c]"),
+            ("[a % (b * c)]", "[a % (b * c)]"),
+            ("[if((a > 0), a, -a)]", "[if(a > 0, a, -a)]"),
+            ("[max(a, b, c) + div(a, 2)]", "[max(a, b, c) + div(a, 2)]"),
+        ];
+        for (input, expected) in cases {
+            let action = parse_action(input, &mut pool).unwrap();
+            let printed = pool.pretty(action);
+            assert_eq!(printed, expected);
+            let reparsed = parse_action(&printed, &mut pool).unwrap();
+            assert_eq!(
+                pool.display_with_children(reparsed),
+                pool.display_with_children(action)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod ruleset_source {
+    use super::*;
+
+    fn rule_lines(pool: &Pool, ruleset: RulesetId) -> Vec<String> {
+        pool.ruleset_rule_ids(ruleset)
+            .iter()
+            .map(|&id| pool.display_with_children(id))
+            .collect()
+    }
+
+    #[test]
+    fn test_ruleset_source_round_trips() {
+        let mut pool = new_test_pool();
+        let text = "infixl 6 <+>
+base {
+  zero: ?x <+> 0 => x
+}
+algebra {
+  use base;
+  combine: #a * ?x + #b * ?x => [a + b] * x
+  split: #a * ?x => [(a - 1) * 2] * x + x when a > 1 && !is_variable(x) || a == 0
+  commute: ?x * ?y <=> ?y * ?x when x != y
+  call: ?f(?x, S{?y}) => ?f(y, x)
+  pad: 0 => fresh(n) * 0
+  parity: f(#a) => [a % 2] when [a % 2 == 0] == 1 || [a % 2 == 1]
+}";
+        let rulesets = parse_rulesets(text, &mut pool).unwrap();
+        let algebra = rulesets[1];
+
+        let source = pool.ruleset_to_source(algebra);
+        assert_eq!(
+            source,
+            "infixl 6 <+>
+
+algebra {
+  use base;
+  combine: #a * ?x + #b * ?x => [a + b] * x
+  split: #a * ?x => [(a - 1) * 2] * x + x when a > 1 && !is_variable(x) || a == 0
+  commute: ?x * ?y <=> ?y * ?x when x != y
+  call: ?f(?x, S{?y}) => ?f(y, x)
+  pad: 0 => fresh(n) * 0
+  parity: f(#a) => [a % 2] when (a % 2 == 0) == 1 || a % 2 == 1
+}
+"
+        );
+
+        let mut reloaded = new_test_pool();
+        let all = parse_rulesets(&pool.rulesets_to_source(), &mut reloaded).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(rule_lines(&reloaded, all[1]), rule_lines(&pool, algebra));
+        assert_eq!(reloaded.ruleset_to_source(all[1]), source);
+        assert!(
+            reloaded
+                .rule_equation(reloaded.ruleset_rule_ids(all[1])[3])
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_guards_keep_their_grouping() {
+        let mut pool = new_test_pool();
+        let text = "g {
+  r: f(?a, ?b, ?c, ?d) => a when !(a > 1 && b < 2) && (c == 0 || d != 1)
+}";
+        let ruleset = parse_ruleset(text, &mut pool).unwrap();
+        let rule = pool.ruleset_rule_ids(ruleset)[0];
+        let source = pool.rule_to_source(rule);
+        assert_eq!(
+            source,
+            "r: f(?a, ?b, ?c, ?d) => a when !(a > 1 && b < 2) && (c == 0 || d != 1)"
+        );
+
+        let mut reloaded = new_test_pool();
+        let ruleset = parse_ruleset(&pool.ruleset_to_source(ruleset), &mut reloaded).unwrap();
+        assert_eq!(
+            rule_lines(&reloaded, ruleset),
+            rule_lines(&pool, RulesetId(0))
+        );
+    }
+
+    #[test]
+    fn test_programmatic_rules_are_exported() {
+        let mut pool = new_test_pool();
+        let pattern = parse_test_pattern_into("?x + 0", &mut pool);
+        let action = parse_test_action_into("x", &mut pool);
+        let name = pool.intern_string("identity".to_string());
+        pool.add_rule(Rule {
+            name,
+            pattern,
+            action,
+            guard: None,
+        });
+        let ruleset_name = pool.intern_string("built".to_string());
+        let ruleset = pool.add_ruleset(Ruleset {
+            name: ruleset_name,
+            rules_start: 0,
+            rules_end: 1,
+            imports: Vec::new(),
+        });
+
+        assert_eq!(
+            pool.ruleset_to_source(ruleset),
+            "built {\n  identity: ?x + 0 => x\n}\n"
+        );
+    }
+}
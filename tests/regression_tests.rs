@@ -40,6 +40,14 @@ mod parser_bug_fixes {
         assert_eq!(count_nodes(&pool, expr), pool.length(expr));
     }
 
+    #[test]
+    fn test_function_call_with_nested_first_argument() {
+        let (pool, expr) = parse_test_expr("g(a, f(a + b, c)) + 1");
+
+        assert_expr_display(&pool, expr, "(g(a, f((a + b), c)) + 1)");
+        assert_eq!(count_nodes(&pool, expr), pool.length(expr));
+    }
+
     #[test]
     fn test_children_iterator_underflow_fix() {
         let (pool, expr) = parse_test_expr("(a + b)");
@@ -151,6 +159,7 @@ mod rule_application_bug_fixes {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -166,7 +175,7 @@ mod rule_application_bug_fixes {
         };
 
         let result = pool.apply_rule(&match_obj);
-        assert!(result.is_some());
+        assert!(result.is_ok());
 
         let new_expr = result.unwrap();
         assert_expr_display(&pool, new_expr, "(2 * x)");
@@ -183,6 +192,7 @@ mod rule_application_bug_fixes {
             name: rule1_name,
             pattern: pattern1,
             action: action1,
+            guard: None,
         };
         let rule1_id = pool.add_rule(rule1);
 
@@ -193,6 +203,7 @@ mod rule_application_bug_fixes {
             name: rule2_name,
             pattern: pattern2,
             action: action2,
+            guard: None,
         };
         let _rule2_id = pool.add_rule(rule2);
 
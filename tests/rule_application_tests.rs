@@ -20,6 +20,7 @@ mod simple_rule_application {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -35,7 +36,7 @@ mod simple_rule_application {
         };
 
         let result = pool.apply_rule(&match_obj);
-        assert!(result.is_some());
+        assert!(result.is_ok());
 
         let new_expr = result.unwrap();
         assert_expr_display(&pool, new_expr, "(2 * x)");
@@ -52,6 +53,7 @@ mod simple_rule_application {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -67,7 +69,7 @@ mod simple_rule_application {
         };
 
         let result = pool.apply_rule(&match_obj);
-        assert!(result.is_some());
+        assert!(result.is_ok());
 
         let new_expr = result.unwrap();
         assert_expr_display(&pool, new_expr, "0");
@@ -84,6 +86,7 @@ mod simple_rule_application {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -99,7 +102,7 @@ mod simple_rule_application {
         };
 
         let result = pool.apply_rule(&match_obj);
-        assert!(result.is_some());
+        assert!(result.is_ok());
 
         let new_expr = result.unwrap();
         assert_expr_display(&pool, new_expr, "x");
@@ -116,6 +119,7 @@ mod simple_rule_application {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -131,13 +135,326 @@ mod simple_rule_application {
         };
 
         let result = pool.apply_rule(&match_obj);
-        assert!(result.is_some());
+        assert!(result.is_ok());
 
         let new_expr = result.unwrap();
         assert_expr_display(&pool, new_expr, "5");
     }
 }
 
+#[cfg(test)]
+mod big_number_computation {
+    use super::*;
+
+    #[test]
+    fn test_multiplication_does_not_overflow() {
+        let (pool, result) = apply_test_rule("123456789 * 987654321", "#a * #b", "[a * b]");
+        assert_expr_display(&pool, result.unwrap(), "121932631112635269");
+    }
+
+    #[test]
+    fn test_large_power() {
+        let (pool, result) = apply_test_rule("2 ^ 100", "#a ^ #b", "[a ^ b]");
+        assert_expr_display(&pool, result.unwrap(), "1267650600228229401496703205376");
+    }
+
+    #[test]
+    fn test_subtraction_below_zero() {
+        let (pool, result) = apply_test_rule("1 - 99999999999999999999", "#a - #b", "[a - b]");
+        assert_expr_display(&pool, result.unwrap(), "-99999999999999999998");
+    }
+}
+
+#[cfg(test)]
+mod rational_computation {
+    use super::*;
+
+    #[test]
+    fn test_division_is_exact() {
+        let (pool, result) = apply_test_rule("7 / 2", "#a / #b", "[a / b]");
+        assert_expr_display(&pool, result.unwrap(), "7//2");
+    }
+
+    #[test]
+    fn test_integral_quotient_stays_integer() {
+        let (pool, result) = apply_test_rule("6 / 3", "#a / #b", "[a / b]");
+        let result = result.unwrap();
+        assert!(matches!(pool[result], ExprNode::Number(_)));
+        assert_expr_display(&pool, result, "2");
+    }
+
+    #[test]
+    fn test_rational_arithmetic() {
+        let (pool, result) = apply_test_rule("1//2 + 1//3", "#a + #b", "[a + b]");
+        assert_expr_display(&pool, result.unwrap(), "5//6");
+    }
+
+    #[test]
+    fn test_power_of_rational() {
+        let (pool, result) = apply_test_rule("2//3 ^ 3", "#a ^ #b", "[a ^ b]");
+        assert_expr_display(&pool, result.unwrap(), "8//27");
+    }
+
+    #[test]
+    fn test_division_by_zero_is_rejected() {
+        let (_pool, result) = apply_test_rule("1 / 0", "#a / #b", "[a / b]");
+        assert_eq!(result, Err(RewriteError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_rational_literal_in_action() {
+        let (pool, result) = apply_test_rule("x", "?x", "1//2 * x");
+        assert_expr_display(&pool, result.unwrap(), "(1//2 * x)");
+    }
+}
+
+#[cfg(test)]
+mod float_computation {
+    use super::*;
+
+    #[test]
+    fn test_float_operand_makes_result_float() {
+        let (pool, result) = apply_test_rule("0.5 * 4", "#a * #b", "[a * b]");
+        let result = result.unwrap();
+        assert!(matches!(pool[result], ExprNode::Float(_)));
+        assert_expr_display(&pool, result, "2.0");
+    }
+
+    #[test]
+    fn test_float_literal_in_compute() {
+        let (pool, result) = apply_test_rule("3", "#a", "[a * 0.5]");
+        assert_expr_display(&pool, result.unwrap(), "1.5");
+    }
+
+    #[test]
+    fn test_float_division_by_zero_is_infinite() {
+        let (pool, result) = apply_test_rule("1 / 0.0", "#a / #b", "[a / b]");
+        assert_expr_display(&pool, result.unwrap(), "(1.0 / 0.0)");
+    }
+
+    #[test]
+    fn test_nan_results_are_equal() {
+        let (mut pool, first) = apply_test_rule("0.0 / 0.0", "#a / #b", "[a / b]");
+        let first = first.unwrap();
+        assert_expr_display(&pool, first, "(0.0 / 0.0)");
+
+        let nan = pool.intern_float(-f64::NAN);
+        let second = pool.add_expr(ExprNode::Float(nan));
+        pool.mark_expr_end(second);
+        assert!(pool.expr_eq(first, second));
+    }
+
+    #[test]
+    fn test_negative_zero_is_distinct() {
+        let (mut pool, zero) = parse_test_expr("0.0");
+        let negative = pool.intern_float(-0.0);
+        let negative_zero = pool.add_expr(ExprNode::Float(negative));
+        pool.mark_expr_end(negative_zero);
+
+        assert_expr_display(&pool, negative_zero, "-0.0");
+        assert!(!pool.expr_eq(zero, negative_zero));
+    }
+}
+
+#[cfg(test)]
+mod extended_computation {
+    use super::*;
+    use expression_explorer::parser::actions::parse_action;
+
+    fn compute(input: &str, pattern: &str, action: &str) -> String {
+        let (pool, result) = apply_test_rule(input, pattern, action);
+        pool.display_with_children(result.expect("compute block has a value"))
+    }
+
+    fn compute_error(input: &str, pattern: &str, action: &str) -> RewriteError {
+        apply_test_rule(input, pattern, action).1.unwrap_err()
+    }
+
+    #[test]
+    fn test_modulo_and_integer_division_round_down() {
+        assert_eq!(compute("f(7, 3)", "f(?a, ?b)", "[a % b]"), "1");
+        assert_eq!(compute("f(-7, 3)", "f(?a, ?b)", "[a % b]"), "2");
+        assert_eq!(compute("f(7, -3)", "f(?a, ?b)", "[a % b]"), "-2");
+        assert_eq!(compute("f(-7, 3)", "f(?a, ?b)", "[div(a, b)]"), "-3");
+        assert_eq!(
+            compute("f(7, 2)", "f(?a, ?b)", "[mod(a, b) + a * 2 % 5]"),
+            "5"
+        );
+    }
+
+    #[test]
+    fn test_integer_functions() {
+        assert_eq!(compute("f(-4, 6)", "f(?a, ?b)", "[abs(a)]"), "4");
+        assert_eq!(compute("f(-4, 6)", "f(?a, ?b)", "[min(a, b, 0)]"), "-4");
+        assert_eq!(compute("f(-4, 6)", "f(?a, ?b)", "[max(a, b, 0)]"), "6");
+        assert_eq!(compute("f(-4, 6)", "f(?a, ?b)", "[gcd(a, b)]"), "2");
+        assert_eq!(compute("f(-4, 6)", "f(?a, ?b)", "[lcm(a, b)]"), "12");
+        assert_eq!(compute("f(0, 6)", "f(?a, ?b)", "[lcm(a, b)]"), "0");
+    }
+
+    #[test]
+    fn test_comparisons_and_conditionals() {
+        assert_eq!(compute("f(2, 3)", "f(?a, ?b)", "[a < b]"), "1");
+        assert_eq!(compute("f(2, 3)", "f(?a, ?b)", "[a + 1 != b]"), "0");
+        assert_eq!(compute("f(-5, 3)", "f(?a, ?b)", "[if(a > 0, a, -a)]"), "5");
+        // The branch that is not taken is never evaluated.
+        assert_eq!(
+            compute("f(5, 0)", "f(?a, ?b)", "[if(b == 0, 0, a / b)]"),
+            "0"
+        );
+    }
+
+    #[test]
+    fn test_numeric_captures_are_evaluated() {
+        assert_eq!(compute("f(2 * 3)", "f(?x)", "[x + 1]"), "7");
+        assert_eq!(compute("f(-(2 ^ 3) / 4)", "f(?x)", "[abs(x)]"), "2");
+        let (_pool, result) = apply_test_rule("f(2 * y)", "f(?x)", "[x + 1]");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_failures_are_typed() {
+        assert_eq!(
+            compute_error("f(1, 0)", "f(?a, ?b)", "[a % b]"),
+            RewriteError::DivisionByZero
+        );
+        assert_eq!(
+            compute_error("f(0, -1)", "f(?a, ?b)", "[a ^ b]"),
+            RewriteError::NegativeExponent
+        );
+        assert!(matches!(
+            compute_error("f(1//2, 2)", "f(?a, ?b)", "[gcd(a, b)]"),
+            RewriteError::TypeMismatch(_)
+        ));
+        assert!(matches!(
+            compute_error("f(y)", "f(?x)", "[x * 2]"),
+            RewriteError::TypeMismatch(_)
+        ));
+        assert!(matches!(
+            compute_error("f(1)", "f(?a)", "[a + c]"),
+            RewriteError::UnboundVariable(_)
+        ));
+    }
+
+    #[test]
+    fn test_wrong_arity_is_a_parse_error() {
+        let mut pool = new_test_pool();
+        let error = parse_action("[gcd(a)]", &mut pool).unwrap_err();
+        assert!(error.to_string().contains("gcd takes 2 arguments, not 1"));
+        assert!(parse_action("[if(a, b)]", &mut pool).is_err());
+    }
+}
+
+#[cfg(test)]
+mod rewrite_errors {
+    use super::*;
+    use expression_explorer::parser::{parse_expression, parse_ruleset};
+
+    #[test]
+    fn test_failed_application_adds_nothing() {
+        let mut pool = new_test_pool();
+        parse_ruleset("r {\n  wrap: #a / #b => g([a / b], fresh(n))\n}", &mut pool).unwrap();
+        let expr = parse_expression("f(1 / 0)", &mut pool).unwrap();
+        let match_ = pool.find_matches(expr).pop().expect("division matches");
+        let (exprs, counter) = (pool.exprs.len(), pool.fresh_counter);
+
+        assert_eq!(pool.apply_rule(&match_), Err(RewriteError::DivisionByZero));
+        assert_eq!(pool.exprs.len(), exprs);
+        assert_eq!(pool.fresh_counter, counter);
+    }
+
+    #[test]
+    fn test_exact_power_errors() {
+        let (_pool, result) = apply_test_rule("0 ^ -1", "#a ^ -#b", "[a ^ -b]");
+        assert_eq!(result, Err(RewriteError::NegativeExponent));
+        let (_pool, result) = apply_test_rule("2 ^ 9999999999", "#a ^ #b", "[a ^ b]");
+        assert_eq!(result, Err(RewriteError::Overflow));
+    }
+
+    #[test]
+    fn test_powers_beyond_the_size_bound_are_rejected() {
+        let start = std::time::Instant::now();
+        let (_pool, result) = apply_test_rule("3 ^ 2000000000", "#a ^ #b", "[a ^ b]");
+        assert_eq!(result, Err(RewriteError::Overflow));
+        let (_pool, result) = apply_test_rule("2 ^ 600000", "#a ^ #b", "[a ^ b]");
+        assert_eq!(result, Err(RewriteError::Overflow));
+        let (_pool, result) = apply_test_rule("1//3 ^ 600000", "#a ^ #b", "[a ^ b]");
+        assert_eq!(result, Err(RewriteError::Overflow));
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+
+        let (pool, result) = apply_test_rule("1 ^ 2000000000", "#a ^ #b", "[a ^ b]");
+        assert_expr_display(&pool, result.unwrap(), "1");
+        let huge = "f(1, 10000000000)";
+        for (action, expected) in [
+            ("[a ^ b]", "1"),
+            ("[(a - 1) ^ b]", "0"),
+            ("[(-a) ^ b]", "1"),
+            ("[(-a) ^ (b + 1)]", "-1"),
+        ] {
+            let (pool, result) = apply_test_rule(huge, "f(#a, #b)", action);
+            assert_expr_display(&pool, result.unwrap(), expected);
+        }
+        let (_pool, result) = apply_test_rule(huge, "f(#a, #b)", "[(a - 1) ^ -b]");
+        assert_eq!(result, Err(RewriteError::NegativeExponent));
+        let (_pool, result) = apply_test_rule("2 ^ 500000", "#a ^ #b", "[a ^ b]");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_captured_names_must_have_the_right_kind() {
+        let (pool, result) = apply_test_rule("g(y)", "g(?f)", "?f(1)");
+        let error = result.unwrap_err();
+        assert!(matches!(error, RewriteError::TypeMismatch(_)));
+        assert_eq!(
+            pool.describe_rewrite_error(&error),
+            "f is not bound to a function name"
+        );
+    }
+
+    #[test]
+    fn test_unbound_variables_are_named() {
+        let (pool, result) = apply_test_rule("g(1)", "g(#a)", "[a + c]");
+        let error = result.unwrap_err();
+        assert!(matches!(error, RewriteError::UnboundVariable(_)));
+        assert_eq!(pool.describe_rewrite_error(&error), "c is not captured");
+    }
+
+    #[test]
+    fn test_applicable_matches_leave_out_failing_actions() {
+        let mut pool = new_test_pool();
+        parse_ruleset(
+            "r {\n  quotient: #a / #b => [a / b]\n  flip: ?x / ?y => y / x\n}",
+            &mut pool,
+        )
+        .unwrap();
+        let names = |pool: &Pool, matches: Vec<Match>| -> Vec<String> {
+            matches
+                .iter()
+                .map(|m| pool.display_name(pool[m.rule_id].name))
+                .collect()
+        };
+
+        let expr = parse_expression("1 / 0", &mut pool).unwrap();
+        assert_eq!(names(&pool, pool.find_matches(expr)), ["quotient", "flip"]);
+        assert_eq!(names(&pool, pool.find_applicable_matches(expr)), ["flip"]);
+
+        let expr = parse_expression("1 / 2", &mut pool).unwrap();
+        assert_eq!(
+            names(&pool, pool.find_applicable_matches(expr)),
+            ["quotient", "flip"]
+        );
+
+        let flip = RuleScope::Rules(vec![RuleId(1)]);
+        assert_eq!(
+            names(&pool, pool.find_applicable_matches_in(expr, &flip)),
+            ["flip"]
+        );
+        let other = parse_ruleset("other {\n  none: z => z\n}", &mut pool).unwrap();
+        let scope = RuleScope::Rulesets(vec![other]);
+        assert!(pool.find_applicable_matches_in(expr, &scope).is_empty());
+    }
+}
+
 #[cfg(test)]
 mod complex_rule_application {
     use super::*;
@@ -153,6 +470,7 @@ mod complex_rule_application {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -168,7 +486,7 @@ mod complex_rule_application {
         };
 
         let result = pool.apply_rule(&match_obj);
-        assert!(result.is_some());
+        assert!(result.is_ok());
 
         let new_expr = result.unwrap();
         assert_expr_display(&pool, new_expr, "((a * b) + (a * c))");
@@ -185,6 +503,7 @@ mod complex_rule_application {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -200,7 +519,7 @@ mod complex_rule_application {
         };
 
         let result = pool.apply_rule(&match_obj);
-        assert!(result.is_some());
+        assert!(result.is_ok());
 
         let new_expr = result.unwrap();
         assert_expr_display(&pool, new_expr, "(b + a)");
@@ -217,6 +536,7 @@ mod complex_rule_application {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -228,17 +548,17 @@ mod complex_rule_application {
         assert!(matches);
 
         let match_obj = Match {
-            root: subexpr,
-            offset: expr,
+            root: expr,
+            offset: subexpr,
             rule_id,
             captures,
         };
 
         let result = pool.apply_rule(&match_obj);
-        assert!(result.is_some());
+        assert!(result.is_ok());
 
-        let new_subexpr = result.unwrap();
-        assert_expr_display(&pool, new_subexpr, "(2 * x)");
+        let new_expr = result.unwrap();
+        assert_expr_display(&pool, new_expr, "((2 * x) + y)");
     }
 }
 
@@ -257,6 +577,7 @@ mod rule_matching_tests {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -280,6 +601,7 @@ mod rule_matching_tests {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let _rule_id = pool.add_rule(rule);
 
@@ -298,6 +620,7 @@ mod rule_matching_tests {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let _rule_id = pool.add_rule(rule);
 
@@ -316,6 +639,7 @@ mod rule_matching_tests {
             name: rule1_name,
             pattern: pattern1,
             action: action1,
+            guard: None,
         };
         let rule_id1 = pool.add_rule(rule1);
 
@@ -326,6 +650,7 @@ mod rule_matching_tests {
             name: rule2_name,
             pattern: pattern2,
             action: action2,
+            guard: None,
         };
         let _rule_id2 = pool.add_rule(rule2);
 
@@ -350,6 +675,7 @@ mod error_handling_tests {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -365,7 +691,7 @@ mod error_handling_tests {
         };
 
         let result = pool.apply_rule(&match_obj);
-        assert!(result.is_some());
+        assert!(result.is_ok());
     }
 
     #[test]
@@ -379,6 +705,7 @@ mod error_handling_tests {
             name: rule_name,
             pattern,
             action,
+            guard: None,
         };
         let rule_id = pool.add_rule(rule);
 
@@ -394,9 +721,383 @@ mod error_handling_tests {
         };
 
         let result = pool.apply_rule(&match_obj);
-        assert!(result.is_some());
+        assert!(result.is_ok());
 
         let new_expr = result.unwrap();
         assert_expr_display(&pool, new_expr, "(x + y)");
     }
 }
+
+#[cfg(test)]
+mod guarded_rules {
+    use super::*;
+    use expression_explorer::parser::{parse_expression, parse_ruleset};
+
+    fn matching_rules(ruleset: &str, input: &str) -> Vec<String> {
+        let mut pool = new_test_pool();
+        parse_ruleset(ruleset, &mut pool).unwrap();
+        let expr = parse_expression(input, &mut pool).unwrap();
+        let mut names: Vec<_> = pool
+            .find_matches(expr)
+            .into_iter()
+            .filter(|m| m.offset == expr)
+            .map(|m| pool.display_name(pool[m.rule_id].name))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_guard_filters_matches() {
+        let rules = "r {\n  div_self: ?x / ?x => 1 when x != 0\n}";
+        assert_eq!(matching_rules(rules, "y / y"), ["div_self"]);
+        assert_eq!(matching_rules(rules, "3 / 3"), ["div_self"]);
+        assert!(matching_rules(rules, "0 / 0").is_empty());
+    }
+
+    #[test]
+    fn test_bracketed_numeric_guard() {
+        let rules = "r {\n  split: #a * ?x => [a - 1] * x + x when [a > 1]\n}";
+        assert_eq!(matching_rules(rules, "3 * y"), ["split"]);
+        assert!(matching_rules(rules, "1 * y").is_empty());
+        assert!(matching_rules(rules, "1//2 * y").is_empty());
+    }
+
+    #[test]
+    fn test_guard_with_computed_operands() {
+        let rules = "r {\n  even: #a + #b => 0 when [a + b] == 2 * b && a >= 0\n}";
+        assert_eq!(matching_rules(rules, "4 + 4"), ["even"]);
+        assert!(matching_rules(rules, "4 + 5").is_empty());
+        assert!(matching_rules(rules, "-4 + -4").is_empty());
+    }
+
+    #[test]
+    fn test_structural_predicates() {
+        let rules = r#"r {
+  num: f(?x) => x when is_number(x)
+  var: f(?x) => x when is_variable(x)
+  neither: f(?x) => x when !(is_number(x) || is_variable(x))
+}"#;
+        assert_eq!(matching_rules(rules, "f(2.5)"), ["num"]);
+        assert_eq!(matching_rules(rules, "f(y)"), ["var"]);
+        assert_eq!(matching_rules(rules, "f(y + 1)"), ["neither"]);
+    }
+
+    #[test]
+    fn test_expression_equality() {
+        let rules =
+            "r {\n  same: g(?x, ?y) => x when x == y\n  differ: g(?x, ?y) => y when x != y\n}";
+        assert_eq!(matching_rules(rules, "g(a + 1, a + 1)"), ["same"]);
+        assert_eq!(matching_rules(rules, "g(a + 1, 1 + a)"), ["differ"]);
+        assert_eq!(matching_rules(rules, "g(2, 4//2)"), ["same"]);
+    }
+
+    #[test]
+    fn test_guard_with_compute_functions() {
+        let rules = "r {\n  even: f(?a) => 0 when [a % 2] == 0 && [if(a > 0, 1, 0)] == 1\n}";
+        assert_eq!(matching_rules(rules, "f(4)"), ["even"]);
+        assert!(matching_rules(rules, "f(3)").is_empty());
+        assert!(matching_rules(rules, "f(-4)").is_empty());
+    }
+
+    #[test]
+    fn test_unevaluable_guard_does_not_hold() {
+        let rules = "r {\n  bad: #a * ?x => x when [a / 0] > 1\n}";
+        assert!(matching_rules(rules, "2 * y").is_empty());
+    }
+}
+
+#[cfg(test)]
+mod fresh_variables {
+    use super::*;
+    use expression_explorer::parser::{parse_expression, parse_ruleset};
+
+    fn apply_first_rule(pool: &mut Pool, ruleset: RulesetId, input: &str) -> ExprId {
+        let rule_id = pool.ruleset_rule_ids(ruleset)[0];
+        let expr = parse_expression(input, pool).unwrap();
+        let match_ = pool
+            .find_matches(expr)
+            .into_iter()
+            .find(|m| m.rule_id == rule_id && m.offset == expr)
+            .expect("rule matches the root");
+        pool.apply_rule(&match_).unwrap()
+    }
+
+    #[test]
+    fn test_each_application_gets_a_new_variable() {
+        let mut pool = new_test_pool();
+        let ruleset = parse_ruleset("r {\n  expand: 0 => 0 * fresh(n)\n}", &mut pool).unwrap();
+
+        let first = apply_first_rule(&mut pool, ruleset, "0");
+        let second = apply_first_rule(&mut pool, ruleset, "0");
+
+        assert_expr_display(&pool, first, "(0 * n_1)");
+        assert_expr_display(&pool, second, "(0 * n_2)");
+        assert!(pool.name_map.contains_key("n_2"));
+    }
+
+    #[test]
+    fn test_same_base_is_one_variable_per_application() {
+        let mut pool = new_test_pool();
+        let ruleset = parse_ruleset(
+            "r {\n  inverse: 0 => fresh(x) + -fresh(x) + fresh(y)\n}",
+            &mut pool,
+        )
+        .unwrap();
+
+        let result = apply_first_rule(&mut pool, ruleset, "0");
+        assert_expr_display(&pool, result, "((x_1 + (-x_1)) + y_1)");
+    }
+
+    #[test]
+    fn test_existing_names_are_skipped() {
+        let mut pool = new_test_pool();
+        let ruleset = parse_ruleset("r {\n  expand: ?a => a + fresh(n)\n}", &mut pool).unwrap();
+
+        let result = apply_first_rule(&mut pool, ruleset, "n_1 * n_2");
+        assert_expr_display(&pool, result, "((n_1 * n_2) + n_3)");
+    }
+
+    #[test]
+    fn test_fresh_is_displayed_in_actions() {
+        let (pool, action) = parse_test_action("fresh(n) * 0");
+        assert_eq!(pool.display_with_children(action), "(fresh(n) * 0)");
+    }
+}
+
+#[cfg(test)]
+mod sequence_wildcards {
+    use super::*;
+    use expression_explorer::parser::{parse_expression, parse_ruleset};
+
+    #[test]
+    fn test_sequence_takes_any_number_of_arguments() {
+        for (input, expected) in [("sum(a)", "total(a)"), ("sum(a, b, c)", "total(a, b, c)")] {
+            let (pool, result) = apply_test_rule(input, "sum(?xs...)", "total(xs...)");
+            assert_expr_display(&pool, result.unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_sequence_can_be_empty() {
+        let (pool, result) = apply_test_rule("f(a)", "f(?x, ?rest...)", "g(rest..., x)");
+        assert_expr_display(&pool, result.unwrap(), "g(a)");
+
+        let (pool, result) = apply_test_rule("f(a)", "f(?x, ?rest...)", "g(rest...)");
+        assert_expr_display(&pool, result.unwrap(), "g()");
+    }
+
+    #[test]
+    fn test_struct_fields_are_spliced() {
+        let (pool, result) = apply_test_rule(
+            "Point{1, 2, 3}",
+            "Point{?x, ?rest...}",
+            "Pair{x, Rest{rest...}}",
+        );
+        assert_expr_display(&pool, result.unwrap(), "Pair{ 1, Rest{ 2, 3 } }");
+
+        let (pool, result) = apply_test_rule("Point{1, 2}", "?S{?fields...}", "?S{fields..., 0}");
+        assert_expr_display(&pool, result.unwrap(), "Point{ 1, 2, 0 }");
+    }
+
+    #[test]
+    fn test_every_split_is_a_match() {
+        let mut pool = new_test_pool();
+        parse_ruleset(
+            "r {\n  split: f(?xs..., ?ys...) => g(h(xs...), h(ys...))\n}",
+            &mut pool,
+        )
+        .unwrap();
+        let expr = parse_expression("f(a, b)", &mut pool).unwrap();
+
+        let mut results: Vec<_> = pool
+            .find_matches(expr)
+            .iter()
+            .map(|found| {
+                let result = pool.apply_rule(found).unwrap();
+                pool.display_with_children(result)
+            })
+            .collect();
+        results.sort();
+        assert_eq!(
+            results,
+            vec!["g(h(), h(a, b))", "g(h(a), h(b))", "g(h(a, b), h())"]
+        );
+    }
+
+    #[test]
+    fn test_repeated_sequence_must_take_the_same_arguments() {
+        let (mut pool, expr) = parse_test_expr("f(a, b, a, b)");
+        let pattern = parse_test_pattern_into("f(?xs..., ?xs...)", &mut pool);
+        let found = pool.pattern_matches_all(pattern, expr, &HashMap::new());
+        assert_eq!(found.len(), 1);
+        let name = pool.intern_string("xs".to_string());
+        assert_eq!(pool.display_captured_value(&found[0][&name]), "a, b");
+
+        let (mut pool, expr) = parse_test_expr("f(a, b, b)");
+        let pattern = parse_test_pattern_into("f(?xs..., ?xs...)", &mut pool);
+        assert!(!pool.pattern_matches(pattern, expr, &mut HashMap::new()));
+    }
+
+    #[test]
+    fn test_fixed_arguments_still_need_their_arity() {
+        let (mut pool, expr) = parse_test_expr("f(a)");
+        let pattern = parse_test_pattern_into("f(?x, ?y, ?rest...)", &mut pool);
+        assert!(!pool.pattern_matches(pattern, expr, &mut HashMap::new()));
+    }
+
+    #[test]
+    fn test_sequence_used_without_splice_is_an_error() {
+        let (pool, result) = apply_test_rule("sum(a, b)", "sum(?xs...)", "g(xs)");
+        let error = result.unwrap_err();
+        assert_eq!(
+            pool.describe_rewrite_error(&error),
+            "xs is a sequence, splice it with xs..."
+        );
+    }
+
+    #[test]
+    fn test_sequence_rules_round_trip() {
+        let mut pool = new_test_pool();
+        let text = "r {
+  rest: f(?x, ?rest...) => g(rest..., x)
+  swap: Point{?x, ?ys...} <=> Point{?ys..., ?x}
+}
+";
+        let ruleset = parse_ruleset(text, &mut pool).unwrap();
+        assert_eq!(pool.ruleset_to_source(ruleset), text);
+    }
+}
+
+#[cfg(test)]
+mod context_patterns {
+    use super::*;
+    use expression_explorer::parser::{parse_expression, parse_ruleset};
+
+    /// Every result of applying the ruleset's matches to `input`, sorted.
+    fn rewrites(rules: &str, input: &str) -> Vec<String> {
+        let mut pool = new_test_pool();
+        parse_ruleset(rules, &mut pool).unwrap();
+        let expr = parse_expression(input, &mut pool).unwrap();
+        let mut results: Vec<_> = pool
+            .find_matches(expr)
+            .iter()
+            .map(|found| {
+                let result = pool.apply_rule(found).unwrap();
+                pool.display_with_children(result)
+            })
+            .collect();
+        results.sort();
+        results
+    }
+
+    #[test]
+    fn test_hole_is_filled_at_any_depth() {
+        let (pool, result) = apply_test_rule("f(a - a, b) + 1", "C[?x - ?x]", "C[0]");
+        assert_expr_display(&pool, result.unwrap(), "(f(0, b) + 1)");
+
+        let (pool, result) = apply_test_rule("a - a", "C[?x - ?x]", "C[0]");
+        assert_expr_display(&pool, result.unwrap(), "0");
+
+        let (pool, result) = apply_test_rule("P{ 1, g(y) }", "C[g(?x)]", "C[h(x, x)]");
+        assert_expr_display(&pool, result.unwrap(), "P{ 1, h(y, y) }");
+    }
+
+    #[test]
+    fn test_each_hole_is_a_separate_match() {
+        assert_eq!(
+            rewrites(
+                "s {\n  cancel: C[?x - ?x] => C[0]\n}",
+                "g(a - a, h(b - b)) * 2"
+            ),
+            ["(g((a - a), h(0)) * 2)", "(g(0, h((b - b))) * 2)"]
+        );
+    }
+
+    #[test]
+    fn test_context_records_the_path_to_the_hole() {
+        let (mut pool, expr) = parse_test_expr("f(a, g(b, c - c))");
+        let pattern = parse_test_pattern_into("C[?x - ?x]", &mut pool);
+        let mut captures = HashMap::new();
+        assert!(pool.pattern_matches(pattern, expr, &mut captures));
+
+        let context = pool.intern_string("C".to_string());
+        let Some(CapturedValue::Context { root, path }) = captures.get(&context) else {
+            panic!("Expected a captured context");
+        };
+        assert_eq!((*root, path.as_slice()), (expr, &[1, 1][..]));
+        assert_expr_display(&pool, pool.context_hole(*root, path), "(c - c)");
+        assert_eq!(
+            pool.display_captured_value(&captures[&context]),
+            "f(a, g(b, (c - c))), hole at (c - c)"
+        );
+    }
+
+    #[test]
+    fn test_substitution_through_a_whole_term() {
+        let mut pool = new_test_pool();
+        parse_ruleset(
+            "s {\n  subst: Let{ ?x:var, ?v, C[?x] } => Let{ x, v, C[v] }\n}",
+            &mut pool,
+        )
+        .unwrap();
+        let mut expr = parse_expression("Let{ y, 2, y * f(y) + z }", &mut pool).unwrap();
+        // One match per occurrence left to substitute.
+        for occurrences in [2, 1] {
+            let found = pool.find_matches(expr);
+            assert_eq!(found.len(), occurrences);
+            expr = pool.apply_rule(&found[0]).unwrap();
+        }
+        assert_expr_display(&pool, expr, "Let{ y, 2, ((2 * f(2)) + z) }");
+        assert!(pool.find_matches(expr).is_empty());
+    }
+
+    #[test]
+    fn test_repeated_context_must_agree_outside_the_hole() {
+        let same = "s {\n  r: f(C[1], C[2]) => C[3]\n}";
+        assert_eq!(rewrites(same, "f(g(a, 1), g(a, 2))"), ["g(a, 3)"]);
+        assert!(rewrites(same, "f(g(a, 1), g(b, 2))").is_empty());
+        assert!(rewrites(same, "f(g(1, a), g(a, 2))").is_empty());
+    }
+
+    #[test]
+    fn test_context_rules_are_only_tried_at_the_root() {
+        let mut pool = new_test_pool();
+        parse_ruleset("s {\n  cancel: C[?x - ?x] => C[0]\n}", &mut pool).unwrap();
+        let expr = parse_expression("f(g(a - a))", &mut pool).unwrap();
+        let found = pool.find_matches(expr);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].offset, expr);
+    }
+
+    #[test]
+    fn test_context_is_not_a_term() {
+        let (_, result) = apply_test_rule("f(a - a)", "C[?x - ?x]", "g(C)");
+        assert!(matches!(result, Err(RewriteError::TypeMismatch(_))));
+        let (pool, result) = apply_test_rule("f(a - a)", "C[?x - ?x]", "D[0]");
+        let context = pool.name_map["D"];
+        assert_eq!(result, Err(RewriteError::UnboundVariable(context)));
+    }
+
+    #[test]
+    fn test_context_rules_print_and_reverse() {
+        let mut pool = new_test_pool();
+        let ruleset = parse_ruleset(
+            "s {\n  cancel: C[?x - ?x] => C[0]\n  unit: C[?x * 1] <=> C[?x]\n}",
+            &mut pool,
+        )
+        .unwrap();
+        let source = pool.ruleset_to_source(ruleset);
+        assert!(source.contains("cancel: C[?x - ?x] => C[0]"));
+        assert!(source.contains("unit: C[?x * 1] <=> C[?x]"));
+
+        let expr = parse_expression("f(a) * 1", &mut pool).unwrap();
+        let unit = pool
+            .find_matches(expr)
+            .into_iter()
+            .find(|found| pool.display_name(pool[found.rule_id].name) == "unit")
+            .unwrap();
+        let result = pool.apply_rule(&unit).unwrap();
+        assert_expr_display(&pool, result, "f(a)");
+    }
+}
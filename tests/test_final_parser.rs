@@ -69,7 +69,7 @@ fn test_final_parser_verification() {
             }
         }
 
-        if let Some(result) = pool.apply_rule(match_) {
+        if let Ok(result) = pool.apply_rule(match_) {
             println!("  Result: {}", pool.display_with_children(result));
         }
     }